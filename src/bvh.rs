@@ -0,0 +1,436 @@
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+use bytemuck::{Pod, Zeroable};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Must match BVH_STACK_SIZE in compute.glsl, traversal never holds more than one node per level
+// plus the children of the deepest one
+pub(crate) const STACK_SIZE: usize = 32;
+const MAX_DEPTH: u32 = STACK_SIZE as u32 - 2;
+
+const BIN_COUNT: usize = 16;
+/// Nodes with more primitives are split even if the SAH says it isn't worth it
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of testing a node's box relative to intersecting a primitive
+const TRAVERSAL_COST: f32 = 1.0;
+
+/// Axis-aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+/// Node as laid out in `compute.glsl` (std430).
+///
+/// Interior nodes have a `count` of 0 and their children at `first` and `first + 1`, leaves
+/// cover the primitives `first..first + count` of `Bvh::reorder`ed primitives.
+#[derive(Copy, Clone, Zeroable, Pod, Default)]
+#[repr(C)]
+pub(crate) struct BvhNode {
+    min: Vec3,
+    first: u32,
+    max: Vec3,
+    count: u32,
+}
+
+/// Bounding volume hierarchy over the primitives of a scene, built with the binned surface area
+/// heuristic.
+///
+/// A `Bvh` can hold several trees sharing the same nodes and order, see `append`.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    order: Vec<u32>,
+    roots: Vec<u32>,
+    stats: BvhStats,
+}
+
+#[derive(Clone, Debug)]
+pub struct BvhStats {
+    pub primitive_count: usize,
+    pub node_count: usize,
+    pub leaf_count: usize,
+    /// Depth of the deepest leaf, the root is at depth 0
+    pub max_depth: u32,
+    pub build_time: Duration,
+}
+
+struct BuildPrimitive {
+    index: u32,
+    bounds: Aabb,
+    centroid: Vec3,
+}
+
+struct Builder {
+    nodes: Vec<BvhNode>,
+    primitives: Vec<BuildPrimitive>,
+    leaf_count: usize,
+    max_depth: u32,
+}
+
+impl Aabb {
+    /// Contains nothing, the identity of `union`
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3 {
+            x: f32::INFINITY,
+            y: f32::INFINITY,
+            z: f32::INFINITY,
+        },
+        max: Vec3 {
+            x: f32::NEG_INFINITY,
+            y: f32::NEG_INFINITY,
+            z: f32::NEG_INFINITY,
+        },
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(&other.min),
+            max: self.max.max(&other.max),
+        }
+    }
+
+    pub fn grow(&self, point: &Vec3) -> Aabb {
+        Aabb {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Bounds of the transformed box, which can be larger than the box itself once rotated
+    pub fn transform(&self, transform: &Transform) -> Aabb {
+        if self.is_empty() {
+            return Aabb::EMPTY;
+        }
+
+        (0..8).fold(Aabb::EMPTY, |bounds, corner| {
+            let pick = |axis: usize| {
+                if corner & (1 << axis) == 0 {
+                    self.min[axis]
+                } else {
+                    self.max[axis]
+                }
+            };
+            bounds.grow(&transform.point(Vec3::new(pick(0), pick(1), pick(2))))
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Slab test, `inv_dir` is `1 / ray.dir` per component. Mirrors `hit_aabb` in `compute.glsl`.
+    pub fn hit(&self, ray: &Ray, inv_dir: &Vec3, t_min: f32, t_max: f32) -> bool {
+        let t0 = (self.min - ray.origin) * *inv_dir;
+        let t1 = (self.max - ray.origin) * *inv_dir;
+        let t_near = t0.min(&t1);
+        let t_far = t0.max(&t1);
+
+        let t_enter = f32::max(f32::max(t_near.x, t_near.y), f32::max(t_near.z, t_min));
+        let t_exit = f32::min(f32::min(t_far.x, t_far.y), f32::min(t_far.z, t_max));
+        t_enter <= t_exit
+    }
+}
+
+impl Bvh {
+    /// Builds the hierarchy over primitives with the given bounds
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let indices: Vec<u32> = (0..bounds.len() as u32).collect();
+        Bvh::build_subset(&indices, bounds)
+    }
+
+    /// Builds the hierarchy over the primitives `indices` of `bounds` only, `order` holds indices
+    /// into `bounds`
+    pub fn build_subset(indices: &[u32], bounds: &[Aabb]) -> Bvh {
+        let start = Instant::now();
+
+        let mut builder = Builder {
+            nodes: Vec::with_capacity(indices.len() * 2),
+            primitives: indices
+                .iter()
+                .map(|&index| BuildPrimitive {
+                    index,
+                    bounds: bounds[index as usize],
+                    centroid: bounds[index as usize].centroid(),
+                })
+                .collect(),
+            leaf_count: 0,
+            max_depth: 0,
+        };
+
+        if !indices.is_empty() {
+            builder.nodes.push(BvhNode::default());
+            builder.build_node(0, 0, indices.len(), 0);
+        }
+
+        let stats = BvhStats {
+            primitive_count: indices.len(),
+            node_count: builder.nodes.len(),
+            leaf_count: builder.leaf_count,
+            max_depth: builder.max_depth,
+            build_time: start.elapsed(),
+        };
+
+        Bvh {
+            nodes: builder.nodes,
+            order: builder.primitives.iter().map(|p| p.index).collect(),
+            roots: vec![0],
+            stats,
+        }
+    }
+
+    /// Moves the trees of `other` after those of `self` and returns the node the first one
+    /// starts at
+    pub fn append(&mut self, other: Bvh) -> u32 {
+        let node_offset = self.nodes.len() as u32;
+        let order_offset = self.order.len() as u32;
+
+        self.nodes
+            .extend(other.nodes.into_iter().map(|node| BvhNode {
+                first: node.first
+                    + if node.count > 0 {
+                        order_offset
+                    } else {
+                        node_offset
+                    },
+                ..node
+            }));
+        self.order.extend(other.order);
+        self.roots
+            .extend(other.roots.iter().map(|root| root + node_offset));
+
+        let stats = &mut self.stats;
+        stats.primitive_count += other.stats.primitive_count;
+        stats.node_count += other.stats.node_count;
+        stats.leaf_count += other.stats.leaf_count;
+        stats.max_depth = stats.max_depth.max(other.stats.max_depth);
+        stats.build_time += other.stats.build_time;
+
+        node_offset
+    }
+
+    pub(crate) fn nodes(&self) -> &[BvhNode] {
+        &self.nodes
+    }
+
+    /// Indices of the primitives passed to `build`, in the order the leaves reference them
+    pub fn order(&self) -> &[u32] {
+        &self.order
+    }
+
+    /// Sorts `primitives` (as passed to `build`) in the order the leaves reference them
+    pub fn reorder<T: Copy>(&self, primitives: &[T]) -> Vec<T> {
+        self.order
+            .iter()
+            .map(|&index| primitives[index as usize])
+            .collect()
+    }
+
+    /// Node each tree starts at, the one `build` made first and then the `append`ed ones
+    pub fn roots(&self) -> &[u32] {
+        &self.roots
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    /// Walks the nodes hit by `ray` closer than `t_max`, calling `hit` with the index of each
+    /// primitive (in `reorder`ed order) and the closest hit so far. `hit` returns the distance of
+    /// a closer hit, if any. Mirrors `hit_scene` in `compute.glsl`.
+    pub fn traverse<F>(&self, ray: &Ray, t_min: f32, t_max: f32, hit: F)
+    where
+        F: FnMut(usize, f32) -> Option<f32>,
+    {
+        self.traverse_from(0, ray, t_min, t_max, hit);
+    }
+
+    /// `traverse` for the tree starting at the node `root`
+    pub fn traverse_from<F>(&self, root: u32, ray: &Ray, t_min: f32, mut t_max: f32, mut hit: F)
+    where
+        F: FnMut(usize, f32) -> Option<f32>,
+    {
+        if root as usize >= self.nodes.len() {
+            return;
+        }
+
+        let inv_dir = Vec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+
+        let mut stack = [root; STACK_SIZE];
+        let mut stack_size = 1;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node = &self.nodes[stack[stack_size] as usize];
+
+            let bounds = Aabb::new(node.min, node.max);
+            if !bounds.hit(ray, &inv_dir, t_min, t_max) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for i in node.first..node.first + node.count {
+                    if let Some(t) = hit(i as usize, t_max) {
+                        t_max = t;
+                    }
+                }
+            } else {
+                stack[stack_size] = node.first + 1;
+                stack[stack_size + 1] = node.first;
+                stack_size += 2;
+            }
+        }
+    }
+}
+
+impl Builder {
+    fn build_node(&mut self, node: usize, start: usize, end: usize, depth: u32) {
+        let bounds = self.primitives[start..end]
+            .iter()
+            .fold(Aabb::EMPTY, |bounds, p| bounds.union(&p.bounds));
+
+        let split = if depth < MAX_DEPTH && end - start > 1 {
+            self.split(start, end, &bounds)
+        } else {
+            None
+        };
+
+        match split {
+            Some(mid) => {
+                let left = self.nodes.len();
+                self.nodes.push(BvhNode::default());
+                self.nodes.push(BvhNode::default());
+
+                self.nodes[node] = BvhNode {
+                    min: bounds.min,
+                    max: bounds.max,
+                    first: left as u32,
+                    count: 0,
+                };
+
+                self.build_node(left, start, mid, depth + 1);
+                self.build_node(left + 1, mid, end, depth + 1);
+            }
+            None => {
+                self.nodes[node] = BvhNode {
+                    min: bounds.min,
+                    max: bounds.max,
+                    first: start as u32,
+                    count: (end - start) as u32,
+                };
+
+                self.leaf_count += 1;
+                self.max_depth = self.max_depth.max(depth);
+            }
+        }
+    }
+
+    /// Partitions `start..end` along the cheapest of the binned split planes and returns where
+    /// the right child starts, `None` when a leaf is cheaper
+    fn split(&mut self, start: usize, end: usize, bounds: &Aabb) -> Option<usize> {
+        let count = end - start;
+
+        let centroid_bounds = self.primitives[start..end]
+            .iter()
+            .fold(Aabb::EMPTY, |bounds, p| bounds.grow(&p.centroid));
+
+        let extent = centroid_bounds.max - centroid_bounds.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+
+        // All the centroids are at the same spot, any split is as good as another
+        if extent[axis] <= 0.0 {
+            return (count > MAX_LEAF_SIZE).then_some(start + count / 2);
+        }
+
+        let origin = centroid_bounds.min[axis];
+        let scale = BIN_COUNT as f32 / extent[axis];
+        let bin_of = |p: &BuildPrimitive| {
+            (((p.centroid[axis] - origin) * scale) as usize).min(BIN_COUNT - 1)
+        };
+
+        let mut bins = [(Aabb::EMPTY, 0usize); BIN_COUNT];
+        for p in self.primitives[start..end].iter() {
+            let bin = &mut bins[bin_of(p)];
+            bin.0 = bin.0.union(&p.bounds);
+            bin.1 += 1;
+        }
+
+        // Cost of splitting before each bin, sweeping from the right then from the left
+        let mut right_costs = [0.0; BIN_COUNT];
+        let mut right = (Aabb::EMPTY, 0);
+        for i in (1..BIN_COUNT).rev() {
+            right = (right.0.union(&bins[i].0), right.1 + bins[i].1);
+            right_costs[i] = right.0.surface_area() * right.1 as f32;
+        }
+
+        let mut best: Option<(usize, f32)> = None;
+        let mut left = (Aabb::EMPTY, 0);
+        for i in 1..BIN_COUNT {
+            left = (left.0.union(&bins[i - 1].0), left.1 + bins[i - 1].1);
+            if left.1 == 0 || left.1 == count {
+                continue;
+            }
+
+            let cost = left.0.surface_area() * left.1 as f32 + right_costs[i];
+            match best {
+                Some((_, best_cost)) if best_cost <= cost => {}
+                _ => best = Some((i, cost)),
+            }
+        }
+
+        let (split_bin, split_cost) = best?;
+
+        // Costs are relative to the parent's area
+        let leaf_cost = bounds.surface_area() * count as f32;
+        if TRAVERSAL_COST * bounds.surface_area() + split_cost >= leaf_cost
+            && count <= MAX_LEAF_SIZE
+        {
+            return None;
+        }
+
+        let mut mid = start;
+        for i in start..end {
+            if bin_of(&self.primitives[i]) < split_bin {
+                self.primitives.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        Some(mid)
+    }
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BVH: {} primitives, {} nodes ({} leaves), depth {}, built in {:.2?}",
+            self.primitive_count, self.node_count, self.leaf_count, self.max_depth, self.build_time
+        )
+    }
+}
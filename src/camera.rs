@@ -0,0 +1,179 @@
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use bytemuck::{Pod, Zeroable};
+use display_json::DebugAsJsonPretty;
+use serde::Serialize;
+use std::fmt;
+
+/// The camera as laid out in `compute.glsl` (std430), use `Camera::look_at` to create one.
+///
+/// Each `vec3` is 16 bytes aligned, the shutter times and `lens_radius` fit in the padding.
+#[derive(Copy, Clone, Zeroable, Pod, Default, Serialize, DebugAsJsonPretty)]
+#[repr(C)]
+pub struct Camera {
+    pub origin: Vec3,
+    /// The shutter opens at `time0` and closes at `time1`, each ray is sent at a random time in
+    /// between. Moving objects are blurred along the path they take.
+    pub time0: f32,
+    pub lower_left_corner: Vec3,
+    pub time1: f32,
+    pub horizontal: Vec3,
+    #[serde(skip)]
+    _2: f32,
+    pub vertical: Vec3,
+    #[serde(skip)]
+    _3: f32,
+    pub up: Vec3,
+    #[serde(skip)]
+    _4: f32,
+    pub u: Vec3,
+    #[serde(skip)]
+    _5: f32,
+    pub v: Vec3,
+    #[serde(skip)]
+    _6: f32,
+    pub w: Vec3,
+    pub lens_radius: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraError {
+    /// `look_from` and `look_at` are the same point so there is no view direction
+    NoViewDirection,
+    /// The up vector is zero or parallel to the view direction
+    DegenerateUp,
+    /// The vertical field of view isn't within (0, 180) degrees
+    InvalidFov(f32),
+    InvalidAspectRatio(f32),
+    NegativeAperture(f32),
+    /// The focus distance is zero, negative or not a number
+    InvalidFocusDistance(f32),
+    /// The shutter must open and close within 0..=1, `time0` first
+    InvalidShutter(f32, f32),
+}
+
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CameraError::NoViewDirection => write!(f, "look_from and look_at are the same point"),
+            CameraError::DegenerateUp => {
+                write!(f, "the up vector is zero or parallel to the view direction")
+            }
+            CameraError::InvalidFov(vfov) => write!(
+                f,
+                "the vertical field of view must be between 0 and 180 degrees, got {}",
+                vfov
+            ),
+            CameraError::InvalidAspectRatio(aspect) => {
+                write!(f, "the aspect ratio must be greater than 0, got {}", aspect)
+            }
+            CameraError::NegativeAperture(aperture) => {
+                write!(f, "the aperture can't be negative, got {}", aperture)
+            }
+            CameraError::InvalidFocusDistance(focus_dist) => write!(
+                f,
+                "the focus distance must be greater than 0, got {}",
+                focus_dist
+            ),
+            CameraError::InvalidShutter(time0, time1) => write!(
+                f,
+                "the shutter must open and close between times 0 and 1, got {} to {}",
+                time0, time1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CameraError {}
+
+impl Camera {
+    /// Places a camera at `look_from` facing `look_at`, with the shutter open from time 0 to 1.
+    ///
+    /// `vfov` is the vertical field of view in degrees and `aspect_ratio` is width / height.
+    /// A `focus_dist` of `None` focuses on `look_at`, only matters when `aperture` isn't 0.
+    pub fn look_at(
+        look_from: Vec3,
+        look_at: Vec3,
+        vup: Vec3,
+        vfov: f32,
+        aspect_ratio: f32,
+        aperture: f32,
+        focus_dist: Option<f32>,
+    ) -> Result<Camera, CameraError> {
+        let view = look_from - look_at;
+        if view.length_squared() < 1e-12 {
+            return Err(CameraError::NoViewDirection);
+        }
+
+        let w = view.unit();
+        let cross = vup.cross(&w);
+        if cross.length_squared() < 1e-12 {
+            return Err(CameraError::DegenerateUp);
+        }
+
+        if vfov.is_nan() || vfov <= 0.0 || vfov >= 180.0 {
+            return Err(CameraError::InvalidFov(vfov));
+        }
+        if aspect_ratio.is_nan() || aspect_ratio <= 0.0 {
+            return Err(CameraError::InvalidAspectRatio(aspect_ratio));
+        }
+        if aperture.is_nan() || aperture < 0.0 {
+            return Err(CameraError::NegativeAperture(aperture));
+        }
+
+        let focus_dist = focus_dist.unwrap_or_else(|| view.length());
+        if focus_dist.is_nan() || focus_dist <= 0.0 {
+            return Err(CameraError::InvalidFocusDistance(focus_dist));
+        }
+
+        let theta = f32::to_radians(vfov);
+        let h = f32::tan(theta / 2.0);
+        let viewport_height = 2.0 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        let u = cross.unit();
+        let v = w.cross(&u);
+
+        let horizontal = focus_dist * u * viewport_width;
+        let vertical = focus_dist * v * viewport_height;
+
+        Ok(Camera {
+            origin: look_from,
+            lower_left_corner: look_from - (horizontal / 2.0) - (vertical / 2.0) - focus_dist * w,
+            horizontal,
+            vertical,
+            up: vup,
+            u,
+            v,
+            w,
+            lens_radius: aperture / 2.0,
+            time0: 0.0,
+            time1: 1.0,
+            ..Default::default()
+        })
+    }
+
+    /// Opens the shutter at `time0` and closes it at `time1`, both within 0..=1. The same times
+    /// freeze moving objects where they are at that time.
+    pub fn shutter(self, time0: f32, time1: f32) -> Result<Camera, CameraError> {
+        if !(0.0 <= time0 && time0 <= time1 && time1 <= 1.0) {
+            return Err(CameraError::InvalidShutter(time0, time1));
+        }
+
+        Ok(Camera {
+            time0,
+            time1,
+            ..self
+        })
+    }
+
+    /// The ray through the center of the lens hitting the viewport at `u`, `v` (0..=1, from the
+    /// lower left corner) when the shutter opens, handy for picking objects on the CPU
+    pub fn ray(&self, u: f32, v: f32) -> Ray {
+        Ray {
+            origin: self.origin,
+            dir: self.lower_left_corner + (u * self.horizontal) + (v * self.vertical) - self.origin,
+            time: self.time0,
+        }
+    }
+}
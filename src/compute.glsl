@@ -28,7 +28,9 @@ float reflectance(float cosine, float ref_idx)
 /** STRUCTURES **/
 struct Camera{
   vec3 origin;
+  float time0;
   vec3 lower_left_corner;
+  float time1;
   vec3 horizontal;
   vec3 vertical;
   vec3 up;
@@ -41,6 +43,7 @@ struct Camera{
 struct Ray {
     vec3 origin;
     vec3 dir;
+    float time;
 };
 
 struct HitRecord {
@@ -50,9 +53,8 @@ struct HitRecord {
 
   bool front_face;
 
-  uint mat_type;
-  vec3 albedo;
-  float fuzz_or_ir;
+  uint material;
+  vec2 uv;
 };
 
 struct ScatterResult {
@@ -61,20 +63,141 @@ struct ScatterResult {
   Ray ray;
 };
 
+// Moves by velocity from time 0 to time 1
 struct Sphere {
+    vec3 center;
     float radius;
-    uint mat_type;
-    float fuzz_or_ir;
+    vec3 velocity;
+    uint material;
+};
 
-    vec3 albedo;
-    vec3 center;
+// Per vertex normals are zero when the mesh has none
+struct Vertex {
+  vec3 position;
+  float u;
+  vec3 normal;
+  float v;
+};
+
+struct Triangle {
+  uint v0;
+  uint v1;
+  uint v2;
+  uint material;
+};
+
+// Spans min..max along the two axes other than axis, at k along axis. The outward normal
+// points towards +axis, or -axis when flip is set.
+struct Rect {
+  vec2 min;
+  vec2 max;
+  float k;
+  uint axis;
+  uint material;
+  uint flip;
+};
+
+// Places the object whose tree of the BVH starts at the node root, to_object maps world space
+// to object space
+struct Instance {
+  mat4 to_object;
+  uint root;
+};
+
+// Medium of constant density filling the object whose tree of the BVH starts at the node root
+struct Volume {
+  uint root;
+  float density;
+  uint material;
+  // Spelled out to match GpuVolume in volume.rs, the stride is 16 bytes
+  uint _0;
+};
+
+// Must match the constants in material.rs
+#define MATERIAL_LAMBERTIAN 0
+#define MATERIAL_METAL 1
+#define MATERIAL_DIELECTRIC 2
+#define MATERIAL_DIFFUSE_LIGHT 3
+#define MATERIAL_ISOTROPIC 4
+
+// Must match the constants in pdf.rs
+#define LIGHT_SAMPLING_OFF 0
+#define LIGHT_SAMPLING_BALANCE 1
+#define LIGHT_SAMPLING_POWER 2
+
+// Must match the constants in sampler.rs
+#define SAMPLER_RANDOM 0u
+#define SAMPLER_STRATIFIED 1u
+#define SAMPLER_HALTON 2u
+#define SAMPLER_SOBOL 3u
+#define SAMPLER_BLUE_NOISE 4u
+#define PIXEL_DIMENSION 0u
+#define LENS_DIMENSION 2u
+#define TIME_DIMENSION 4u
+#define BOUNCE_DIMENSION 5u
+#define BLUE_NOISE_SIZE 64u
+
+// Interior nodes have a count of 0 and their children at first and first + 1,
+// leaves cover the primitives first..first + count of bvh_primitives
+struct BvhNode {
+  vec3 min;
+  uint first;
+  vec3 max;
+  uint count;
+};
+
+// Must match STACK_SIZE in bvh.rs
+#define BVH_STACK_SIZE 32
+
+struct Material {
+  vec3 albedo;
+  uint kind;
+  vec3 emission;
+  float fuzz;
+  float ior;
+  uint texture;
+  uint fuzz_texture;
+};
+
+// Must match the constants in texture.rs
+#define NO_TEXTURE 0xFFFFFFFFu
+#define TEXTURE_IMAGE 0
+#define TEXTURE_SOLID 1
+#define TEXTURE_CHECKER 2
+#define TEXTURE_NOISE 3
+#define TEXTURE_TURBULENCE 4
+#define TEXTURE_MARBLE 5
+#define WRAP_REPEAT 0
+#define WRAP_MIRROR 1
+#define WRAP_CLAMP 2
+#define COLOR_SPACE_SRGB 0
+#define COLOR_SPACE_LINEAR 1
+
+// Images are width * height RGBA8 texels starting at first_texel, top row first
+struct Texture {
+  vec3 color;
+  uint kind;
+  uint first_texel;
+  uint width;
+  uint height;
+  uint wrap;
+  uint color_space;
+  uint even;
+  uint odd;
+  float scale;
+  uint depth;
+  // Spelled out to match GpuTexture in texture.rs, std430 would round the stride up to 64 anyway
+  uint _0;
+  uint _1;
+  uint _2;
 };
 
 /** SHADER LAYOUT **/
 layout(local_size_x = 1 , local_size_y = 1, local_size_z = 1) in;
 
+// Linear RGB, the average of the accumulated samples
 layout(set = 0, binding = 0) writeonly buffer Data {
-    uint colors[];
+    float colors[];
 } data;
 
 layout(set = 0, binding = 1) readonly buffer Config {
@@ -83,20 +206,104 @@ layout(set = 0, binding = 1) readonly buffer Config {
   uint max_bounces;
   uint width;
   uint height;
+  uint num_triangles;
+  uint num_rects;
+  uint num_instances;
+  uint num_volumes;
+  uint num_lights;
+  uint light_sampling;
+  uint seed;
 
   Camera camera;
+
+  vec3 background_bottom;
+  vec3 background_top;
+
+  // A density of 0 disables the fog
+  vec3 fog_color;
+  float fog_density;
+
+  uint sampler_mode;
+  uint num_bvh_nodes;
 } config;
 
 layout(set = 0, binding = 2) readonly buffer Scene {
   Sphere spheres[];
 } scene;
 
+layout(set = 0, binding = 3) readonly buffer Materials {
+  Material materials[];
+} material_buffer;
+
+layout(set = 0, binding = 4) readonly buffer Bvh {
+  BvhNode nodes[];
+} bvh;
+
+// Sums of the color samples (xyz) and of their squared luminance (w)
+layout(set = 0, binding = 5) buffer Accumulation {
+  vec4 pixels[];
+} accumulation;
+
+layout(set = 0, binding = 6) readonly buffer Vertices {
+  Vertex vertices[];
+} mesh_vertices;
+
+layout(set = 0, binding = 7) readonly buffer Triangles {
+  Triangle triangles[];
+} mesh_triangles;
+
+// Primitives in the order of the BVH leaves: spheres below num_spheres, then the triangles,
+// the rectangles, the instances and the volumes
+layout(set = 0, binding = 8) readonly buffer BvhPrimitives {
+  uint indices[];
+} bvh_primitives;
+
+layout(set = 0, binding = 9) readonly buffer Textures {
+  Texture textures[];
+} texture_buffer;
+
+layout(set = 0, binding = 10) readonly buffer Texels {
+  uint texels[];
+} texel_buffer;
+
+layout(set = 0, binding = 11) readonly buffer Rects {
+  Rect rects[];
+} rect_buffer;
+
+layout(set = 0, binding = 12) readonly buffer Instances {
+  Instance instances[];
+} instance_buffer;
+
+layout(set = 0, binding = 13) readonly buffer Volumes {
+  Volume volumes[];
+} volume_buffer;
+
+// Emissive spheres and rectangles, numbered like the primitives of the BVH leaves
+layout(set = 0, binding = 14) readonly buffer Lights {
+  uint primitives[];
+} light_buffer;
+
+// Threshold mask of sampler::blue_noise, only filled for SAMPLER_BLUE_NOISE
+layout(set = 0, binding = 15) readonly buffer BlueNoise {
+  float values[];
+} blue_noise;
+
 layout(push_constant) uniform PushConstantData {
   uint index;
+  // Samples sample_offset..sample_offset + sample_count get added to the accumulation buffer,
+  // an offset of 0 starts a new image
+  uint sample_offset;
+  uint sample_count;
 } push_constants;
 
+const float PI = 3.1415926535897932385;
+
+// Rec. 709, must match LUMINANCE in progressive.rs
+const vec3 LUMINANCE = vec3(0.2126, 0.7152, 0.0722);
+
 /** RANDOM NUMBER GENERATOR **/
-// A single iteration of Bob Jenkins' One-At-A-Time hashing algorithm.
+// A single iteration of Bob Jenkins' One-At-A-Time hashing algorithm, the lattice of the
+// Perlin noise textures.
 uint hash( uint x ) {
     x += ( x << 10u );
     x ^= ( x >>  6u );
@@ -106,70 +313,450 @@ uint hash( uint x ) {
     return x;
 }
 
-// Compound versions of the hashing algorithm I whipped together.
-uint hash( uvec2 v ) { return hash( v.x ^ hash(v.y)                         ); }
-uint hash( uvec3 v ) { return hash( v.x ^ hash(v.y) ^ hash(v.z)             ); }
-uint hash( uvec4 v ) { return hash( v.x ^ hash(v.y) ^ hash(v.z) ^ hash(v.w) ); }
+// 32 bit PCG (RXS-M-XS), must match rng.rs. Every sample of every pixel draws from its own
+// stream, seeded by begin_sample.
+uint rng_state;
+
+uint pcg_step(uint state)
+{
+  return state * 747796405u + 2891336453u;
+}
+
+uint pcg_output(uint state)
+{
+  uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+  return (word >> 22u) ^ word;
+}
+
+// A single round of the generator, scrambles the seeds
+uint pcg_hash(uint x)
+{
+  return pcg_output(pcg_step(x));
+}
+
+uint random_uint()
+{
+  rng_state = pcg_step(rng_state);
+  return pcg_output(rng_state);
+}
+
+// The top 24 bits in [0:1), so that every value is exact
+float to_unit(uint x)
+{
+  return float(x >> 8u) * (1.0 / 16777216.0);
+}
+
+float random()
+{
+  return to_unit(random_uint());
+}
 
-// Construct a float with half-open range [0:1] using low 23 bits.
-// All zeroes yields 0.0, all ones yields the next smallest representable value below 1.0.
-float floatConstruct( uint m ) {
-    const uint ieeeMantissa = 0x007FFFFFu; // binary32 mantissa bitmask
-    const uint ieeeOne      = 0x3F800000u; // 1.0 in IEEE binary32
+/** SAMPLER **/
+// Mirrors PixelSample in sampler.rs: the numbers of the dimensions of the current sample
+uint sample_x;
+uint sample_y;
+uint sample_pixel;
+uint sample_number;
 
-    m &= ieeeMantissa;                     // Keep only mantissa bits (fractional part)
-    m |= ieeeOne;                          // Add fractional part to 1.0
+const uint PRIMES[32] = uint[](
+  2u, 3u, 5u, 7u, 11u, 13u, 17u, 19u, 23u, 29u, 31u, 37u, 41u, 43u, 47u, 53u, 59u, 61u, 67u,
+  71u, 73u, 79u, 83u, 89u, 97u, 101u, 103u, 107u, 109u, 113u, 127u, 131u
+);
 
-    float  f = uintBitsToFloat( m );       // Range [1:2]
-    return f - 1.0;                        // Range [0:1]
+void begin_sample(uint x, uint y, uint sample_index)
+{
+  sample_x = x;
+  sample_y = y;
+  sample_pixel = y * config.width + x;
+  sample_number = sample_index;
+  rng_state = pcg_hash(config.seed + pcg_hash(sample_index + pcg_hash(sample_pixel)));
 }
 
-// Pseudo-random value in half-open range [0:1].
-float random( float x ) { return floatConstruct(hash(floatBitsToUint(x))); }
-float random( vec2  v ) { return floatConstruct(hash(floatBitsToUint(v))); }
-float random( vec3  v ) { return floatConstruct(hash(floatBitsToUint(v))); }
-float random( vec4  v ) { return floatConstruct(hash(floatBitsToUint(v))); }
+// Seed shared by every pixel for the dimension
+uint sampler_pattern(uint dimension)
+{
+  return pcg_hash(config.seed + pcg_hash(dimension));
+}
 
-vec3 randomDiskPoint(vec3 rand) {
-  float x = 1.0;
-  while (true)
+uint pixel_pattern(uint dimension)
+{
+  return pcg_hash(sampler_pattern(dimension) + pcg_hash(sample_pixel));
+}
+
+// Back into [0:1) after an offset, x is in [0:2)
+float wrap_unit(float x)
+{
+  return min(x >= 1.0 ? x - 1.0 : x, 0.99999994);
+}
+
+// Permutation of 0..length picked by p, from Kensler's "Correlated Multi-Jittered Sampling"
+uint permute(uint i, uint length, uint p)
+{
+  uint w = length - 1u;
+  w |= w >> 1u;
+  w |= w >> 2u;
+  w |= w >> 4u;
+  w |= w >> 8u;
+  w |= w >> 16u;
+
+  do {
+    i ^= p;
+    i *= 0xe170893du;
+    i ^= p >> 16u;
+    i ^= (i & w) >> 4u;
+    i ^= p >> 8u;
+    i *= 0x0929eb3fu;
+    i ^= p >> 23u;
+    i ^= (i & w) >> 1u;
+    i *= 1u | p >> 27u;
+    i *= 0x6935fa69u;
+    i ^= (i & w) >> 11u;
+    i *= 0x74dcb303u;
+    i ^= (i & w) >> 2u;
+    i *= 0x9e501cc3u;
+    i ^= (i & w) >> 2u;
+    i *= 0xc860a3dfu;
+    i &= w;
+    i ^= i >> 5u;
+  } while(i >= length);
+
+  return (i + p) % length;
+}
+
+// Offset within a stratum, Kensler's randfloat
+float jitter(uint i, uint p)
+{
+  i ^= p;
+  i ^= i >> 17u;
+  i ^= i >> 10u;
+  i *= 0xb36534e5u;
+  i ^= i >> 12u;
+  i ^= i >> 21u;
+  i *= 0x93fc4795u;
+  i ^= 0xdf6e307fu;
+  i ^= i >> 17u;
+  i *= 1u | p >> 18u;
+  return to_unit(i);
+}
+
+// Index among the sample_count strata (x) and the pattern they're shuffled by (y)
+uvec2 stratum(uint dimension)
+{
+  uint count = max(config.sample_count, 1u);
+  uint set = sample_number / count;
+  return uvec2(sample_number % count, pcg_hash(pixel_pattern(dimension) + set));
+}
+
+vec2 multi_jittered(uint index, uint count, uint pattern)
+{
+  uint m = max(uint(sqrt(float(count))), 1u);
+  uint n = (count + m - 1u) / m;
+
+  uint s = permute(index, count, pattern * 0x51633e2du);
+  uint column = s % m;
+  uint row = s / m;
+  uint sx = permute(column, m, pattern * 0x68bc21ebu);
+  uint sy = permute(row, n, pattern * 0x02e5be93u);
+  float jx = jitter(s, pattern * 0x967a889bu);
+  float jy = jitter(s, pattern * 0x368cc8b7u);
+
+  return vec2(
+    (float(column) + (float(sy) + jx) / float(n)) / float(m),
+    (float(row) + (float(sx) + jy) / float(m)) / float(n)
+  );
+}
+
+// Digits of index in base mirrored around the point, each position shuffled by its own
+// permutation. The zeros past the last digit are shuffled too, as long as they add anything.
+float radical_inverse(uint index, uint base, uint pattern)
+{
+  float inverse = 1.0 / float(base);
+  float scale = 1.0;
+  float result = 0.0;
+  for(uint position = 0u;; position++)
   {
-    vec3 p = vec3(
-        -1.0 + (random(rand + vec3(x)) * 2.0),
-        -1.0 + (random(-rand - vec3(x)) * 2.0),
-        0.0
-    );
+    scale *= inverse;
+    if(index == 0u && scale < 1.0 / 16777216.0) {
+      break;
+    }
+
+    uint digit = permute(index % base, base, pcg_hash(pattern + position));
+    result += scale * float(digit);
+    index /= base;
+  }
+  return min(result, 0.99999994);
+}
 
-    if(length_squared(p) < 1.0)
-      return p;
+float halton(uint dimension)
+{
+  if(dimension >= 32u) {
+    return random();
+  }
+  return radical_inverse(sample_number, PRIMES[dimension], pixel_pattern(dimension));
+}
 
-    x++;
+// The first two dimensions of the Sobol sequence
+uvec2 sobol(uint index)
+{
+  uint i = index;
+  uint v = 1u << 31u;
+  uint y = 0u;
+  while(i != 0u)
+  {
+    if((i & 1u) != 0u) {
+      y ^= v;
+    }
+    i >>= 1u;
+    v ^= v >> 1u;
   }
+  return uvec2(bitfieldReverse(index), y);
+}
+
+// Owen scrambling of the bits of x, from Burley's "Practical Hash-based Owen Scrambling"
+uint owen_scramble(uint x, uint seed)
+{
+  x = bitfieldReverse(x);
+  x += seed;
+  x ^= x * 0x6c50b47cu;
+  x ^= x * 0xb82f1e52u;
+  x ^= x * 0xc7afe638u;
+  x ^= x * 0x8d22f6e6u;
+  return bitfieldReverse(x);
+}
+
+// Sobol point of the sequence shuffled and scrambled by pattern
+vec2 owen_sobol(uint index, uint pattern)
+{
+  uvec2 point = sobol(owen_scramble(index, pattern));
+  return vec2(
+    to_unit(owen_scramble(point.x, pcg_hash(pattern))),
+    to_unit(owen_scramble(point.y, pcg_hash(pattern ^ 1u)))
+  );
 }
 
-vec3 randomSpherePoint(vec3 rand) {
-  float x = 1.0;
-  vec3 p;
+float blue_noise_mask(uint offset)
+{
+  uint x = (sample_x + (offset & 0xffffu)) % BLUE_NOISE_SIZE;
+  uint y = (sample_y + (offset >> 16u)) % BLUE_NOISE_SIZE;
+  return blue_noise.values[y * BLUE_NOISE_SIZE + x];
+}
 
-  while(true)
+// The same Sobol points for every pixel, shifted by the mask at an offset per number
+vec2 shifted_sobol(uint dimension)
+{
+  uint pattern = sampler_pattern(dimension);
+  vec2 point = owen_sobol(sample_number, pattern);
+  uint offset = pcg_hash(pattern);
+  return vec2(
+    wrap_unit(point.x + blue_noise_mask(offset)),
+    wrap_unit(point.y + blue_noise_mask(pcg_hash(offset)))
+  );
+}
+
+float sample_1d(uint dimension)
+{
+  switch(config.sampler_mode)
   {
-    p = vec3(
-        -1.0 + (random(rand + vec3(x)) * 2.0),
-        -1.0 + (random(-rand - vec3(x)) * 2.0),
-        -1.0 + (random(rand / (2.0 * x)) * 2.0)
-     );
+    case SAMPLER_RANDOM:
+      return random();
+    case SAMPLER_STRATIFIED:
+    {
+      uvec2 s = stratum(dimension);
+      uint count = max(config.sample_count, 1u);
+      uint index = permute(s.x, count, s.y * 0x68bc21ebu);
+      return (float(index) + jitter(s.x, s.y * 0x967a889bu)) / float(count);
+    }
+    case SAMPLER_HALTON:
+      return halton(dimension);
+    case SAMPLER_BLUE_NOISE:
+      return shifted_sobol(dimension).x;
+    case SAMPLER_SOBOL:
+    default:
+      return owen_sobol(sample_number, pixel_pattern(dimension)).x;
+  }
+}
+
+// Numbers of the dimension and the next one
+vec2 sample_2d(uint dimension)
+{
+  switch(config.sampler_mode)
+  {
+    case SAMPLER_RANDOM:
+    {
+      float x = random();
+      return vec2(x, random());
+    }
+    case SAMPLER_STRATIFIED:
+    {
+      uvec2 s = stratum(dimension);
+      return multi_jittered(s.x, max(config.sample_count, 1u), s.y);
+    }
+    case SAMPLER_HALTON:
+    {
+      float x = halton(dimension);
+      return vec2(x, halton(dimension + 1u));
+    }
+    case SAMPLER_BLUE_NOISE:
+      return shifted_sobol(dimension);
+    case SAMPLER_SOBOL:
+    default:
+      return owen_sobol(sample_number, pixel_pattern(dimension));
+  }
+}
+
+// A pair for the direction and one for the choices of the material
+vec3 sample_bounce(uint bounce)
+{
+  uint dimension = BOUNCE_DIMENSION + 3u * bounce;
+  vec2 direction = sample_2d(dimension);
+  return vec3(direction, sample_1d(dimension + 2u));
+}
+
+// Concentric mapping onto the unit disk of the xy plane, keeps the strata of the square
+vec3 square_to_disk(vec2 u)
+{
+  vec2 p = 2.0 * u - 1.0;
+  if(p.x == 0.0 && p.y == 0.0) {
+    return vec3(0.0);
+  }
+
+  float r;
+  float theta;
+  if(abs(p.x) > abs(p.y)) {
+    r = p.x;
+    theta = (PI / 4.0) * (p.y / p.x);
+  } else {
+    r = p.y;
+    theta = (PI / 2.0) - (PI / 4.0) * (p.x / p.y);
+  }
+  return vec3(r * cos(theta), r * sin(theta), 0.0);
+}
 
-    if(length_squared(p) < 1.0)
-        return p;
-    x++;
+// Uniform over the surface of the unit sphere
+vec3 square_to_sphere(vec2 u)
+{
+  float z = 1.0 - 2.0 * u.x;
+  float radial = sqrt(max(1.0 - z * z, 0.0));
+  float phi = 2.0 * PI * u.y;
+  return vec3(radial * cos(phi), radial * sin(phi), z);
+}
+/** TEXTURES **/
+// Maps the texel coordinate i into 0..size, mirrors WrapMode::apply in texture.rs
+uint wrap(float i, uint wrap_mode, uint size)
+{
+  float n = float(size);
+  if(wrap_mode == WRAP_REPEAT) {
+    i = mod(i, n);
+  } else if(wrap_mode == WRAP_MIRROR) {
+    i = mod(i, 2.0 * n);
+    i = i < n ? i : 2.0 * n - 1.0 - i;
+  }
+  return uint(clamp(i, 0.0, n - 1.0));
+}
+
+vec3 texel(Texture image, float x, float y)
+{
+  uint i = wrap(y, image.wrap, image.height) * image.width + wrap(x, image.wrap, image.width);
+  vec3 color = unpackUnorm4x8(texel_buffer.texels[image.first_texel + i]).rgb;
+
+  if(image.color_space == COLOR_SPACE_SRGB) {
+    color = mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), greaterThan(color, vec3(0.04045)));
+  }
+  return color;
+}
+
+// Bilinear lookup, (0, 0) is the bottom left corner of the image
+vec3 image_value(Texture image, vec2 uv)
+{
+  // Texel centers sit at half coordinates, rows are stored top down
+  float x = uv.x * float(image.width) - 0.5;
+  float y = (1.0 - uv.y) * float(image.height) - 0.5;
+  float x0 = floor(x);
+  float y0 = floor(y);
+  float fx = x - x0;
+  float fy = y - y0;
+
+  vec3 top = mix(texel(image, x0, y0), texel(image, x0 + 1.0, y0), fx);
+  vec3 bottom = mix(texel(image, x0, y0 + 1.0), texel(image, x0 + 1.0, y0 + 1.0), fx);
+  return mix(top, bottom, fy);
+}
+
+// Dot product of offset with the gradient of the lattice point corner, one of the 12 edges of
+// a cube as in Ken Perlin's improved noise. Mirrors gradient in texture.rs
+float gradient(ivec3 corner, vec3 offset)
+{
+  uvec3 i = uvec3(corner);
+  uint h = hash(i.x ^ hash(i.y ^ hash(i.z))) & 15u;
+
+  float u = h < 8u ? offset.x : offset.y;
+  float v = h < 4u ? offset.y : (h == 12u || h == 14u ? offset.x : offset.z);
+  return ((h & 1u) == 0u ? u : -u) + ((h & 2u) == 0u ? v : -v);
+}
+
+// Perlin noise, roughly between -1 and 1
+float noise(vec3 p)
+{
+  vec3 cell = floor(p);
+  vec3 f = p - cell;
+  // Hermite smoothing hides the lattice
+  vec3 w = f * f * (3.0 - 2.0 * f);
+
+  float result = 0.0;
+  for(int corner = 0; corner < 8; corner++) {
+    ivec3 c = ivec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+    vec3 weight = mix(1.0 - w, w, vec3(c));
+    result += weight.x * weight.y * weight.z * gradient(ivec3(cell) + c, f - vec3(c));
+  }
+  return result;
+}
+
+// Sum of depth octaves of noise, each at twice the frequency and half the weight of the last
+float turbulence(vec3 p, uint depth)
+{
+  float accumulated = 0.0;
+  float weight = 1.0;
+  for(uint i = 0u; i < depth; i++) {
+    accumulated += weight * noise(p);
+    weight *= 0.5;
+    p *= 2.0;
+  }
+  return abs(accumulated);
+}
+
+// Color of a texture at the texture coordinates uv of the hit point, mirrors Texture::value
+vec3 texture_value(uint index, vec2 uv, vec3 point)
+{
+  Texture tex = texture_buffer.textures[index];
+
+  // Checkers only reference textures added before them, this can't loop forever
+  while(tex.kind == TEXTURE_CHECKER) {
+    ivec3 cell = ivec3(floor(point / tex.scale));
+    index = ((cell.x + cell.y + cell.z) & 1) == 0 ? tex.even : tex.odd;
+    tex = texture_buffer.textures[index];
+  }
+
+  switch(tex.kind)
+  {
+    case TEXTURE_SOLID:
+      return tex.color;
+    case TEXTURE_NOISE:
+      return vec3(0.5 * (1.0 + noise(tex.scale * point)));
+    case TEXTURE_TURBULENCE:
+      return vec3(turbulence(tex.scale * point, tex.depth));
+    case TEXTURE_MARBLE:
+      return vec3(0.5 * (1.0 + sin(tex.scale * point.z + 10.0 * turbulence(point, tex.depth))));
+    case TEXTURE_IMAGE:
+    default:
+      return image_value(tex, uv);
   }
 }
 /** SCATTER FUNCS **/
-ScatterResult scatter_lambertian(Ray ray, HitRecord hit_record, vec3 rand)
+ScatterResult scatter_lambertian(Ray ray, HitRecord hit_record, Material material, vec3 u)
 {
   Ray scatter_ray;
   scatter_ray.origin = hit_record.point;
-  scatter_ray.dir = hit_record.normal + unit(randomSpherePoint(rand));
+  scatter_ray.time = ray.time;
+  scatter_ray.dir = hit_record.normal + square_to_sphere(u.xy);
 
   if(is_near_zero(scatter_ray.dir)){
     scatter_ray.dir = hit_record.normal;
@@ -177,42 +764,59 @@ ScatterResult scatter_lambertian(Ray ray, HitRecord hit_record, vec3 rand)
 
   ScatterResult result;
   result.scattered = true;
-  result.attenuation = hit_record.albedo;
+  result.attenuation = material.albedo;
   result.ray = scatter_ray;
   return result;
 }
 
-ScatterResult scatter_metal(Ray ray, HitRecord hit_record, vec3 rand)
+ScatterResult scatter_isotropic(Ray ray, HitRecord hit_record, Material material, vec3 u)
+{
+  Ray scatter_ray;
+  scatter_ray.origin = hit_record.point;
+  scatter_ray.time = ray.time;
+  scatter_ray.dir = square_to_sphere(u.xy);
+
+  ScatterResult result;
+  result.scattered = true;
+  result.attenuation = material.albedo;
+  result.ray = scatter_ray;
+  return result;
+}
+
+ScatterResult scatter_metal(Ray ray, HitRecord hit_record, Material material, vec3 u)
 {
     Ray scatter_ray;
     scatter_ray.origin = hit_record.point;
+    scatter_ray.time = ray.time;
+    // Uniform in the ball of radius fuzz
     scatter_ray.dir = reflect(unit(ray.dir), hit_record.normal) 
-    + (hit_record.fuzz_or_ir * randomSpherePoint(rand * 69.0));
+    + (material.fuzz * pow(u.z, 1.0 / 3.0) * square_to_sphere(u.xy));
 
     ScatterResult result;
     result.scattered = dot(scatter_ray.dir, hit_record.normal) > 0.0;
-    result.attenuation = hit_record.albedo;
+    result.attenuation = material.albedo;
     result.ray = scatter_ray;
 
     return result;
 }
 
-ScatterResult scatter_dielectric(Ray ray, HitRecord hit_record, vec3 rand)
+ScatterResult scatter_dielectric(Ray ray, HitRecord hit_record, Material material, vec3 u)
 {
   Ray scatter_ray;
   scatter_ray.origin = hit_record.point;
+  scatter_ray.time = ray.time;
 
   vec3 unit_dir = unit(ray.dir);
 
   float refraction_ratio = hit_record.front_face ?  
-    1.0 / hit_record.fuzz_or_ir :
-    hit_record.fuzz_or_ir;
+    1.0 / material.ior :
+    material.ior;
 
   float cos_theta = min(dot(-unit_dir, hit_record.normal), 1.0);
   float sin_theta = sqrt(1.0 - (cos_theta * cos_theta));
 
   scatter_ray.dir = refraction_ratio * sin_theta > 1.0 || 
-                    reflectance(cos_theta, refraction_ratio) > random(rand * cos_theta) ?
+                    reflectance(cos_theta, refraction_ratio) > u.z ?
                     reflect(unit_dir, hit_record.normal) :
                     refract(unit_dir, hit_record.normal, refraction_ratio);
     
@@ -225,79 +829,530 @@ ScatterResult scatter_dielectric(Ray ray, HitRecord hit_record, vec3 rand)
 }
 
 /** RAY PROCESSING **/
-vec3 ProcessRay(Ray ray, uint x, uint y, uint z)
+bool hit_sphere(Sphere sphere, Ray ray, float t_max, inout HitRecord hit_record)
 {
-  vec3 out_color = vec3(1.0);
+  vec3 center = sphere.center + ray.time * sphere.velocity;
+  vec3 oc = ray.origin - center;
+  float a = length_squared(ray.dir);
+  float half_b = dot(oc, ray.dir);
+  float c = length_squared(oc) - (sphere.radius * sphere.radius);
 
-  for(uint b = 0;; b++)
-  {
-    if(b == config.max_bounces - 1) {
-      return vec3(0.0, 0.0, 0.0);
+  float discriminant = (half_b * half_b) - (a * c);
+
+  if(discriminant < 0.0) {
+    return false;
+  }
+
+  float sqrtd = sqrt(discriminant);
+  float root = (-half_b - sqrtd) / a;
+
+  if(root <  0.001 || root > t_max){
+      root = (-half_b + sqrtd) / a;
+    if(root <  0.001 || root > t_max){
+      return false;
     }
+  }
 
-    bool hit_anything = false;
-    HitRecord hit_record;
+  hit_record.material = sphere.material;
 
-    float t_max = 1.0 / 0.0; // INF
-    for(uint i = 0; i < config.num_spheres; i++)
-    {
-      Sphere sphere = scene.spheres[i];
+  hit_record.t = root;
+  hit_record.point = ray.origin + root * ray.dir;
+
+  vec3 outward_normal = (hit_record.point - center) / sphere.radius;
+  hit_record.front_face = dot(ray.dir, outward_normal) < 0.0;
+  hit_record.normal = hit_record.front_face ? outward_normal : -outward_normal;
+
+  // Longitude from -x around through +z, latitude from -y
+  float theta = acos(clamp(-outward_normal.y, -1.0, 1.0));
+  float phi = atan(-outward_normal.z, outward_normal.x) + PI;
+  hit_record.uv = vec2(phi / (2.0 * PI), theta / PI);
+  return true;
+}
+
+// Moller-Trumbore ray-triangle intersection
+bool hit_triangle(Triangle triangle, Ray ray, float t_max, inout HitRecord hit_record)
+{
+  Vertex a = mesh_vertices.vertices[triangle.v0];
+  Vertex b = mesh_vertices.vertices[triangle.v1];
+  Vertex c = mesh_vertices.vertices[triangle.v2];
+
+  vec3 edge1 = b.position - a.position;
+  vec3 edge2 = c.position - a.position;
+  vec3 p = cross(ray.dir, edge2);
+  float det = dot(edge1, p);
+
+  // The ray is parallel to the triangle
+  if(abs(det) < 1e-8) {
+    return false;
+  }
+
+  float inv_det = 1.0 / det;
+  vec3 s = ray.origin - a.position;
+  float u = dot(s, p) * inv_det;
+  if(u < 0.0 || u > 1.0) {
+    return false;
+  }
+
+  vec3 q = cross(s, edge1);
+  float v = dot(ray.dir, q) * inv_det;
+  if(v < 0.0 || u + v > 1.0) {
+    return false;
+  }
+
+  float t = dot(edge2, q) * inv_det;
+  if(t < 0.001 || t > t_max) {
+    return false;
+  }
+
+  hit_record.material = triangle.material;
+
+  hit_record.t = t;
+  hit_record.point = ray.origin + t * ray.dir;
+
+  vec3 outward_normal = unit(cross(edge1, edge2));
+  hit_record.front_face = dot(ray.dir, outward_normal) < 0.0;
+
+  // Interpolated normals can lean past the silhouette, keep them on the side of the face
+  vec3 normal = (1.0 - u - v) * a.normal + u * b.normal + v * c.normal;
+  normal = is_near_zero(normal) ? outward_normal : unit(normal);
+  normal = dot(normal, outward_normal) < 0.0 ? -normal : normal;
+
+  hit_record.normal = hit_record.front_face ? normal : -normal;
+  hit_record.uv = (1.0 - u - v) * vec2(a.u, a.v) + u * vec2(b.u, b.v) + v * vec2(c.u, c.v);
+  return true;
+}
+
+bool hit_rect(Rect rect, Ray ray, float t_max, inout HitRecord hit_record)
+{
+  // Indices of the two axes the rectangle spans
+  uint a = rect.axis == 0u ? 1u : 0u;
+  uint b = rect.axis == 2u ? 1u : 2u;
+
+  // Also rejects rays parallel to the rectangle, for which t isn't a number
+  float t = (rect.k - ray.origin[rect.axis]) / ray.dir[rect.axis];
+  if(!(t >= 0.001 && t <= t_max)) {
+    return false;
+  }
+
+  vec3 point = ray.origin + t * ray.dir;
+  vec2 uv = (vec2(point[a], point[b]) - rect.min) / (rect.max - rect.min);
+  if(any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+    return false;
+  }
+
+  hit_record.material = rect.material;
+
+  hit_record.t = t;
+  hit_record.point = point;
+
+  vec3 outward_normal = vec3(0.0);
+  outward_normal[rect.axis] = rect.flip == 0u ? 1.0 : -1.0;
+  hit_record.front_face = dot(ray.dir, outward_normal) < 0.0;
+  hit_record.normal = hit_record.front_face ? outward_normal : -outward_normal;
+  hit_record.uv = uv;
+  return true;
+}
+
+bool hit_aabb(BvhNode node, Ray ray, vec3 inv_dir, float t_max)
+{
+  vec3 t0 = (node.min - ray.origin) * inv_dir;
+  vec3 t1 = (node.max - ray.origin) * inv_dir;
+  vec3 t_near = min(t0, t1);
+  vec3 t_far = max(t0, t1);
+
+  float t_enter = max(max(t_near.x, t_near.y), max(t_near.z, 0.001));
+  float t_exit = min(min(t_far.x, t_far.y), min(t_far.z, t_max));
+  return t_enter <= t_exit;
+}
+
+// Sphere, triangle or rectangle, numbered like the BVH leaves
+bool hit_primitive(uint primitive, Ray ray, float t_max, inout HitRecord hit_record)
+{
+  uint meshes_end = config.num_spheres + config.num_triangles;
+  if(primitive < config.num_spheres) {
+    return hit_sphere(scene.spheres[primitive], ray, t_max, hit_record);
+  } else if(primitive < meshes_end) {
+    return hit_triangle(mesh_triangles.triangles[primitive - config.num_spheres], ray, t_max, hit_record);
+  }
+  return hit_rect(rect_buffer.rects[primitive - meshes_end], ray, t_max, hit_record);
+}
+
+// Walks the tree of an object, which never holds instances. Mirrors hit_scene, shaders can't
+// recurse.
+bool hit_object(uint root, Ray ray, float t_max, inout HitRecord hit_record)
+{
+  bool hit_anything = false;
+  vec3 inv_dir = 1.0 / ray.dir;
+
+  uint stack[BVH_STACK_SIZE];
+  uint stack_size = 1;
+  stack[0] = root;
 
-      vec3 oc = ray.origin - sphere.center;
-      float a = length_squared(ray.dir);
-      float half_b = dot(oc, ray.dir);
-      float c = length_squared(oc) - (sphere.radius * sphere.radius);
+  while(stack_size > 0)
+  {
+    stack_size--;
+    BvhNode node = bvh.nodes[stack[stack_size]];
 
-      float discriminant = (half_b * half_b) - (a * c);
+    if(!hit_aabb(node, ray, inv_dir, t_max)) {
+      continue;
+    }
 
-      if(discriminant < 0.0) {
-        continue;
+    if(node.count > 0)
+    {
+      for(uint i = node.first; i < node.first + node.count; i++)
+      {
+        if(hit_primitive(bvh_primitives.indices[i], ray, t_max, hit_record))
+        {
+          hit_anything = true;
+          t_max = hit_record.t;
+        }
       }
+    }
+    else
+    {
+      stack[stack_size] = node.first + 1;
+      stack[stack_size + 1] = node.first;
+      stack_size += 2;
+    }
+  }
+
+  return hit_anything;
+}
+
+bool hit_instance(Instance instance, Ray ray, float t_max, inout HitRecord hit_record)
+{
+  // The direction isn't normalized so that distances along both rays are the same
+  Ray object_ray;
+  object_ray.origin = (instance.to_object * vec4(ray.origin, 1.0)).xyz;
+  object_ray.dir = mat3(instance.to_object) * ray.dir;
+  object_ray.time = ray.time;
+
+  if(!hit_object(instance.root, object_ray, t_max, hit_record)) {
+    return false;
+  }
+
+  // The inverse transpose keeps normals perpendicular to the transformed surface
+  hit_record.normal = unit(transpose(mat3(instance.to_object)) * hit_record.normal);
+  hit_record.point = ray.origin + hit_record.t * ray.dir;
+  return true;
+}
+
+// Medium filling a closed object, the ConstantMedium of the second book. The ray scatters after
+// a random distance within it, exponentially distributed with the density.
+bool hit_volume(Volume volume, Ray ray, float t_max, inout HitRecord hit_record)
+{
+  // Where the ray enters and leaves the boundary, from its origin when it starts inside
+  HitRecord boundary;
+  if(!hit_object(volume.root, ray, 1.0 / 0.0, boundary)) {
+    return false;
+  }
+
+  float t_enter = 0.0;
+  float t_exit = boundary.t;
+  if(boundary.front_face)
+  {
+    Ray inside;
+    inside.origin = boundary.point;
+    inside.dir = ray.dir;
+    inside.time = ray.time;
+
+    t_enter = boundary.t;
+    if(!hit_object(volume.root, inside, 1.0 / 0.0, boundary)) {
+      return false;
+    }
+    t_exit = t_enter + boundary.t;
+  }
+  t_exit = min(t_exit, t_max);
+  if(t_enter >= t_exit) {
+    return false;
+  }
+
+  // The distance to the next collision with a particle of the medium
+  float distance = -log(1.0 - random()) / volume.density;
+  float t = t_enter + distance / length(ray.dir);
+  if(t >= t_exit) {
+    return false;
+  }
+
+  hit_record.material = volume.material;
+
+  hit_record.t = t;
+  hit_record.point = ray.origin + t * ray.dir;
+
+  // Isotropic materials ignore the normal
+  hit_record.normal = vec3(1.0, 0.0, 0.0);
+  hit_record.front_face = true;
+  hit_record.uv = vec2(0.0);
+  return true;
+}
+
+bool hit_scene(Ray ray, inout HitRecord hit_record)
+{
+  uint primitives_end = config.num_spheres + config.num_triangles + config.num_rects;
+  uint instances_end = primitives_end + config.num_instances;
+  if(config.num_bvh_nodes == 0) {
+    return false;
+  }
+
+  bool hit_anything = false;
+  float t_max = 1.0 / 0.0; // INF
+  vec3 inv_dir = 1.0 / ray.dir;
+
+  uint stack[BVH_STACK_SIZE];
+  uint stack_size = 1;
+  stack[0] = 0;
+
+  while(stack_size > 0)
+  {
+    stack_size--;
+    BvhNode node = bvh.nodes[stack[stack_size]];
+
+    if(!hit_aabb(node, ray, inv_dir, t_max)) {
+      continue;
+    }
 
-      float sqrtd = sqrt(discriminant);
-      float root = (-half_b - sqrtd) / a;
+    if(node.count > 0)
+    {
+      for(uint i = node.first; i < node.first + node.count; i++)
+      {
+        uint primitive = bvh_primitives.indices[i];
+        bool hit;
+        if(primitive < primitives_end) {
+          hit = hit_primitive(primitive, ray, t_max, hit_record);
+        } else if(primitive < instances_end) {
+          hit = hit_instance(instance_buffer.instances[primitive - primitives_end], ray, t_max, hit_record);
+        } else {
+          hit = hit_volume(volume_buffer.volumes[primitive - instances_end], ray, t_max, hit_record);
+        }
 
-      if(root <  0.001 || root > t_max){
-          root = (-half_b + sqrtd) / a;
-        if(root <  0.001 || root > t_max){
-          continue;
+        if(hit)
+        {
+          hit_anything = true;
+          t_max = hit_record.t;
         }
       }
+    }
+    else
+    {
+      stack[stack_size] = node.first + 1;
+      stack[stack_size + 1] = node.first;
+      stack_size += 2;
+    }
+  }
+
+  return hit_anything;
+}
+
+/** LIGHT SAMPLING **/
+// Orthonormal basis around w, mirrors Onb in pdf.rs
+mat3 onb(vec3 w)
+{
+  w = unit(w);
+  vec3 a = abs(w.x) > 0.9 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+  vec3 v = unit(cross(w, a));
+  vec3 u = cross(w, v);
+  return mat3(u, v, w);
+}
+
+vec3 random_cosine_direction(float r1, float r2)
+{
+  float phi = 2.0 * PI * r1;
+  return vec3(cos(phi) * sqrt(r2), sin(phi) * sqrt(r2), sqrt(1.0 - r2));
+}
+
+float cosine_pdf(vec3 normal, vec3 dir)
+{
+  return max(dot(normal, dir), 0.0) / PI;
+}
+
+// -1 from inside the sphere, so that the cone covers every direction
+float sphere_cos_theta_max(float radius, float distance_squared)
+{
+  if(distance_squared <= radius * radius) {
+    return -1.0;
+  }
+  return sqrt(max(1.0 - radius * radius / distance_squared, 0.0));
+}
+
+vec3 random_to_sphere(float radius, float distance_squared, float r1, float r2)
+{
+  float z = 1.0 + r2 * (sphere_cos_theta_max(radius, distance_squared) - 1.0);
+  float phi = 2.0 * PI * r1;
+  float radial = sqrt(max(1.0 - z * z, 0.0));
+  return vec3(cos(phi) * radial, sin(phi) * radial, z);
+}
+
+float sphere_pdf(float radius, float distance_squared)
+{
+  return 1.0 / (2.0 * PI * (1.0 - sphere_cos_theta_max(radius, distance_squared)));
+}
+
+float area_pdf(float area, float distance_squared, float cosine)
+{
+  return distance_squared / (abs(cosine) * area);
+}
+
+// Direction from origin towards a random point of the light
+vec3 random_to_light(uint light, vec3 origin, float time, float r1, float r2)
+{
+  if(light < config.num_spheres) {
+    Sphere sphere = scene.spheres[light];
+    vec3 to_center = sphere.center + time * sphere.velocity - origin;
+    return onb(to_center) * random_to_sphere(sphere.radius, length_squared(to_center), r1, r2);
+  }
+
+  Rect rect = rect_buffer.rects[light - config.num_spheres - config.num_triangles];
+  uint a = rect.axis == 0u ? 1u : 0u;
+  uint b = rect.axis == 2u ? 1u : 2u;
+  vec3 point;
+  point[a] = mix(rect.min.x, rect.max.x, r1);
+  point[b] = mix(rect.min.y, rect.max.y, r2);
+  point[rect.axis] = rect.k;
+  return point - origin;
+}
+
+// Density of random_to_light for the unit direction dir, averaged over the lights
+float light_pdf(vec3 origin, vec3 dir, float time)
+{
+  Ray ray;
+  ray.origin = origin;
+  ray.dir = dir;
+  ray.time = time;
+
+  float total = 0.0;
+  for(uint i = 0u; i < config.num_lights; i++)
+  {
+    uint light = light_buffer.primitives[i];
+    HitRecord hit_record;
+
+    if(light < config.num_spheres) {
+      Sphere sphere = scene.spheres[light];
+      if(hit_sphere(sphere, ray, 1.0 / 0.0, hit_record)) {
+        vec3 to_center = sphere.center + time * sphere.velocity - origin;
+        total += sphere_pdf(sphere.radius, length_squared(to_center));
+      }
+    } else {
+      Rect rect = rect_buffer.rects[light - config.num_spheres - config.num_triangles];
+      if(hit_rect(rect, ray, 1.0 / 0.0, hit_record)) {
+        vec2 size = rect.max - rect.min;
+        total += area_pdf(size.x * size.y, hit_record.t * hit_record.t, dir[rect.axis]);
+      }
+    }
+  }
+  return total / float(config.num_lights);
+}
+
+// Mirrors LightSampling::weight in pdf.rs
+float mis_weight(float scattering, float picked, float other)
+{
+  picked *= 0.5;
+  other *= 0.5;
+  float weight = config.light_sampling == LIGHT_SAMPLING_POWER ?
+    scattering * picked / (picked * picked + other * other) :
+    scattering / (picked + other);
+  return isinf(weight) || isnan(weight) ? 0.0 : weight;
+}
 
-      t_max = root;
-      hit_anything = true;
-      
-      hit_record.mat_type = sphere.mat_type;
-      hit_record.albedo = sphere.albedo;
-      hit_record.fuzz_or_ir = sphere.fuzz_or_ir;
+// Picks the direction from the cosine of the surface or towards a light, the mixture of the
+// third book
+ScatterResult scatter_lambertian_mis(Ray ray, HitRecord hit_record, Material material, vec3 u)
+{
+  vec3 normal = hit_record.normal;
+  vec3 origin = hit_record.point;
+
+  // The lower half of u.z picks a light, stretched over the lights
+  bool toward_light = config.num_lights > 0u && u.z < 0.5;
+  vec3 dir;
+  if(toward_light) {
+    uint light = light_buffer.primitives[min(uint(2.0 * u.z * float(config.num_lights)), config.num_lights - 1u)];
+    dir = unit(random_to_light(light, origin, ray.time, u.x, u.y));
+  } else {
+    dir = unit(onb(normal) * random_cosine_direction(u.x, u.y));
+  }
 
-      hit_record.t = root;
-      hit_record.point = ray.origin + root * ray.dir;
+  float scattering = cosine_pdf(normal, dir);
+  float weight = 1.0;
+  if(config.num_lights > 0u) {
+    float light = light_pdf(origin, dir, ray.time);
+    weight = toward_light ? mis_weight(scattering, light, scattering) : mis_weight(scattering, scattering, light);
+  }
 
-      vec3 outward_normal = (hit_record.point - sphere.center) / sphere.radius;
-      hit_record.front_face = dot(ray.dir, outward_normal) < 0.0;
-      hit_record.normal = hit_record.front_face ? outward_normal : -outward_normal;
+  ScatterResult result;
+  result.scattered = scattering > 0.0 && weight > 0.0;
+  result.attenuation = material.albedo * weight;
+  result.ray.origin = origin;
+  result.ray.dir = dir;
+  result.ray.time = ray.time;
+  return result;
+}
+
+vec3 ProcessRay(Ray ray)
+{
+  vec3 out_color = vec3(0.0);
+  vec3 attenuation = vec3(1.0);
+
+  for(uint b = 0;; b++)
+  {
+    if(b + 1 >= config.max_bounces) {
+      return out_color;
     }
 
+    HitRecord hit_record;
+    bool hit_anything = hit_scene(ray, hit_record);
+
     if(hit_anything)
     {
+      if(config.fog_density > 0.0) {
+        float transmittance = exp(-config.fog_density * hit_record.t * length(ray.dir));
+        out_color += attenuation * (1.0 - transmittance) * config.fog_color;
+        attenuation *= transmittance;
+      }
+
       ScatterResult scatter;
+      Material material = material_buffer.materials[hit_record.material];
 
-      switch(hit_record.mat_type)
+      // Textures multiply the constant they're paired with
+      if(material.texture != NO_TEXTURE) {
+        vec3 color = texture_value(material.texture, hit_record.uv, hit_record.point);
+        material.albedo *= color;
+        material.emission *= color;
+      }
+      if(material.fuzz_texture != NO_TEXTURE) {
+        material.fuzz *= dot(LUMINANCE, texture_value(material.fuzz_texture, hit_record.uv, hit_record.point));
+      }
+      out_color += attenuation * material.emission;
+
+      vec3 u = sample_bounce(b);
+
+      switch(material.kind)
       {
-        case 0:
+        case MATERIAL_LAMBERTIAN:
+        {
+          scatter = config.light_sampling == LIGHT_SAMPLING_OFF ?
+            scatter_lambertian(ray, hit_record, material, u) :
+            scatter_lambertian_mis(ray, hit_record, material, u);
+          break;
+        }
+        case MATERIAL_METAL:
         {
-          scatter = scatter_lambertian(ray, hit_record, vec3(x + b, y + z, hit_record.t));
+          scatter = scatter_metal(ray, hit_record, material, u);
           break;
         }
-        case 1:
+        case MATERIAL_DIELECTRIC:
         {
-          scatter = scatter_metal(ray, hit_record, vec3(x + b, y + z, hit_record.t));
+          scatter = scatter_dielectric(ray, hit_record, material, u);
           break;
         }
-        case 2:
+        case MATERIAL_ISOTROPIC:
         {
-          scatter = scatter_dielectric(ray, hit_record, vec3(x + b, y + z, hit_record.t));
+          scatter = scatter_isotropic(ray, hit_record, material, u);
+          break;
+        }
+        // Lights only emit
+        case MATERIAL_DIFFUSE_LIGHT:
+        default:
+        {
+          scatter.scattered = false;
           break;
         }
       }
@@ -305,17 +1360,23 @@ vec3 ProcessRay(Ray ray, uint x, uint y, uint z)
       if(scatter.scattered)
       {
         ray = scatter.ray;
-        out_color *=  scatter.attenuation;
+        attenuation *=  scatter.attenuation;
       }
       else
       {
-        return vec3(0.0, 0.0, 0.0);
+        return out_color;
       }
     }
     else
     {
+      // The background is infinitely far, behind as much fog as there can be
+      if(config.fog_density > 0.0) {
+        out_color += attenuation * config.fog_color;
+        break;
+      }
+
       float unit_y =  unit(ray.dir).y;
-      out_color *= mix(vec3(1.0, 1.0, 1.0), vec3(0.5, 0.7, 0.9), 0.5 * (unit_y + 1.0));
+      out_color += attenuation * mix(config.background_bottom, config.background_top, 0.5 * (unit_y + 1.0));
       break;
     }
   }
@@ -330,14 +1391,20 @@ void main()
   uint idy = index / config.width;
 
   Camera camera = config.camera;
-  vec3 color = vec3(0.0);
+  vec4 sum = push_constants.sample_offset == 0 ? vec4(0.0) : accumulation.pixels[index];
 
-  for(uint i = 0; i < config.sample_count; i++)
+  for(uint s = 0; s < push_constants.sample_count; s++)
   {
-    float u = float(idx) / (config.width - 1.0);
-    float v = float(idy) / (config.height - 1.0);
+    uint i = push_constants.sample_offset + s;
 
-    vec3 rd = camera.lens_radius * randomDiskPoint(vec3(u, v, i));
+    begin_sample(idx, idy, i);
+
+    // Anywhere within the pixel, centered on the corners of the image for the outer ones
+    vec2 pixel = sample_2d(PIXEL_DIMENSION);
+    float u = (float(idx) + pixel.x - 0.5) / (config.width - 1.0);
+    float v = (float(idy) + pixel.y - 0.5) / (config.height - 1.0);
+
+    vec3 rd = camera.lens_radius * square_to_disk(sample_2d(LENS_DIMENSION));
     vec3 offset = (camera.u * rd.x) + (camera.v * rd.y);
 
     Ray ray;
@@ -347,18 +1414,19 @@ void main()
       + (v * camera.vertical) 
       - (camera.origin)
       - (offset);
+    ray.time = mix(camera.time0, camera.time1, sample_1d(TIME_DIMENSION));
 
-    color += ProcessRay(ray, idx, idy, i);
-    
+    vec3 sample_color = ProcessRay(ray);
+    float luminance = dot(sample_color, LUMINANCE);
+    sum += vec4(sample_color, luminance * luminance);
   }
-  float scale = 1.0 / config.sample_count;
-  color.x = 256.0 * (clamp(sqrt(color.x * scale), 0.0, 0.999));
-  color.y = 256.0 * (clamp(sqrt(color.y * scale), 0.0, 0.999));
-  color.z = 256.0 * (clamp(sqrt(color.z * scale), 0.0, 0.999));
-  
-  data.colors[(index * 3) + 0] = uint(color.x);
-  data.colors[(index * 3) + 1] = uint(color.y);
-  data.colors[(index * 3) + 2] = uint(color.z);
+  accumulation.pixels[index] = sum;
+
+  vec3 color = sum.xyz / float(push_constants.sample_offset + push_constants.sample_count);
+
+  data.colors[(index * 3) + 0] = color.x;
+  data.colors[(index * 3) + 1] = color.y;
+  data.colors[(index * 3) + 2] = color.z;
 }
 
 
@@ -0,0 +1,813 @@
+use crate::bvh::Bvh;
+use crate::instance::Instance;
+use crate::material::Material;
+use crate::mesh::Triangle;
+use crate::pdf::{self, LightSampling, Onb};
+use crate::progressive::{self, luminance, Pass, PassRenderer, ProgressiveOptions};
+use crate::ray::Ray;
+use crate::raytracer::{Config, Sphere};
+use crate::rect::Rect;
+use crate::rng::Pcg;
+use crate::sampler::{self, PixelSample, Sampler, LENS_DIMENSION, PIXEL_DIMENSION, TIME_DIMENSION};
+use crate::scene::Scene;
+use crate::texture::TextureId;
+use crate::vec3::Vec3;
+use crate::volume::Volume;
+use pbr::ProgressBar;
+use std::convert::Infallible;
+use std::f32::consts::PI;
+use std::io::Stdout;
+use std::ops::{ControlFlow, Range};
+use std::sync::Mutex;
+use std::thread;
+
+/// Multi-threaded CPU port of `compute.glsl`.
+///
+/// Takes the same inputs and produces the same pixel layout as `Raytracer`, so it can stand in
+/// for it when there's no Vulkan device and serve as the ground truth for the GPU output.
+pub struct CpuRaytracer {
+    config: Config,
+    scene: Scene,
+    bvh: Bvh,
+    /// `Scene::lights`
+    lights: Vec<u32>,
+    /// `sampler::blue_noise` when rendering with `Sampler::BlueNoise`
+    blue_noise: Vec<f32>,
+    num_threads: usize,
+
+    accumulation: Vec<[f32; 4]>,
+    pixels: Vec<f32>,
+    progress_bar: Mutex<ProgressBar<Stdout>>,
+}
+
+struct HitRecord<'a> {
+    normal: Vec3,
+    point: Vec3,
+    t: f32,
+    /// Texture coordinates
+    uv: [f32; 2],
+
+    front_face: bool,
+
+    material: &'a Material,
+}
+
+struct ScatterResult {
+    attenuation: Vec3,
+    ray: Ray,
+}
+
+fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
+    let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
+    let r0 = r0 * r0;
+    r0 + (1.0 - r0) * f32::powf(1.0 - cosine, 5.0)
+}
+
+fn scatter_lambertian(
+    ray: &Ray,
+    hit_record: &HitRecord,
+    albedo: Vec3,
+    u: [f32; 3],
+) -> Option<ScatterResult> {
+    let mut dir = hit_record.normal + sampler::square_to_sphere([u[0], u[1]]);
+
+    if dir.is_near_zero() {
+        dir = hit_record.normal;
+    }
+
+    Some(ScatterResult {
+        attenuation: albedo,
+        ray: Ray {
+            origin: hit_record.point,
+            dir,
+            time: ray.time,
+        },
+    })
+}
+
+fn scatter_isotropic(
+    ray: &Ray,
+    hit_record: &HitRecord,
+    albedo: Vec3,
+    u: [f32; 3],
+) -> Option<ScatterResult> {
+    Some(ScatterResult {
+        attenuation: albedo,
+        ray: Ray {
+            origin: hit_record.point,
+            dir: sampler::square_to_sphere([u[0], u[1]]),
+            time: ray.time,
+        },
+    })
+}
+
+fn scatter_metal(
+    ray: &Ray,
+    hit_record: &HitRecord,
+    albedo: Vec3,
+    fuzz: f32,
+    u: [f32; 3],
+) -> Option<ScatterResult> {
+    // Uniform in the ball of radius fuzz
+    let offset = u[2].cbrt() * sampler::square_to_sphere([u[0], u[1]]);
+    let dir = ray.dir.unit().reflect(&hit_record.normal) + fuzz * offset;
+
+    if dir.dot(&hit_record.normal) <= 0.0 {
+        return None;
+    }
+
+    Some(ScatterResult {
+        attenuation: albedo,
+        ray: Ray {
+            origin: hit_record.point,
+            dir,
+            time: ray.time,
+        },
+    })
+}
+
+fn scatter_dielectric(
+    ray: &Ray,
+    hit_record: &HitRecord,
+    ior: f32,
+    u: [f32; 3],
+) -> Option<ScatterResult> {
+    let unit_dir = ray.dir.unit();
+
+    let refraction_ratio = if hit_record.front_face {
+        1.0 / ior
+    } else {
+        ior
+    };
+
+    let cos_theta = f32::min((-unit_dir).dot(&hit_record.normal), 1.0);
+    let sin_theta = f32::sqrt(1.0 - (cos_theta * cos_theta));
+
+    let dir =
+        if refraction_ratio * sin_theta > 1.0 || reflectance(cos_theta, refraction_ratio) > u[2] {
+            unit_dir.reflect(&hit_record.normal)
+        } else {
+            unit_dir.refract(&hit_record.normal, refraction_ratio)
+        };
+
+    Some(ScatterResult {
+        attenuation: Vec3::ONE,
+        ray: Ray {
+            origin: hit_record.point,
+            dir,
+            time: ray.time,
+        },
+    })
+}
+
+impl CpuRaytracer {
+    pub fn new(config: Config, scene: Scene) -> CpuRaytracer {
+        let num_threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+        println!("Using device: CPU ({} threads)", num_threads);
+        println!(
+            "Dimensions: [{} x {}] -> {}",
+            config.width,
+            config.height,
+            config.width as u64 * config.height as u64
+        );
+        println!("Sample count: {}", config.sample_count);
+        println!("Max bounces: {}", config.max_bounces);
+
+        let bvh = scene.build_bvh();
+        println!("{}", bvh.stats());
+
+        CpuRaytracer {
+            config,
+            bvh,
+            lights: scene.lights(),
+            blue_noise: match Sampler::from_config(config.sampler_mode) {
+                Sampler::BlueNoise => sampler::blue_noise(),
+                _ => Vec::new(),
+            },
+            scene,
+            num_threads,
+
+            accumulation: vec![[0.0; 4]; config.width as usize * config.height as usize],
+            pixels: vec![0.0; config.width as usize * config.height as usize * 3],
+            progress_bar: Mutex::new(progressive::progress_bar(0)),
+        }
+    }
+
+    /// Renders every pixel with `Config::sample_count` samples in a single pass, the layout
+    /// matches `Raytracer::raytrace`: linear RGB, starting from the bottom row
+    pub fn raytrace(&mut self) -> Vec<f32> {
+        let options = ProgressiveOptions {
+            samples_per_pass: self.config.sample_count,
+            ..Default::default()
+        };
+
+        self.raytrace_progressive(&options, |_| ControlFlow::Continue(()))
+    }
+
+    /// Counterpart of `Raytracer::raytrace_progressive`
+    pub fn raytrace_progressive<F>(&mut self, options: &ProgressiveOptions, on_pass: F) -> Vec<f32>
+    where
+        F: FnMut(&Pass) -> ControlFlow<()>,
+    {
+        let sample_count = self.config.sample_count;
+        match progressive::render(self, sample_count, options, on_pass) {
+            Ok(pixels) => pixels,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Sum of the samples `samples` of the pixel, each one drawing from its own `PixelSample`
+    fn render_pixel(&self, x: u32, y: u32, samples: Range<u32>) -> [f32; 4] {
+        let config = &self.config;
+        let camera = &config.camera;
+        let mut sum = [0.0; 4];
+
+        for i in samples {
+            let mut sample = PixelSample::new(config, &self.blue_noise, x, y, i);
+            // Anywhere within the pixel, centered on the corners of the image for the outer ones
+            let [jx, jy] = sample.get_2d(PIXEL_DIMENSION);
+            let u = (x as f32 + jx - 0.5) / (config.width as f32 - 1.0);
+            let v = (y as f32 + jy - 0.5) / (config.height as f32 - 1.0);
+
+            let rd = camera.lens_radius * sampler::square_to_disk(sample.get_2d(LENS_DIMENSION));
+            let offset = (camera.u * rd.x) + (camera.v * rd.y);
+
+            let ray = Ray {
+                origin: camera.origin + offset,
+                dir: camera.lower_left_corner + (u * camera.horizontal) + (v * camera.vertical)
+                    - camera.origin
+                    - offset,
+                time: camera.time0 + (camera.time1 - camera.time0) * sample.get_1d(TIME_DIMENSION),
+            };
+
+            let color = self.process_ray(ray, &mut sample);
+            let luminance = progressive::luminance([color.x, color.y, color.z]);
+
+            sum[0] += color.x;
+            sum[1] += color.y;
+            sum[2] += color.z;
+            sum[3] += luminance * luminance;
+        }
+
+        sum
+    }
+
+    /// `rng` samples the distances within volumes
+    fn hit(&self, ray: &Ray, rng: &mut Pcg) -> Option<HitRecord<'_>> {
+        self.hit_tree(0, ray, f32::INFINITY, rng)
+    }
+
+    /// Closest hit in the tree of the BVH starting at `root`
+    fn hit_tree(&self, root: u32, ray: &Ray, t_max: f32, rng: &mut Pcg) -> Option<HitRecord<'_>> {
+        let mut closest: Option<HitRecord> = None;
+
+        self.bvh.traverse_from(root, ray, 0.001, t_max, |i, t_max| {
+            let primitive = self.bvh.order()[i] as usize;
+            let hit_record = self.hit_primitive(primitive, ray, t_max, rng)?;
+            let t = hit_record.t;
+            closest = Some(hit_record);
+            Some(t)
+        });
+
+        closest
+    }
+
+    /// `primitive` is numbered as in `Scene::build_bvh`
+    fn hit_primitive(
+        &self,
+        primitive: usize,
+        ray: &Ray,
+        t_max: f32,
+        rng: &mut Pcg,
+    ) -> Option<HitRecord<'_>> {
+        let spheres = self.scene.spheres();
+        let triangles = self.scene.triangles();
+        let rects = self.scene.rects();
+        let instances = self.scene.instances();
+        let instances_start = spheres.len() + triangles.len() + rects.len();
+
+        if primitive < spheres.len() {
+            self.hit_sphere(&spheres[primitive], ray, t_max)
+        } else if primitive < spheres.len() + triangles.len() {
+            self.hit_triangle(&triangles[primitive - spheres.len()], ray, t_max)
+        } else if primitive < instances_start {
+            let rect = primitive - spheres.len() - triangles.len();
+            self.hit_rect(&rects[rect], ray, t_max)
+        } else if primitive < instances_start + instances.len() {
+            self.hit_instance(&instances[primitive - instances_start], ray, t_max, rng)
+        } else {
+            let volume = primitive - instances_start - instances.len();
+            self.hit_volume(&self.scene.volumes()[volume], ray, t_max, rng)
+        }
+    }
+
+    /// Mirrors `hit_instance` in `compute.glsl`
+    fn hit_instance(
+        &self,
+        instance: &Instance,
+        ray: &Ray,
+        t_max: f32,
+        rng: &mut Pcg,
+    ) -> Option<HitRecord<'_>> {
+        // The direction isn't normalized so that distances along both rays are the same
+        let to_object = instance.inverse();
+        let object_ray = Ray {
+            origin: to_object.point(ray.origin),
+            dir: to_object.vector(ray.dir),
+            time: ray.time,
+        };
+
+        let root = self.bvh.roots()[instance.object().index() + 1];
+        let hit_record = self.hit_tree(root, &object_ray, t_max, rng)?;
+
+        Some(HitRecord {
+            normal: instance.transform().normal(hit_record.normal).unit(),
+            point: ray.origin + hit_record.t * ray.dir,
+            ..hit_record
+        })
+    }
+
+    /// Mirrors `hit_volume` in `compute.glsl`
+    fn hit_volume(
+        &self,
+        volume: &Volume,
+        ray: &Ray,
+        t_max: f32,
+        rng: &mut Pcg,
+    ) -> Option<HitRecord<'_>> {
+        let root = self.bvh.roots()[volume.object().index() + 1];
+
+        // Where the ray enters and leaves the boundary, from its origin when it starts inside
+        let boundary = self.hit_tree(root, ray, f32::INFINITY, rng)?;
+        let (t_enter, t_exit) = if boundary.front_face {
+            let inside = Ray {
+                origin: boundary.point,
+                dir: ray.dir,
+                time: ray.time,
+            };
+            let exit = self.hit_tree(root, &inside, f32::INFINITY, rng)?;
+            (boundary.t, boundary.t + exit.t)
+        } else {
+            (0.0, boundary.t)
+        };
+        let t_exit = t_exit.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        // The distance to the next collision with a particle of the medium
+        let distance = -(1.0 - rng.next_f32()).ln() / volume.density();
+        let t = t_enter + distance / ray.dir.length();
+        if t >= t_exit {
+            return None;
+        }
+
+        // Isotropic materials ignore the normal
+        Some(HitRecord {
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            point: ray.origin + t * ray.dir,
+            t,
+            uv: [0.0, 0.0],
+
+            front_face: true,
+
+            material: self.scene.material(volume.material()),
+        })
+    }
+
+    fn hit_sphere(&self, sphere: &Sphere, ray: &Ray, t_max: f32) -> Option<HitRecord<'_>> {
+        let center = sphere.center(ray.time);
+        let oc = ray.origin - center;
+        let a = ray.dir.length_squared();
+        let half_b = oc.dot(&ray.dir);
+        let c = oc.length_squared() - (sphere.radius * sphere.radius);
+
+        let discriminant = (half_b * half_b) - (a * c);
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = f32::sqrt(discriminant);
+        let mut root = (-half_b - sqrtd) / a;
+
+        if root < 0.001 || root > t_max {
+            root = (-half_b + sqrtd) / a;
+            if root < 0.001 || root > t_max {
+                return None;
+            }
+        }
+
+        let point = ray.origin + root * ray.dir;
+        let outward_normal = (point - center) / sphere.radius;
+        let front_face = ray.dir.dot(&outward_normal) < 0.0;
+
+        // Longitude from -x around through +z, latitude from -y
+        let theta = (-outward_normal.y).clamp(-1.0, 1.0).acos();
+        let phi = f32::atan2(-outward_normal.z, outward_normal.x) + PI;
+
+        Some(HitRecord {
+            normal: if front_face {
+                outward_normal
+            } else {
+                -outward_normal
+            },
+            point,
+            t: root,
+            uv: [phi / (2.0 * PI), theta / PI],
+
+            front_face,
+
+            material: &self.scene.materials()[sphere.material as usize],
+        })
+    }
+
+    /// Moller-Trumbore, mirrors `hit_triangle` in `compute.glsl`
+    fn hit_triangle(&self, triangle: &Triangle, ray: &Ray, t_max: f32) -> Option<HitRecord<'_>> {
+        let vertices = self.scene.vertices();
+        let [a, b, c] = triangle.vertices.map(|i| &vertices[i as usize]);
+
+        let edge1 = b.position - a.position;
+        let edge2 = c.position - a.position;
+        let p = ray.dir.cross(&edge2);
+        let det = edge1.dot(&p);
+
+        // The ray is parallel to the triangle
+        if det.abs() < 1e-8 {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let s = ray.origin - a.position;
+        let u = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(&edge1);
+        let v = ray.dir.dot(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&q) * inv_det;
+        if t < 0.001 || t > t_max {
+            return None;
+        }
+
+        let outward_normal = edge1.cross(&edge2).unit();
+        let front_face = ray.dir.dot(&outward_normal) < 0.0;
+
+        // Interpolated normals can lean past the silhouette, keep them on the side of the face
+        let mut normal = (1.0 - u - v) * a.normal + u * b.normal + v * c.normal;
+        normal = if normal.is_near_zero() {
+            outward_normal
+        } else {
+            normal.unit()
+        };
+        if normal.dot(&outward_normal) < 0.0 {
+            normal = -normal;
+        }
+
+        Some(HitRecord {
+            normal: if front_face { normal } else { -normal },
+            point: ray.origin + t * ray.dir,
+            t,
+            uv: [
+                (1.0 - u - v) * a.u + u * b.u + v * c.u,
+                (1.0 - u - v) * a.v + u * b.v + v * c.v,
+            ],
+
+            front_face,
+
+            material: &self.scene.materials()[triangle.material as usize],
+        })
+    }
+
+    /// Mirrors `hit_rect` in `compute.glsl`
+    fn hit_rect(&self, rect: &Rect, ray: &Ray, t_max: f32) -> Option<HitRecord<'_>> {
+        let [a, b, axis] = rect.plane().axes();
+
+        // Also rejects rays parallel to the rectangle, for which t isn't a number
+        let t = (rect.k - ray.origin[axis]) / ray.dir[axis];
+        if !(0.001..=t_max).contains(&t) {
+            return None;
+        }
+
+        let point = ray.origin + t * ray.dir;
+        let uv = [
+            (point[a] - rect.min[0]) / (rect.max[0] - rect.min[0]),
+            (point[b] - rect.min[1]) / (rect.max[1] - rect.min[1]),
+        ];
+        if !uv.iter().all(|c| (0.0..=1.0).contains(c)) {
+            return None;
+        }
+
+        let outward_normal = rect.normal();
+        let front_face = ray.dir.dot(&outward_normal) < 0.0;
+
+        Some(HitRecord {
+            normal: if front_face {
+                outward_normal
+            } else {
+                -outward_normal
+            },
+            point,
+            t,
+            uv,
+
+            front_face,
+
+            material: &self.scene.materials()[rect.material as usize],
+        })
+    }
+
+    /// Mirrors `scatter_lambertian_mis` in `compute.glsl`: picks the direction from the cosine
+    /// of the surface or towards a light, and weights it by `sampling`
+    fn scatter_lambertian_mis(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        albedo: Vec3,
+        sampling: LightSampling,
+        u: [f32; 3],
+    ) -> Option<ScatterResult> {
+        let normal = hit_record.normal;
+        let origin = hit_record.point;
+
+        // The lower half of u[2] picks a light, stretched over the lights
+        let toward_light = !self.lights.is_empty() && u[2] < 0.5;
+        let dir = if toward_light {
+            let count = self.lights.len();
+            let light = self.lights[((2.0 * u[2] * count as f32) as usize).min(count - 1)];
+            self.random_to_light(light, origin, ray.time, u[0], u[1])
+        } else {
+            Onb::new(normal).local(pdf::random_cosine_direction(u[0], u[1]))
+        }
+        .unit();
+
+        let scattering = pdf::cosine_pdf(normal, dir);
+        let weight = if self.lights.is_empty() {
+            1.0
+        } else {
+            let light = self.light_pdf(origin, dir, ray.time);
+            if toward_light {
+                sampling.weight(scattering, light, scattering)
+            } else {
+                sampling.weight(scattering, scattering, light)
+            }
+        };
+        if scattering <= 0.0 || weight <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterResult {
+            attenuation: albedo * weight,
+            ray: Ray {
+                origin,
+                dir,
+                time: ray.time,
+            },
+        })
+    }
+
+    /// Direction from `origin` towards a random point of the light, `light` is numbered like in
+    /// `Scene::lights`
+    fn random_to_light(&self, light: u32, origin: Vec3, time: f32, r1: f32, r2: f32) -> Vec3 {
+        let spheres = self.scene.spheres();
+        let light = light as usize;
+
+        if light < spheres.len() {
+            let sphere = &spheres[light];
+            let to_center = sphere.center(time) - origin;
+            let dir = pdf::random_to_sphere(sphere.radius, to_center.length_squared(), r1, r2);
+            Onb::new(to_center).local(dir)
+        } else {
+            let rect = &self.scene.rects()[light - spheres.len() - self.scene.triangles().len()];
+            let [a, b, axis] = rect.plane().axes();
+            let mut point = [0.0; 3];
+            point[a] = rect.min[0] + r1 * (rect.max[0] - rect.min[0]);
+            point[b] = rect.min[1] + r2 * (rect.max[1] - rect.min[1]);
+            point[axis] = rect.k;
+            Vec3::from(point) - origin
+        }
+    }
+
+    /// Density of `random_to_light` for the unit direction `dir`, averaged over the lights
+    fn light_pdf(&self, origin: Vec3, dir: Vec3, time: f32) -> f32 {
+        let spheres = self.scene.spheres();
+        let ray = Ray { origin, dir, time };
+
+        let total: f32 = self
+            .lights
+            .iter()
+            .map(|&light| {
+                let light = light as usize;
+                if light < spheres.len() {
+                    let sphere = &spheres[light];
+                    match self.hit_sphere(sphere, &ray, f32::INFINITY) {
+                        Some(_) => {
+                            let distance_squared = (sphere.center(time) - origin).length_squared();
+                            pdf::sphere_pdf(sphere.radius, distance_squared)
+                        }
+                        None => 0.0,
+                    }
+                } else {
+                    let rect =
+                        &self.scene.rects()[light - spheres.len() - self.scene.triangles().len()];
+                    match self.hit_rect(rect, &ray, f32::INFINITY) {
+                        Some(hit_record) => {
+                            let area = (rect.max[0] - rect.min[0]) * (rect.max[1] - rect.min[1]);
+                            let cosine = dir[rect.plane().axes()[2]];
+                            pdf::area_pdf(area, hit_record.t * hit_record.t, cosine)
+                        }
+                        None => 0.0,
+                    }
+                }
+            })
+            .sum();
+
+        total / self.lights.len() as f32
+    }
+
+    fn process_ray(&self, mut ray: Ray, sample: &mut PixelSample) -> Vec3 {
+        let mut out_color = Vec3::ZERO;
+        let mut attenuation = Vec3::ONE;
+
+        for b in 0.. {
+            if b + 1 >= self.config.max_bounces {
+                return out_color;
+            }
+
+            match self.hit(&ray, sample.rng()) {
+                Some(hit_record) => {
+                    if self.config.fog_density > 0.0 {
+                        let distance = hit_record.t * ray.dir.length();
+                        let transmittance = (-self.config.fog_density * distance).exp();
+                        out_color += attenuation * (1.0 - transmittance) * self.config.fog_color;
+                        attenuation *= transmittance;
+                    }
+
+                    // Textures multiply the constant they're paired with
+                    let textured = |value: Vec3, texture: Option<TextureId>| match texture {
+                        Some(texture) => {
+                            value
+                                * self.scene.texture(texture).value(
+                                    hit_record.uv,
+                                    hit_record.point,
+                                    self.scene.textures(),
+                                )
+                        }
+                        None => value,
+                    };
+
+                    let u = sample.get_bounce(b);
+                    let scatter = match *hit_record.material {
+                        Material::Lambertian { albedo, texture } => {
+                            let albedo = textured(albedo, texture);
+                            match LightSampling::from_config(self.config.light_sampling) {
+                                LightSampling::Off => {
+                                    scatter_lambertian(&ray, &hit_record, albedo, u)
+                                }
+                                sampling => self.scatter_lambertian_mis(
+                                    &ray,
+                                    &hit_record,
+                                    albedo,
+                                    sampling,
+                                    u,
+                                ),
+                            }
+                        }
+                        Material::Metal {
+                            albedo,
+                            fuzz,
+                            texture,
+                            fuzz_texture,
+                        } => {
+                            let roughness = textured(Vec3::ONE, fuzz_texture);
+                            let fuzz = fuzz * luminance([roughness.x, roughness.y, roughness.z]);
+                            scatter_metal(&ray, &hit_record, textured(albedo, texture), fuzz, u)
+                        }
+                        Material::Dielectric { ior } => {
+                            scatter_dielectric(&ray, &hit_record, ior, u)
+                        }
+                        Material::Isotropic { albedo, texture } => {
+                            scatter_isotropic(&ray, &hit_record, textured(albedo, texture), u)
+                        }
+                        Material::DiffuseLight { texture, .. } => {
+                            let emission = textured(hit_record.material.emission(), texture);
+                            out_color += attenuation * emission;
+                            None
+                        }
+                    };
+
+                    match scatter {
+                        Some(scatter) => {
+                            ray = scatter.ray;
+                            attenuation = attenuation * scatter.attenuation;
+                        }
+                        None => return out_color,
+                    }
+                }
+                None => {
+                    // The background is infinitely far, behind as much fog as there can be
+                    if self.config.fog_density > 0.0 {
+                        out_color += attenuation * self.config.fog_color;
+                        break;
+                    }
+
+                    let unit_y = ray.dir.unit().y;
+                    let t = 0.5 * (unit_y + 1.0);
+                    let background =
+                        (1.0 - t) * self.config.background_bottom + t * self.config.background_top;
+                    out_color += attenuation * background;
+                    break;
+                }
+            }
+        }
+
+        out_color
+    }
+}
+
+impl PassRenderer for CpuRaytracer {
+    type Error = Infallible;
+
+    fn begin(&mut self, target_samples: u32) {
+        let total_pixels = self.config.width as u64 * self.config.height as u64;
+        self.progress_bar = Mutex::new(progressive::progress_bar(
+            total_pixels * target_samples as u64,
+        ));
+    }
+
+    fn render_pass(&mut self, sample_offset: u32, sample_count: u32) -> Result<(), Infallible> {
+        let width = self.config.width as usize;
+
+        // Hand the rows out to the threads, each one owning its slice of both buffers
+        let mut accumulation = std::mem::take(&mut self.accumulation);
+        let mut pixels = std::mem::take(&mut self.pixels);
+        let rows = Mutex::new(
+            accumulation
+                .chunks_mut(width)
+                .zip(pixels.chunks_mut(width * 3))
+                .enumerate(),
+        );
+
+        let this = &*self;
+        thread::scope(|scope| {
+            for _ in 0..this.num_threads {
+                scope.spawn(|| loop {
+                    let next = rows.lock().unwrap().next();
+                    let (y, (sums, row)) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+
+                    for (x, (sum, pixel)) in
+                        sums.iter_mut().zip(row.chunks_exact_mut(3)).enumerate()
+                    {
+                        let samples = this.render_pixel(
+                            x as u32,
+                            y as u32,
+                            sample_offset..sample_offset + sample_count,
+                        );
+                        if sample_offset == 0 {
+                            *sum = samples;
+                        } else {
+                            for c in 0..4 {
+                                sum[c] += samples[c];
+                            }
+                        }
+
+                        let scale = 1.0 / (sample_offset + sample_count) as f32;
+                        pixel.copy_from_slice(&[sum[0] * scale, sum[1] * scale, sum[2] * scale]);
+                    }
+
+                    this.progress_bar
+                        .lock()
+                        .unwrap()
+                        .add(width as u64 * sample_count as u64);
+                });
+            }
+        });
+
+        self.accumulation = accumulation;
+        self.pixels = pixels;
+        Ok(())
+    }
+
+    fn pixels(&self) -> Result<Vec<f32>, Infallible> {
+        Ok(self.pixels.clone())
+    }
+
+    fn accumulation(&self) -> Result<Vec<[f32; 4]>, Infallible> {
+        Ok(self.accumulation.clone())
+    }
+
+    fn finish(&mut self) {
+        self.progress_bar
+            .get_mut()
+            .unwrap()
+            .finish_println("Finished\n");
+    }
+}
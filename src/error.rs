@@ -0,0 +1,289 @@
+use std::fmt;
+use std::time::Duration;
+
+use vulkano::{
+    buffer::cpu_access::ReadLockError,
+    command_buffer::{
+        BuildError, CommandBufferBeginError, CommandBufferExecError, PipelineExecutionError,
+    },
+    descriptor_set::DescriptorSetCreationError,
+    device::DeviceCreationError,
+    instance::InstanceCreationError,
+    memory::allocator::AllocationCreationError,
+    pipeline::compute::ComputePipelineCreationError,
+    shader::ShaderCreationError,
+    sync::FlushError,
+    LoadingError, OomError, VulkanError,
+};
+
+/// Everything that can go wrong while setting up or running the GPU raytracer
+#[derive(Debug)]
+pub enum RaytracerError {
+    /// The Vulkan library (loader) couldn't be found or loaded
+    LibraryLoad(LoadingError),
+    InstanceCreation(InstanceCreationError),
+    DeviceEnumeration(VulkanError),
+
+    /// None of the devices support compute and the required extensions
+    NoComputeDevice,
+    /// The image has more pixels than the shader can index
+    ImageTooLarge {
+        width: u32,
+        height: u32,
+    },
+    /// The requested device doesn't exist or isn't able to run the raytracer
+    UnsuitableDevice {
+        index: usize,
+        device_count: usize,
+    },
+    DeviceCreation(DeviceCreationError),
+
+    OutOfMemory(OomError),
+    BufferAllocation(AllocationCreationError),
+
+    ShaderCreation(ShaderCreationError),
+    PipelineCreation(ComputePipelineCreationError),
+    DescriptorSetCreation(DescriptorSetCreationError),
+
+    CommandBufferBegin(CommandBufferBeginError),
+    CommandBufferBuild(BuildError),
+    Dispatch(PipelineExecutionError),
+    Execution(CommandBufferExecError),
+    Flush(FlushError),
+
+    /// The connection to the device was lost mid-render (driver crash, GPU reset, ...)
+    DeviceLost,
+    /// A dispatch didn't finish within the allowed time
+    Timeout(Duration),
+
+    Readback(ReadLockError),
+}
+
+impl RaytracerError {
+    /// Whether the error means Vulkan is unusable on this machine, as opposed to a failure of a
+    /// device that was found
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            RaytracerError::LibraryLoad(_)
+                | RaytracerError::InstanceCreation(_)
+                | RaytracerError::DeviceEnumeration(_)
+                | RaytracerError::NoComputeDevice
+        )
+    }
+}
+
+impl fmt::Display for RaytracerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RaytracerError::LibraryLoad(why) => {
+                write!(f, "Failed to load the Vulkan library: {}", why)
+            }
+            RaytracerError::InstanceCreation(why) => {
+                write!(f, "Failed to create the Vulkan instance: {}", why)
+            }
+            RaytracerError::DeviceEnumeration(why) => {
+                write!(f, "Failed to enumerate the Vulkan devices: {}", why)
+            }
+            RaytracerError::NoComputeDevice => {
+                write!(f, "No Vulkan device with compute support was found")
+            }
+            RaytracerError::ImageTooLarge { width, height } => write!(
+                f,
+                "A {}x{} image is too large to render on the GPU",
+                width, height
+            ),
+            RaytracerError::UnsuitableDevice {
+                index,
+                device_count,
+            } => {
+                if index >= device_count {
+                    write!(
+                        f,
+                        "There is no device {}, only {} device(s) were found",
+                        index, device_count
+                    )
+                } else {
+                    write!(f, "Device {} doesn't support compute", index)
+                }
+            }
+            RaytracerError::DeviceCreation(why) => {
+                write!(f, "Failed to initialize the device: {}", why)
+            }
+            RaytracerError::OutOfMemory(why) => write!(f, "Out of memory: {}", why),
+            RaytracerError::BufferAllocation(why) => {
+                write!(f, "Failed to allocate a buffer: {}", why)
+            }
+            RaytracerError::ShaderCreation(why) => {
+                write!(f, "Failed to load the compute shader: {}", why)
+            }
+            RaytracerError::PipelineCreation(why) => {
+                write!(f, "Failed to create the compute pipeline: {}", why)
+            }
+            RaytracerError::DescriptorSetCreation(why) => {
+                write!(f, "Failed to bind the buffers: {}", why)
+            }
+            RaytracerError::CommandBufferBegin(why) => {
+                write!(f, "Failed to begin a command buffer: {}", why)
+            }
+            RaytracerError::CommandBufferBuild(why) => {
+                write!(f, "Failed to build a command buffer: {}", why)
+            }
+            RaytracerError::Dispatch(why) => write!(f, "Failed to record a dispatch: {}", why),
+            RaytracerError::Execution(why) => {
+                write!(f, "Failed to submit a command buffer: {}", why)
+            }
+            RaytracerError::Flush(why) => write!(f, "Failed to flush the queue: {}", why),
+            RaytracerError::DeviceLost => write!(f, "The connection to the device was lost"),
+            RaytracerError::Timeout(timeout) => write!(
+                f,
+                "A dispatch took longer than {:.1}s to finish",
+                timeout.as_secs_f32()
+            ),
+            RaytracerError::Readback(why) => {
+                write!(f, "Failed to read the rendered image back: {}", why)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RaytracerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RaytracerError::LibraryLoad(why) => Some(why),
+            RaytracerError::InstanceCreation(why) => Some(why),
+            RaytracerError::DeviceEnumeration(why) => Some(why),
+            RaytracerError::DeviceCreation(why) => Some(why),
+            RaytracerError::OutOfMemory(why) => Some(why),
+            RaytracerError::BufferAllocation(why) => Some(why),
+            RaytracerError::ShaderCreation(why) => Some(why),
+            RaytracerError::PipelineCreation(why) => Some(why),
+            RaytracerError::DescriptorSetCreation(why) => Some(why),
+            RaytracerError::CommandBufferBegin(why) => Some(why),
+            RaytracerError::CommandBufferBuild(why) => Some(why),
+            RaytracerError::Dispatch(why) => Some(why),
+            RaytracerError::Execution(why) => Some(why),
+            RaytracerError::Flush(why) => Some(why),
+            RaytracerError::Readback(why) => Some(why),
+            RaytracerError::NoComputeDevice
+            | RaytracerError::ImageTooLarge { .. }
+            | RaytracerError::UnsuitableDevice { .. }
+            | RaytracerError::DeviceLost
+            | RaytracerError::Timeout(_) => None,
+        }
+    }
+}
+
+impl From<LoadingError> for RaytracerError {
+    fn from(why: LoadingError) -> Self {
+        RaytracerError::LibraryLoad(why)
+    }
+}
+
+impl From<InstanceCreationError> for RaytracerError {
+    fn from(why: InstanceCreationError) -> Self {
+        RaytracerError::InstanceCreation(why)
+    }
+}
+
+impl From<DeviceCreationError> for RaytracerError {
+    fn from(why: DeviceCreationError) -> Self {
+        match why {
+            DeviceCreationError::DeviceLost => RaytracerError::DeviceLost,
+            DeviceCreationError::OutOfHostMemory => {
+                RaytracerError::OutOfMemory(OomError::OutOfHostMemory)
+            }
+            DeviceCreationError::OutOfDeviceMemory => {
+                RaytracerError::OutOfMemory(OomError::OutOfDeviceMemory)
+            }
+            why => RaytracerError::DeviceCreation(why),
+        }
+    }
+}
+
+impl From<AllocationCreationError> for RaytracerError {
+    fn from(why: AllocationCreationError) -> Self {
+        match why {
+            AllocationCreationError::VulkanError(VulkanError::OutOfHostMemory) => {
+                RaytracerError::OutOfMemory(OomError::OutOfHostMemory)
+            }
+            AllocationCreationError::VulkanError(VulkanError::OutOfDeviceMemory) => {
+                RaytracerError::OutOfMemory(OomError::OutOfDeviceMemory)
+            }
+            why => RaytracerError::BufferAllocation(why),
+        }
+    }
+}
+
+impl From<ShaderCreationError> for RaytracerError {
+    fn from(why: ShaderCreationError) -> Self {
+        match why {
+            ShaderCreationError::OomError(why) => RaytracerError::OutOfMemory(why),
+            why => RaytracerError::ShaderCreation(why),
+        }
+    }
+}
+
+impl From<ComputePipelineCreationError> for RaytracerError {
+    fn from(why: ComputePipelineCreationError) -> Self {
+        match why {
+            ComputePipelineCreationError::OomError(why) => RaytracerError::OutOfMemory(why),
+            why => RaytracerError::PipelineCreation(why),
+        }
+    }
+}
+
+impl From<DescriptorSetCreationError> for RaytracerError {
+    fn from(why: DescriptorSetCreationError) -> Self {
+        match why {
+            DescriptorSetCreationError::OomError(why) => RaytracerError::OutOfMemory(why),
+            why => RaytracerError::DescriptorSetCreation(why),
+        }
+    }
+}
+
+impl From<CommandBufferBeginError> for RaytracerError {
+    fn from(why: CommandBufferBeginError) -> Self {
+        match why {
+            CommandBufferBeginError::OomError(why) => RaytracerError::OutOfMemory(why),
+            why => RaytracerError::CommandBufferBegin(why),
+        }
+    }
+}
+
+impl From<BuildError> for RaytracerError {
+    fn from(why: BuildError) -> Self {
+        match why {
+            BuildError::OomError(why) => RaytracerError::OutOfMemory(why),
+            why => RaytracerError::CommandBufferBuild(why),
+        }
+    }
+}
+
+impl From<PipelineExecutionError> for RaytracerError {
+    fn from(why: PipelineExecutionError) -> Self {
+        RaytracerError::Dispatch(why)
+    }
+}
+
+impl From<CommandBufferExecError> for RaytracerError {
+    fn from(why: CommandBufferExecError) -> Self {
+        RaytracerError::Execution(why)
+    }
+}
+
+impl From<FlushError> for RaytracerError {
+    fn from(why: FlushError) -> Self {
+        match why {
+            FlushError::DeviceLost => RaytracerError::DeviceLost,
+            FlushError::OomError(why) => RaytracerError::OutOfMemory(why),
+            why => RaytracerError::Flush(why),
+        }
+    }
+}
+
+impl From<ReadLockError> for RaytracerError {
+    fn from(why: ReadLockError) -> Self {
+        RaytracerError::Readback(why)
+    }
+}
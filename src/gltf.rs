@@ -0,0 +1,491 @@
+//! glTF 2.0 import (`.gltf` and `.glb`), with the metallic-roughness materials mapped onto
+//! `Material` and `Texture` and the node hierarchy flattened into world space meshes.
+
+use crate::bvh::Aabb;
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::texture::{self, ColorSpace, Image, Texture, WrapMode};
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+use ::gltf::camera::Projection;
+use ::gltf::image::{Data, Format};
+use ::gltf::material::AlphaMode;
+use ::gltf::mesh::Mode;
+use ::gltf::texture::WrappingMode;
+use ::gltf::{Document, Node};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The content of the default scene of a glTF file, as returned by `load`
+pub struct GltfModel {
+    /// One mesh per triangle primitive of every node, in world space
+    pub meshes: Vec<GltfMesh>,
+    /// Materials of the file, by index, referencing `textures` by index
+    pub materials: Vec<Material<usize>>,
+    /// The textures the materials use, see `to_material`
+    pub textures: Vec<Texture>,
+    /// What the raytracer can't represent, the model is still usable
+    pub warnings: Vec<GltfWarning>,
+}
+
+pub struct GltfMesh {
+    /// Index into `GltfModel::materials`, `None` for primitives without a material
+    pub material: Option<usize>,
+    pub mesh: Mesh,
+}
+
+/// A perspective camera of the file, in world space
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GltfCamera {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub vup: Vec3,
+    /// Vertical field of view in degrees
+    pub vfov: f32,
+    /// Width over height, `None` when the file leaves it to the viewport
+    pub aspect_ratio: Option<f32>,
+}
+
+#[derive(Debug)]
+pub struct GltfError {
+    pub path: PathBuf,
+    pub source: ::gltf::Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfWarning {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+/// Extensions mapped onto `Material`, the others are ignored with a warning
+const SUPPORTED_EXTENSIONS: [&str; 3] = [
+    "KHR_materials_emissive_strength",
+    "KHR_materials_ior",
+    "KHR_materials_transmission",
+];
+
+/// Vertical field of view of the camera framing files without one
+const DEFAULT_VFOV: f32 = 40.0;
+
+/// Loads a glTF file with its buffers and images, whether they're embedded (data URIs or the
+/// binary chunk of a `.glb`) or separate files next to it.
+///
+/// Only the triangles of the default scene (or of the first one when there's no default) are
+/// imported, with the transforms of their nodes applied.
+pub fn load(path: &Path) -> Result<GltfModel, GltfError> {
+    let (document, buffers, images) = ::gltf::import(path).map_err(|source| GltfError {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let mut warnings = Warnings::new(path);
+    for extension in document.extensions_used() {
+        if !SUPPORTED_EXTENSIONS.contains(&extension) {
+            warnings.push(format!("extension '{}' ignored", extension));
+        }
+    }
+
+    if document.animations().next().is_some() {
+        warnings.push("animations ignored");
+    }
+
+    let mut textures = Textures {
+        images: &images,
+        textures: Vec::new(),
+        indices: HashMap::new(),
+    };
+    let materials = document
+        .materials()
+        .map(|material| to_material(&material, &mut textures, &mut warnings))
+        .collect();
+
+    let mut meshes = Vec::new();
+    for (node, transform) in nodes(&document, &mut warnings) {
+        if node.skin().is_some() || node.weights().is_some() {
+            warnings.push("skins and morph targets ignored, meshes are imported at rest");
+        }
+        if let Some(camera) = node.camera() {
+            if let Projection::Orthographic(_) = camera.projection() {
+                warnings.push("orthographic cameras ignored");
+            }
+        }
+
+        let primitives = node.mesh().into_iter().flat_map(|mesh| mesh.primitives());
+        for primitive in primitives {
+            if primitive.mode() != Mode::Triangles {
+                warnings.push(format!(
+                    "primitives drawn as {:?} ignored, only triangles are supported",
+                    primitive.mode()
+                ));
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<Vec3> = match reader.read_positions() {
+                Some(positions) => positions.map(Vec3::from).collect(),
+                None => {
+                    warnings.push("primitives without positions ignored");
+                    continue;
+                }
+            };
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            let mut mesh = Mesh {
+                positions,
+                normals: reader
+                    .read_normals()
+                    .map(|normals| normals.map(Vec3::from).collect())
+                    .unwrap_or_default(),
+                // glTF puts the origin of the texture coordinates at the top left corner
+                uvs: reader
+                    .read_tex_coords(0)
+                    .map(|uvs| uvs.into_f32().map(|[u, v]| [u, 1.0 - v]).collect())
+                    .unwrap_or_default(),
+                indices: indices
+                    .chunks_exact(3)
+                    .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+                    .collect(),
+            };
+            mesh.transform(&transform);
+
+            meshes.push(GltfMesh {
+                material: primitive.material().index(),
+                mesh,
+            });
+        }
+    }
+
+    Ok(GltfModel {
+        meshes,
+        materials,
+        textures: textures.textures,
+        warnings: warnings.finish(),
+    })
+}
+
+/// Reads the first perspective camera of the default scene, without loading the buffers. Files
+/// without one get a camera looking down -z at the whole scene.
+pub fn load_camera(path: &Path) -> Result<GltfCamera, GltfError> {
+    let gltf = ::gltf::Gltf::open(path).map_err(|source| GltfError {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let nodes = nodes(&gltf.document, &mut Warnings::new(path));
+
+    for (node, transform) in nodes.iter() {
+        let camera = match node.camera() {
+            Some(camera) => camera,
+            None => continue,
+        };
+        if let Projection::Perspective(perspective) = camera.projection() {
+            // glTF cameras look down their -z axis, with y up
+            let look_from = transform.point(Vec3::ZERO);
+            return Ok(GltfCamera {
+                look_from,
+                look_at: look_from + transform.vector(Vec3::new(0.0, 0.0, -1.0)).unit(),
+                vup: transform.vector(Vec3::new(0.0, 1.0, 0.0)).unit(),
+                vfov: perspective.yfov().to_degrees(),
+                aspect_ratio: perspective.aspect_ratio(),
+            });
+        }
+    }
+
+    // The accessors of the positions hold their bounds
+    let mut bounds = Aabb::EMPTY;
+    for (node, transform) in nodes.iter() {
+        for primitive in node.mesh().into_iter().flat_map(|mesh| mesh.primitives()) {
+            let [min, max] = {
+                let bounds = primitive.bounding_box();
+                [Vec3::from(bounds.min), Vec3::from(bounds.max)]
+            };
+            for corner in 0..8 {
+                let pick = |bit: usize, min: f32, max: f32| match corner & bit {
+                    0 => min,
+                    _ => max,
+                };
+                bounds = bounds.grow(&transform.point(Vec3::new(
+                    pick(1, min.x, max.x),
+                    pick(2, min.y, max.y),
+                    pick(4, min.z, max.z),
+                )));
+            }
+        }
+    }
+
+    let (center, radius) = match bounds.is_empty() {
+        true => (Vec3::ZERO, 1.0),
+        false => (bounds.centroid(), (bounds.max - bounds.min).length() / 2.0),
+    };
+    let distance = radius / (DEFAULT_VFOV / 2.0).to_radians().sin();
+
+    Ok(GltfCamera {
+        look_from: center + Vec3::new(0.0, 0.0, distance),
+        look_at: center,
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: DEFAULT_VFOV,
+        aspect_ratio: None,
+    })
+}
+
+/// The nodes of the default scene with their world transforms, parents before their children
+fn nodes<'a>(document: &'a Document, warnings: &mut Warnings) -> Vec<(Node<'a>, Transform)> {
+    fn visit<'a>(node: Node<'a>, parent: &Transform, nodes: &mut Vec<(Node<'a>, Transform)>) {
+        let transform = Transform::from_columns(node.transform().matrix()).then(parent);
+        nodes.push((node.clone(), transform));
+        for child in node.children() {
+            visit(child, &transform, nodes);
+        }
+    }
+
+    let scene = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene,
+        None => {
+            warnings.push("the file has no scene, nothing to import");
+            return Vec::new();
+        }
+    };
+
+    let mut nodes = Vec::new();
+    for node in scene.nodes() {
+        visit(node, &Transform::IDENTITY, &mut nodes);
+    }
+    nodes
+}
+
+/// Emissive materials become lights, transmissive (or blended and mostly transparent) ones glass
+/// and mostly metallic ones metal, blurred by their roughness. Everything else is diffuse with
+/// the base color.
+///
+/// The base color and emissive textures tint the albedo and the light, the roughness of the
+/// metallic-roughness texture scales the fuzz of metals. Normal and occlusion maps are ignored.
+fn to_material(
+    material: &::gltf::Material,
+    textures: &mut Textures,
+    warnings: &mut Warnings,
+) -> Material<usize> {
+    let clamp = |color: Vec3| {
+        Vec3::new(
+            color.x.clamp(0.0, 1.0),
+            color.y.clamp(0.0, 1.0),
+            color.z.clamp(0.0, 1.0),
+        )
+    };
+
+    let pbr = material.pbr_metallic_roughness();
+    if material.normal_texture().is_some() || material.occlusion_texture().is_some() {
+        warnings.push("normal and occlusion textures ignored");
+    }
+    let mut texture = |info: Option<::gltf::texture::Info>, channel| {
+        let info = info?;
+        textures.add(info.texture(), info.tex_coord(), channel, warnings)
+    };
+
+    let [r, g, b, alpha] = pbr.base_color_factor();
+    let base_color = clamp(Vec3::new(r, g, b));
+    let emissive =
+        Vec3::from(material.emissive_factor()) * material.emissive_strength().unwrap_or(1.0);
+    let emission = emissive.x.max(emissive.y).max(emissive.z);
+    let transmission = material
+        .transmission()
+        .map_or(0.0, |transmission| transmission.transmission_factor());
+    let transparent = material.alpha_mode() == AlphaMode::Blend && alpha < 0.5;
+
+    if emission > 0.0 {
+        Material::DiffuseLight {
+            color: clamp(emissive / emission),
+            intensity: emission,
+            texture: texture(material.emissive_texture(), Channel::Color),
+        }
+    } else if transmission >= 0.5 || transparent {
+        Material::Dielectric {
+            ior: material.ior().filter(|ior| *ior > 0.0).unwrap_or(1.5),
+        }
+    } else if pbr.metallic_factor() >= 0.5 {
+        Material::Metal {
+            albedo: base_color,
+            fuzz: pbr.roughness_factor().clamp(0.0, 1.0),
+            texture: texture(pbr.base_color_texture(), Channel::Color),
+            fuzz_texture: texture(pbr.metallic_roughness_texture(), Channel::Roughness),
+        }
+    } else {
+        Material::Lambertian {
+            albedo: base_color,
+            texture: texture(pbr.base_color_texture(), Channel::Color),
+        }
+    }
+}
+
+/// What a material reads from a texture of the file
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Channel {
+    /// The colors, sRGB encoded
+    Color,
+    /// The roughness, in the green channel of metallic-roughness textures
+    Roughness,
+}
+
+/// Turns the textures of a file into `Texture::Image`s as the materials use them, once per channel
+struct Textures<'a> {
+    /// Decoded by `::gltf::import`, by index
+    images: &'a [Data],
+    textures: Vec<Texture>,
+    /// Index into `textures` by texture of the file
+    indices: HashMap<(usize, Channel), usize>,
+}
+
+impl Textures<'_> {
+    /// Index of `texture` in `textures`, `None` when it can't be used
+    fn add(
+        &mut self,
+        texture: ::gltf::texture::Texture,
+        tex_coord: u32,
+        channel: Channel,
+        warnings: &mut Warnings,
+    ) -> Option<usize> {
+        if tex_coord != 0 {
+            warnings.push("textures using a second set of texture coordinates ignored");
+            return None;
+        }
+        if let Some(&index) = self.indices.get(&(texture.index(), channel)) {
+            return Some(index);
+        }
+
+        let image = match to_image(&self.images[texture.source().index()], channel) {
+            Ok(image) => image,
+            Err(why) => {
+                warnings.push(format!("texture {} ignored: {}", texture.index(), why));
+                return None;
+            }
+        };
+
+        let sampler = texture.sampler();
+        if sampler.wrap_s() != sampler.wrap_t() {
+            warnings.push("textures wrapping differently along u and v wrap like along u");
+        }
+
+        self.textures.push(Texture::Image {
+            image: Arc::new(image),
+            wrap: match sampler.wrap_s() {
+                WrappingMode::Repeat => WrapMode::Repeat,
+                WrappingMode::MirroredRepeat => WrapMode::Mirror,
+                WrappingMode::ClampToEdge => WrapMode::Clamp,
+            },
+            color_space: match channel {
+                Channel::Color => ColorSpace::Srgb,
+                Channel::Roughness => ColorSpace::Linear,
+            },
+        });
+        let index = self.textures.len() - 1;
+        self.indices.insert((texture.index(), channel), index);
+        Some(index)
+    }
+}
+
+/// The image in 8 bits per channel, the roughness of `Channel::Roughness` in gray
+fn to_image(data: &Data, channel: Channel) -> Result<Image, texture::TextureError> {
+    let (channels, bytes) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    // Keeps the most significant byte of 16-bit channels, clamps float ones
+    let samples: Vec<u8> = data
+        .pixels
+        .chunks_exact(bytes)
+        .map(|sample| match *sample {
+            [c] => c,
+            [low, high] => u16::from_ne_bytes([low, high]).to_be_bytes()[0],
+            [a, b, c, d] => {
+                (f32::from_ne_bytes([a, b, c, d]).clamp(0.0, 1.0) * 255.0).round() as u8
+            }
+            _ => unreachable!("channels are 1, 2 or 4 bytes"),
+        })
+        .collect();
+
+    let texels = samples
+        .chunks_exact(channels)
+        .map(|texel| match channel {
+            Channel::Color => texture::rgba(texel),
+            Channel::Roughness => {
+                let roughness = texel.get(1).copied().unwrap_or(texel[0]);
+                [roughness, roughness, roughness, 255]
+            }
+        })
+        .collect();
+
+    Image::new(data.width, data.height, texels)
+}
+
+/// Collects the warnings of a file, reporting each message once
+struct Warnings {
+    path: PathBuf,
+    messages: Vec<String>,
+}
+
+impl Warnings {
+    fn new(path: &Path) -> Warnings {
+        Warnings {
+            path: path.to_path_buf(),
+            messages: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        if !self.messages.contains(&message) {
+            self.messages.push(message);
+        }
+    }
+
+    fn finish(self) -> Vec<GltfWarning> {
+        let path = self.path;
+        self.messages
+            .into_iter()
+            .map(|message| GltfWarning {
+                path: path.clone(),
+                message,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: failed to load glTF model: {}",
+            self.path.display(),
+            self.source
+        )
+    }
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl fmt::Display for GltfWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
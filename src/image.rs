@@ -0,0 +1,273 @@
+use crate::tonemap::ToneMapping;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// 8 bits per channel PNG
+    Png,
+    /// 16 bits per channel PNG
+    Png16,
+    /// Binary PPM (P6)
+    Ppm,
+    /// ASCII PPM (P3)
+    PpmAscii,
+    /// OpenEXR with 32-bit float channels, keeps the linear radiance
+    Exr,
+    /// Radiance RGBE, keeps the linear radiance
+    Hdr,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    UnknownFormat {
+        path: PathBuf,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Png {
+        path: PathBuf,
+        source: png::EncodingError,
+    },
+    Exr {
+        path: PathBuf,
+        source: exr::error::Error,
+    },
+}
+
+impl ImageFormat {
+    pub const NAMES: [&'static str; 6] = ["png", "png16", "ppm", "ppm-ascii", "exr", "hdr"];
+
+    /// Picks the format from the file extension, `.png` maps to 8-bit PNG and `.ppm` to binary PPM
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" | "pnm" => Some(ImageFormat::Ppm),
+            "exr" => Some(ImageFormat::Exr),
+            "hdr" => Some(ImageFormat::Hdr),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" | "png8" => Ok(ImageFormat::Png),
+            "png16" => Ok(ImageFormat::Png16),
+            "ppm" | "p6" => Ok(ImageFormat::Ppm),
+            "ppm-ascii" | "p3" => Ok(ImageFormat::PpmAscii),
+            "exr" => Ok(ImageFormat::Exr),
+            "hdr" | "rgbe" => Ok(ImageFormat::Hdr),
+            _ => Err(format!(
+                "unknown image format '{}', expected one of: {}",
+                s,
+                ImageFormat::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::UnknownFormat { path } => write!(
+                f,
+                "{}: can't pick an image format from the extension, use one of: {}",
+                path.display(),
+                ImageFormat::NAMES.join(", ")
+            ),
+            ImageError::Io { path, source } => {
+                write!(f, "Failed to write {}: {}", path.display(), source)
+            }
+            ImageError::Png { path, source } => {
+                write!(f, "Failed to encode {}: {}", path.display(), source)
+            }
+            ImageError::Exr { path, source } => {
+                write!(f, "Failed to encode {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::UnknownFormat { .. } => None,
+            ImageError::Io { source, .. } => Some(source),
+            ImageError::Png { source, .. } => Some(source),
+            ImageError::Exr { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Writes the linear RGB pixels returned by `Raytracer::raytrace`.
+///
+/// EXR and HDR files keep the linear values, the other formats are mapped to display colors by
+/// `tone_mapping`. The raytracer fills rows bottom-up, they get flipped so the file reads
+/// top-down.
+pub fn write(
+    path: &Path,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    pixels: &[f32],
+    tone_mapping: &ToneMapping,
+) -> Result<(), ImageError> {
+    assert_eq!(
+        pixels.len(),
+        width as usize * height as usize * 3,
+        "pixel buffer doesn't match the image dimensions"
+    );
+
+    let io_error = |source| ImageError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    // The exr crate creates the file itself
+    if format == ImageFormat::Exr {
+        return write_exr(path, width, height, pixels).map_err(|source| ImageError::Exr {
+            path: path.to_path_buf(),
+            source,
+        });
+    }
+
+    let file = File::create(path).map_err(io_error)?;
+    let mut writer = BufWriter::new(file);
+
+    match format {
+        ImageFormat::Png | ImageFormat::Png16 => {
+            write_png(&mut writer, format, width, height, pixels, tone_mapping).map_err(
+                |source| ImageError::Png {
+                    path: path.to_path_buf(),
+                    source,
+                },
+            )?
+        }
+        ImageFormat::Ppm => {
+            let bytes = tone_mapping.quantize(pixels);
+            write_ppm(&mut writer, width, height, &bytes).map_err(io_error)?
+        }
+        ImageFormat::PpmAscii => {
+            let bytes = tone_mapping.quantize(pixels);
+            write_ppm_ascii(&mut writer, width, height, &bytes).map_err(io_error)?
+        }
+        ImageFormat::Hdr => write_hdr(&mut writer, width, height, pixels).map_err(io_error)?,
+        ImageFormat::Exr => unreachable!("EXR files are written above"),
+    };
+
+    writer.flush().map_err(io_error)
+}
+
+fn rows_top_down<T>(pixels: &[T], width: u32) -> impl Iterator<Item = &[T]> {
+    pixels.chunks_exact(width as usize * 3).rev()
+}
+
+fn write_png<W: Write>(
+    writer: W,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    pixels: &[f32],
+    tone_mapping: &ToneMapping,
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+
+    let data: Vec<u8> = if format == ImageFormat::Png16 {
+        encoder.set_depth(png::BitDepth::Sixteen);
+        let channels = tone_mapping.quantize_u16(pixels);
+        rows_top_down(&channels, width)
+            .flatten()
+            .flat_map(|channel| channel.to_be_bytes())
+            .collect()
+    } else {
+        encoder.set_depth(png::BitDepth::Eight);
+        let channels = tone_mapping.quantize(pixels);
+        rows_top_down(&channels, width).flatten().copied().collect()
+    };
+
+    let mut png_writer = encoder.write_header()?;
+    png_writer.write_image_data(&data)?;
+    png_writer.finish()
+}
+
+fn write_ppm<W: Write>(mut writer: W, width: u32, height: u32, bytes: &[u8]) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+
+    for row in rows_top_down(bytes, width) {
+        writer.write_all(row)?;
+    }
+
+    Ok(())
+}
+
+fn write_ppm_ascii<W: Write>(
+    mut writer: W,
+    width: u32,
+    height: u32,
+    bytes: &[u8],
+) -> io::Result<()> {
+    write!(writer, "P3\n{} {}\n255\n", width, height)?;
+
+    for row in rows_top_down(bytes, width) {
+        for pixel in row.chunks_exact(3) {
+            write!(writer, "{} {} {} ", pixel[0], pixel[1], pixel[2])?;
+        }
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+fn write_exr(path: &Path, width: u32, height: u32, pixels: &[f32]) -> exr::error::Result<()> {
+    let (width, height) = (width as usize, height as usize);
+
+    exr::prelude::write_rgb_file(path, width, height, |x, y| {
+        let i = ((height - 1 - y) * width + x) * 3;
+        (pixels[i], pixels[i + 1], pixels[i + 2])
+    })
+}
+
+/// Flat (not run-length encoded) RGBE scanlines, which every Radiance reader accepts
+fn write_hdr<W: Write>(mut writer: W, width: u32, height: u32, pixels: &[f32]) -> io::Result<()> {
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+
+    for row in rows_top_down(pixels, width) {
+        let bytes: Vec<u8> = row.chunks_exact(3).flat_map(to_rgbe).collect();
+        writer.write_all(&bytes)?;
+    }
+
+    Ok(())
+}
+
+/// Shared exponent encoding: the largest channel's exponent plus a byte of mantissa per channel
+fn to_rgbe(pixel: &[f32]) -> [u8; 4] {
+    let (r, g, b) = (pixel[0].max(0.0), pixel[1].max(0.0), pixel[2].max(0.0));
+    let max = r.max(g).max(b);
+
+    if !max.is_finite() || max < 1e-32 {
+        return [0; 4];
+    }
+
+    // max = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let exponent = (max.log2().floor() as i32 + 1).clamp(-127, 127);
+    let scale = 256.0 / f32::powi(2.0, exponent);
+
+    let channel = |c: f32| (c * scale).min(255.0) as u8;
+    [channel(r), channel(g), channel(b), (exponent + 128) as u8]
+}
@@ -0,0 +1,115 @@
+use crate::transform::Transform;
+use bytemuck::{Pod, Zeroable};
+use std::fmt;
+use std::ops::Range;
+
+/// Returned by `Scene::add_object`, see `Scene::add_instance`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectId(pub(crate) u32);
+
+/// Primitives only placed in the scene through instances: the spheres, triangles and rectangles
+/// of `Scene` in these ranges, in object space
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Object {
+    pub spheres: Range<usize>,
+    pub triangles: Range<usize>,
+    pub rects: Range<usize>,
+}
+
+/// Copy of an object placed in the scene by an affine transform
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Instance {
+    object: ObjectId,
+    to_world: Transform,
+    to_object: Transform,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InstanceError {
+    /// The object isn't part of the scene
+    UnknownObject(ObjectId),
+    /// The object has no primitives, there would be nothing to place
+    EmptyObject(ObjectId),
+    /// The transform must be finite and invertible
+    InvalidTransform(Transform),
+}
+
+/// Instance as laid out in `compute.glsl` (std430)
+#[derive(Pod, Zeroable, Copy, Clone, Default)]
+#[repr(C)]
+pub(crate) struct GpuInstance {
+    to_object: [[f32; 4]; 4],
+    /// Node of the BVH the hierarchy of the object starts at
+    root: u32,
+    _0: u32,
+    _1: u32,
+    _2: u32,
+}
+
+impl ObjectId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Object {
+    pub fn is_empty(&self) -> bool {
+        self.spheres.is_empty() && self.triangles.is_empty() && self.rects.is_empty()
+    }
+}
+
+impl Instance {
+    /// `None` when the transform isn't finite or can't be inverted
+    pub fn new(object: ObjectId, transform: Transform) -> Option<Instance> {
+        if !transform.is_finite() {
+            return None;
+        }
+
+        Some(Instance {
+            object,
+            to_world: transform,
+            to_object: transform.inverse().filter(Transform::is_finite)?,
+        })
+    }
+
+    pub fn object(&self) -> ObjectId {
+        self.object
+    }
+
+    /// From object to world space
+    pub fn transform(&self) -> &Transform {
+        &self.to_world
+    }
+
+    /// From world to object space
+    pub fn inverse(&self) -> &Transform {
+        &self.to_object
+    }
+
+    /// `root` is the node of the BVH the hierarchy of the object starts at
+    pub(crate) fn lower(&self, root: u32) -> GpuInstance {
+        GpuInstance {
+            to_object: self.to_object.columns(),
+            root,
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceError::UnknownObject(id) => {
+                write!(f, "object {} isn't part of the scene", id.index())
+            }
+            InstanceError::EmptyObject(id) => {
+                write!(f, "object {} has no primitives", id.index())
+            }
+            InstanceError::InvalidTransform(_) => {
+                write!(f, "the transform must be finite and invertible")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InstanceError {}
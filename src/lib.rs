@@ -0,0 +1,58 @@
+//! Hikari, a raytracer following Peter Shirley's raytracing books.
+//!
+//! Scenes are described by a [`SceneDescription`], either loaded from a file or built in code,
+//! and lowered into the [`Config`] and [`Scene`] consumed by the renderers. [`Raytracer`]
+//! renders on a Vulkan device, [`CpuRaytracer`] is its CPU counterpart. Both return the same
+//! pixel buffer of linear radiance which [`image::write`] saves to disk, tone mapped by
+//! [`ToneMapping`] unless the format keeps floats.
+
+pub mod bvh;
+pub mod camera;
+pub mod cpu;
+pub mod error;
+pub mod gltf;
+pub mod image;
+pub mod instance;
+pub mod material;
+pub mod mesh;
+pub mod obj;
+pub mod pdf;
+pub mod progressive;
+pub mod ray;
+pub mod raytracer;
+pub mod rect;
+pub mod rng;
+pub mod sampler;
+pub mod scene;
+pub mod texture;
+pub mod tonemap;
+pub mod transform;
+pub mod vec3;
+pub mod volume;
+
+pub use crate::bvh::{Aabb, Bvh, BvhStats};
+pub use crate::camera::{Camera, CameraError};
+pub use crate::cpu::CpuRaytracer;
+pub use crate::error::RaytracerError;
+pub use crate::image::{ImageError, ImageFormat};
+pub use crate::instance::{Instance, InstanceError, Object, ObjectId};
+pub use crate::material::{Material, MaterialError, MaterialId};
+pub use crate::mesh::{Mesh, MeshError, Triangle, Vertex};
+pub use crate::pdf::{LightSampling, Onb};
+pub use crate::progressive::{Pass, ProgressiveOptions};
+pub use crate::ray::Ray;
+pub use crate::raytracer::{Config, Raytracer, Sphere};
+pub use crate::rect::{Plane, Rect};
+pub use crate::rng::Pcg;
+pub use crate::sampler::{PixelSample, Sampler};
+pub use crate::scene::{
+    Background, BoxDescription, CameraDescription, GeometryError, InstanceDescription,
+    MeshDescription, ModelDescription, ModelWarning, ObjectDescription, RectDescription,
+    RenderSettings, Scene, SceneDescription, SceneError, SphereDescription, TextureDescription,
+    VolumeDescription,
+};
+pub use crate::texture::{ColorSpace, Image, Texture, TextureError, TextureId, WrapMode};
+pub use crate::tonemap::{ToneMapOperator, ToneMapping, TransferFunction};
+pub use crate::transform::Transform;
+pub use crate::vec3::Vec3;
+pub use crate::volume::{Fog, Volume, VolumeError};
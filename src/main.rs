@@ -1,157 +1,367 @@
-pub mod raytracer;
-pub mod vec3;
-
-use crate::raytracer::*;
-use crate::vec3::Vec3;
-use rand::distributions::{Distribution, Uniform};
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::Path;
-
-fn rand() -> f32 {
-    Uniform::from(0.0..1.0).sample(&mut rand::thread_rng())
+use clap::Parser;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use raytracer::image;
+use raytracer::{
+    CpuRaytracer, ImageError, ImageFormat, LightSampling, Pass, ProgressiveOptions, Raytracer,
+    RaytracerError, Sampler, SceneDescription, ToneMapOperator, TransferFunction, Vec3,
+};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Renders a scene with the Vulkan raytracer, flags override the values of the scene file
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Scene file (.ron, .json, .gltf or .glb), the random sphere grid of the first book is rendered if omitted
+    scene: Option<PathBuf>,
+
+    /// Built-in scene to render instead of a scene file
+    #[arg(long, value_parser = ["random", "cornell-box"], conflicts_with = "scene")]
+    preset: Option<String>,
+
+    /// Output image, the format is picked from the extension unless --format is given
+    #[arg(short, long, default_value = "output.png")]
+    output: PathBuf,
+
+    /// Output format: png, png16, ppm, ppm-ascii, exr or hdr
+    #[arg(short, long)]
+    format: Option<ImageFormat>,
+
+    /// Image width in pixels
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// Image height in pixels
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// Samples per pixel
+    #[arg(short, long)]
+    samples: Option<u32>,
+
+    /// Maximum number of bounces per ray
+    #[arg(short = 'b', long)]
+    max_bounces: Option<u32>,
+
+    /// How diffuse surfaces pick their next bounce: off, balance or power
+    #[arg(long)]
+    light_sampling: Option<LightSampling>,
+
+    /// How the samples of a pixel are spread: random, stratified, halton, sobol or blue-noise
+    #[arg(long)]
+    sampler: Option<Sampler>,
+
+    /// Render progressively, adding this many samples per pixel in each pass and writing the
+    /// output after every one
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pass_samples: Option<u32>,
+
+    /// Stop rendering progressively after this many seconds
+    #[arg(long, value_parser = parse_seconds)]
+    time_budget: Option<Duration>,
+
+    /// Stop rendering progressively once the estimated noise drops to this value
+    #[arg(long)]
+    noise_threshold: Option<f32>,
+
+    /// Exposure in stops applied before tone mapping
+    #[arg(long, allow_hyphen_values = true)]
+    exposure: Option<f32>,
+
+    /// Tone mapping operator: clamp, reinhard, extended-reinhard, aces or agx
+    #[arg(long)]
+    tone_map: Option<ToneMapOperator>,
+
+    /// Radiance mapped to white by the extended-reinhard operator
+    #[arg(long)]
+    white_point: Option<f32>,
+
+    /// Transfer function of the display: srgb, linear or a gamma such as 2.2
+    #[arg(long)]
+    transfer: Option<TransferFunction>,
+
+    /// Dither before quantizing to 8 or 16 bits
+    #[arg(long)]
+    dither: bool,
+
+    /// Camera position as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    look_from: Option<Vec3>,
+
+    /// Point the camera looks at as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    look_at: Option<Vec3>,
+
+    /// Camera up vector as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    vup: Option<Vec3>,
+
+    /// Vertical field of view in degrees
+    #[arg(long)]
+    vfov: Option<f32>,
+
+    /// Lens aperture, 0 disables depth of field
+    #[arg(long, allow_hyphen_values = true)]
+    aperture: Option<f32>,
+
+    /// Distance to the plane in focus
+    #[arg(long, conflicts_with = "auto_focus", allow_hyphen_values = true)]
+    focus_dist: Option<f32>,
+
+    /// Focus on the look-at point, ignoring the focus distance of the scene file
+    #[arg(long)]
+    auto_focus: bool,
+
+    /// Time the shutter opens, between 0 and 1
+    #[arg(long)]
+    time0: Option<f32>,
+
+    /// Time the shutter closes, between 0 and 1, moving spheres are blurred in between
+    #[arg(long)]
+    time1: Option<f32>,
+
+    /// Seed for the random sphere grid
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Seed of the random numbers of the samples, the same seed renders the same image
+    #[arg(long)]
+    render_seed: Option<u32>,
+
+    /// Index of the device to render on, see --list-devices
+    #[arg(short, long)]
+    device: Option<usize>,
+
+    /// Seconds a single GPU dispatch may take before giving up, 0 waits forever
+    #[arg(long, value_parser = parse_seconds, default_value = "60")]
+    timeout: Duration,
+
+    /// Render on the CPU, this is also the fallback when no Vulkan device is found
+    #[arg(long)]
+    cpu: bool,
+
+    /// Print the available devices and exit
+    #[arg(long)]
+    list_devices: bool,
 }
 
-fn main() {
-    // Image
-    let aspect_ratio: f32 = 3.0 / 2.0;
-    let image_width = (720.0 * aspect_ratio) as u32;
-    let image_height = (image_width as f32 / aspect_ratio) as u32;
-
-    let lookfrom = Vec3::new(13.0, 2.0, 3.0);
-    let lookat = Vec3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-
-    let vfov: f32 = 20.0;
-    let aperture: f32 = 0.1;
-
-    let theta = f32::to_radians(vfov);
-    let h = f32::tan(theta / 2.0);
-    let viewport_height = 2.0 * h;
-    let viewport_width = aspect_ratio * viewport_height;
-
-    let w = (lookfrom - lookat).unit();
-    let u = vup.cross(&w).unit();
-    let v = w.cross(&u);
-
-    let focus_dist = 10.0;
-
-    let horizontal = focus_dist * u * viewport_width;
-    let vertical = focus_dist * v * viewport_height;
-
-    let camera = Camera {
-        origin: lookfrom,
-        horizontal,
-        vertical,
-        lower_left_corner: lookfrom - (horizontal / 2.0) - (vertical / 2.0) - focus_dist * w,
-        up: vup,
-        u,
-        v,
-        w,
-        lens_radius: aperture / 2.0,
-        ..Default::default()
-    };
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let components = s
+        .split(',')
+        .map(|c| c.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|why| format!("'{}' is not a vector: {}", s, why))?;
 
-    let mut spheres = [
-        Sphere {
-            center: Vec3::new(0.0, -1000.0, -1.0),
-            radius: 1000.0,
-            mat_type: 0,
-            albedo: Vec3::new(0.5, 0.5, 0.5),
-            ..Default::default()
-        },
-        Sphere {
-            center: Vec3::new(0.0, 1.0, 0.0),
-            radius: 1.0,
-            mat_type: 2,
-            fuzz_or_ir: 1.5,
-            ..Default::default()
-        },
-        Sphere {
-            center: Vec3::new(-4.0, 1.0, 0.0),
-            radius: 1.0,
-            mat_type: 0,
-            albedo: Vec3::new(0.4, 0.2, 0.1),
-            ..Default::default()
-        },
-        Sphere {
-            center: Vec3::new(4.0, 1.0, 0.0),
-            radius: 1.0,
-            fuzz_or_ir: 0.0,
-            mat_type: 1,
-            albedo: Vec3::new(0.7, 0.6, 0.5),
-            ..Default::default()
-        },
-    ]
-    .to_vec();
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let mat = rand();
-            let center = Vec3::new(a as f32 + 0.9 * rand(), 0.2, b as f32 + 0.9 * rand());
-
-            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if mat < 0.8 {
-                    spheres.push(Sphere {
-                        center,
-                        radius: 0.2,
-                        mat_type: 0,
-                        albedo: Vec3::new(rand(), rand(), rand()),
-                        ..Default::default()
-                    })
-                } else if mat < 0.95 {
-                    spheres.push(Sphere {
-                        center,
-                        radius: 0.2,
-                        mat_type: 1,
-                        albedo: Vec3::new(
-                            0.5 + rand() / 2.0,
-                            0.5 + rand() / 2.0,
-                            0.5 + rand() / 2.0,
-                        ),
-                        fuzz_or_ir: rand() / 2.0,
-                        ..Default::default()
-                    })
-                } else {
-                    spheres.push(Sphere {
-                        center,
-                        radius: 0.2,
-                        mat_type: 2,
-                        fuzz_or_ir: 1.5,
-                        ..Default::default()
-                    })
-                }
-            }
+    match components[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("'{}' is not a vector, expected x,y,z", s)),
+    }
+}
+
+/// Seconds as a `Duration`, which can't be negative, NaN or overflow it
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds = s
+        .trim()
+        .parse::<f32>()
+        .map_err(|why| format!("'{}' is not a number: {}", s, why))?;
+
+    Duration::try_from_secs_f32(seconds)
+        .map_err(|_| format!("'{}' is out of range, expected 0 or more seconds", s))
+}
+
+fn exit_with(why: impl std::fmt::Display) -> ! {
+    eprintln!("{}", why);
+    std::process::exit(1);
+}
+
+/// What the user can do about a GPU error
+fn hint(why: &RaytracerError) -> Option<&'static str> {
+    match why {
+        RaytracerError::LibraryLoad(_) | RaytracerError::InstanceCreation(_) => {
+            Some("Make sure a Vulkan driver is installed, or render on the CPU with --cpu")
+        }
+        RaytracerError::NoComputeDevice => Some("Render on the CPU with --cpu"),
+        RaytracerError::ImageTooLarge { .. } => {
+            Some("Try a lower --width and --height, or render on the CPU with --cpu")
         }
+        RaytracerError::UnsuitableDevice { .. } => {
+            Some("See --list-devices for the available devices")
+        }
+        RaytracerError::OutOfMemory(_) | RaytracerError::BufferAllocation(_) => {
+            Some("Try a lower resolution or a smaller scene")
+        }
+        RaytracerError::DeviceLost => Some(
+            "The driver may have reset the GPU, try fewer samples or bounces, or a longer --timeout",
+        ),
+        RaytracerError::Timeout(_) => Some("Try a longer --timeout, or 0 to wait forever"),
+        _ => None,
     }
+}
+
+fn exit_with_gpu_error(why: RaytracerError) -> ! {
+    match hint(&why) {
+        Some(hint) => exit_with(format!("{}\n{}", why, hint)),
+        None => exit_with(why),
+    }
+}
+
+impl Args {
+    fn apply(&self, scene: &mut SceneDescription) {
+        let settings = &mut scene.settings;
+        let camera = &mut scene.camera;
+        let tone_mapping = &mut scene.tone_mapping;
+
+        settings.width = self.width.unwrap_or(settings.width);
+        settings.height = self.height.unwrap_or(settings.height);
+        settings.sample_count = self.samples.unwrap_or(settings.sample_count);
+        settings.max_bounces = self.max_bounces.unwrap_or(settings.max_bounces);
+        settings.light_sampling = self.light_sampling.unwrap_or(settings.light_sampling);
+        settings.seed = self.render_seed.unwrap_or(settings.seed);
+        settings.sampler = self.sampler.unwrap_or(settings.sampler);
 
-    let config = Config {
-        num_spheres: spheres.len() as u32,
-        sample_count: 32,
-        max_bounces: 4,
-        width: image_width,
-        height: image_height,
+        camera.look_from = self.look_from.unwrap_or(camera.look_from);
+        camera.look_at = self.look_at.unwrap_or(camera.look_at);
+        camera.vup = self.vup.unwrap_or(camera.vup);
+        camera.vfov = self.vfov.unwrap_or(camera.vfov);
+        camera.aperture = self.aperture.unwrap_or(camera.aperture);
+        if self.auto_focus {
+            camera.focus_dist = None;
+        }
+        camera.focus_dist = self.focus_dist.or(camera.focus_dist);
+        camera.time0 = self.time0.unwrap_or(camera.time0);
+        camera.time1 = self.time1.unwrap_or(camera.time1);
+
+        tone_mapping.exposure = self.exposure.unwrap_or(tone_mapping.exposure);
+        tone_mapping.operator = self.tone_map.unwrap_or(tone_mapping.operator);
+        tone_mapping.white_point = self.white_point.unwrap_or(tone_mapping.white_point);
+        tone_mapping.transfer = self.transfer.unwrap_or(tone_mapping.transfer);
+        tone_mapping.dither |= self.dither;
+    }
+
+    fn is_progressive(&self) -> bool {
+        self.pass_samples.is_some() || self.time_budget.is_some() || self.noise_threshold.is_some()
+    }
+
+    /// A single pass of `sample_count` samples unless rendering progressively
+    fn progressive_options(&self, sample_count: u32) -> ProgressiveOptions {
+        let defaults = ProgressiveOptions::default();
+
+        ProgressiveOptions {
+            samples_per_pass: match self.pass_samples {
+                Some(samples) => samples,
+                None if self.is_progressive() => defaults.samples_per_pass,
+                None => sample_count,
+            },
+            time_budget: self.time_budget,
+            noise_threshold: self.noise_threshold,
+            ..defaults
+        }
+    }
+}
 
-        camera,
-        ..Default::default()
+fn main() {
+    let args = Args::parse();
+
+    if args.list_devices {
+        let devices = Raytracer::list_devices().unwrap_or_else(|why| exit_with_gpu_error(why));
+        for (i, device) in devices.iter().enumerate() {
+            println!("{}: {}", i, device);
+        }
+        return;
+    }
+
+    let format = match args.format {
+        Some(format) => format,
+        None => ImageFormat::from_path(&args.output).unwrap_or_else(|| {
+            exit_with(ImageError::UnknownFormat {
+                path: args.output.clone(),
+            })
+        }),
     };
 
-    let mut raytracer = Raytracer::new(config, spheres);
-    let output = raytracer.raytrace();
+    let mut description = match (&args.scene, args.preset.as_deref()) {
+        (Some(path), _) => SceneDescription::load(path).unwrap_or_else(|why| exit_with(why)),
+        (None, Some("cornell-box")) => SceneDescription::cornell_box(),
+        (None, _) => {
+            let mut rng = match args.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+            SceneDescription::random(&mut rng)
+        }
+    };
 
-    let mut out_string: String = String::new();
-    out_string.push_str(&format!("P3\n{} {}\n255\n", image_width, image_height));
-    for i in output.iter() {
-        out_string.push_str(i.to_string().as_str());
-        out_string.push(' ');
+    // Validated once the flags are merged in, blaming the scene file only if it's wrong by itself
+    let file = description.clone();
+    args.apply(&mut description);
+    if let Err(why) = description.validate() {
+        exit_with(match &args.scene {
+            Some(path) if file.validate().is_err() => why.with_path(path),
+            _ => why,
+        });
+    }
+    let (config, scene, warnings) = description
+        .build_with_warnings()
+        .unwrap_or_else(|why| exit_with(why));
+    for warning in warnings.iter() {
+        eprintln!("warning: {}", warning);
     }
+    let image_width = config.width;
+    let image_height = config.height;
+    let tone_mapping = description.tone_mapping;
+
+    let options = args.progressive_options(config.sample_count);
+    let write = |pixels: &[f32]| {
+        if let Err(why) = image::write(
+            &args.output,
+            format,
+            image_width,
+            image_height,
+            pixels,
+            &tone_mapping,
+        ) {
+            exit_with(why);
+        }
+    };
+    let mut last_pass = None;
+    let on_pass = |pass: &Pass| {
+        if args.is_progressive() {
+            write(pass.pixels);
+        }
+        last_pass = Some((pass.index, pass.samples, pass.noise));
+        ControlFlow::Continue(())
+    };
 
-    let path = Path::new("a.bpp");
-    match File::create(&path) {
-        Err(why) => panic!("Failed to create {}: {}", path.display(), why),
-        Ok(mut file) => match file.write_all(out_string.as_bytes()) {
-            Err(why) => panic!("Failed to write to {}: {}", path.display(), why),
-            Ok(_) => {}
-        },
+    let output = if args.cpu {
+        CpuRaytracer::new(config, scene).raytrace_progressive(&options, on_pass)
+    } else {
+        match Raytracer::with_device(config, scene.clone(), args.device) {
+            Ok(mut raytracer) => {
+                raytracer.set_timeout(Some(args.timeout).filter(|t| !t.is_zero()));
+                raytracer
+                    .raytrace_progressive(&options, on_pass)
+                    .unwrap_or_else(|why| exit_with_gpu_error(why))
+            }
+            // Only fall back when no device was asked for explicitly
+            Err(why) if why.is_unavailable() && args.device.is_none() => {
+                eprintln!("{}, falling back to the CPU renderer", why);
+                CpuRaytracer::new(config, scene).raytrace_progressive(&options, on_pass)
+            }
+            Err(why) => exit_with_gpu_error(why),
+        }
     };
+
+    write(&output);
+
+    if let (true, Some((passes, samples, noise))) = (args.is_progressive(), last_pass) {
+        match noise {
+            Some(noise) => println!(
+                "{} samples per pixel in {} passes, estimated noise {:.4}",
+                samples, passes, noise
+            ),
+            None => println!("{} samples per pixel in {} passes", samples, passes),
+        }
+    }
 }
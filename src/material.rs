@@ -0,0 +1,306 @@
+use crate::texture::{TextureId, NO_TEXTURE};
+use crate::vec3::Vec3;
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Surface of an object. `T` references textures: a `TextureId` in a `Scene`, the name of an
+/// entry of `SceneDescription::textures` in a scene file.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum Material<T = TextureId> {
+    /// Diffuse surface, the color of `texture` (if any) is multiplied by `albedo`
+    Lambertian {
+        #[serde(default = "default_color")]
+        albedo: Vec3,
+        #[serde(default)]
+        texture: Option<T>,
+    },
+    /// Reflective surface, `fuzz` (0..=1) blurs the reflection. `texture` tints it like the
+    /// albedo of `Lambertian`, the luminance of `fuzz_texture` is multiplied by `fuzz`.
+    Metal {
+        #[serde(default = "default_color")]
+        albedo: Vec3,
+        #[serde(default)]
+        fuzz: f32,
+        #[serde(default)]
+        texture: Option<T>,
+        #[serde(default)]
+        fuzz_texture: Option<T>,
+    },
+    /// Glass-like surface that refracts with the index of refraction `ior`
+    Dielectric { ior: f32 },
+    /// Light source emitting `color` scaled by `intensity`, and by the color of `texture` if
+    /// any. Doesn't scatter any light.
+    DiffuseLight {
+        #[serde(default = "default_color")]
+        color: Vec3,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+        #[serde(default)]
+        texture: Option<T>,
+    },
+    /// Scatters light the same way in every direction, the phase function of volumes (see
+    /// `Scene::add_volume`). The color of `texture` is multiplied by `albedo` like for
+    /// `Lambertian`.
+    Isotropic {
+        #[serde(default = "default_color")]
+        albedo: Vec3,
+        #[serde(default)]
+        texture: Option<T>,
+    },
+}
+
+/// Index of a material in a `Scene`, returned by `Scene::add_material`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub(crate) u32);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaterialError {
+    /// Albedo components must be finite and within 0..=1
+    InvalidAlbedo(Vec3),
+    InvalidFuzz(f32),
+    /// The index of refraction must be finite and greater than 0
+    InvalidIor(f32),
+    /// Light color components must be within 0..=1, use `intensity` for brighter lights
+    InvalidColor(Vec3),
+    /// The intensity must be finite and can't be negative
+    InvalidIntensity(f32),
+    /// The texture isn't part of the scene
+    UnknownTexture(TextureId),
+}
+
+/// `Material` as laid out in `compute.glsl` (std430)
+#[derive(Copy, Clone, Zeroable, Pod, Default)]
+#[repr(C)]
+pub(crate) struct GpuMaterial {
+    albedo: Vec3,
+    kind: u32,
+    emission: Vec3,
+    fuzz: f32,
+    ior: f32,
+    /// Indices into the textures of the scene, `NO_TEXTURE` if there's none
+    texture: u32,
+    fuzz_texture: u32,
+    _2: f32,
+}
+
+// Must match the constants in compute.glsl
+const LAMBERTIAN: u32 = 0;
+const METAL: u32 = 1;
+const DIELECTRIC: u32 = 2;
+const DIFFUSE_LIGHT: u32 = 3;
+const ISOTROPIC: u32 = 4;
+
+fn default_color() -> Vec3 {
+    Vec3::ONE
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+impl<T> Material<T> {
+    pub fn validate(&self) -> Result<(), MaterialError> {
+        let valid_albedo = |albedo: &Vec3| {
+            [albedo.x, albedo.y, albedo.z]
+                .iter()
+                .all(|c| (0.0..=1.0).contains(c))
+        };
+
+        match *self {
+            Material::Lambertian { albedo, .. }
+            | Material::Metal { albedo, .. }
+            | Material::Isotropic { albedo, .. }
+                if !valid_albedo(&albedo) =>
+            {
+                Err(MaterialError::InvalidAlbedo(albedo))
+            }
+            Material::Metal { fuzz, .. } if !(0.0..=1.0).contains(&fuzz) => {
+                Err(MaterialError::InvalidFuzz(fuzz))
+            }
+            Material::Dielectric { ior } if !ior.is_finite() || ior <= 0.0 => {
+                Err(MaterialError::InvalidIor(ior))
+            }
+            Material::DiffuseLight { color, .. } if !valid_albedo(&color) => {
+                Err(MaterialError::InvalidColor(color))
+            }
+            Material::DiffuseLight { intensity, .. }
+                if !intensity.is_finite() || intensity < 0.0 =>
+            {
+                Err(MaterialError::InvalidIntensity(intensity))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Radiance emitted by the surface, zero for everything but lights
+    pub fn emission(&self) -> Vec3 {
+        match *self {
+            Material::DiffuseLight {
+                color, intensity, ..
+            } => color * intensity,
+            _ => Vec3::ZERO,
+        }
+    }
+
+    /// The textures of the material, with the names of their fields
+    pub fn textures(&self) -> impl Iterator<Item = (&'static str, &T)> {
+        let (texture, fuzz_texture) = match self {
+            Material::Lambertian { texture, .. }
+            | Material::DiffuseLight { texture, .. }
+            | Material::Isotropic { texture, .. } => (texture.as_ref(), None),
+            Material::Metal {
+                texture,
+                fuzz_texture,
+                ..
+            } => (texture.as_ref(), fuzz_texture.as_ref()),
+            Material::Dielectric { .. } => (None, None),
+        };
+
+        [("texture", texture), ("fuzz_texture", fuzz_texture)]
+            .into_iter()
+            .filter_map(|(field, texture)| Some((field, texture?)))
+    }
+
+    /// Swaps the texture references, such as the names of a scene file for the ids of a `Scene`
+    pub fn map_textures<U>(self, mut f: impl FnMut(T) -> U) -> Material<U> {
+        match self {
+            Material::Lambertian { albedo, texture } => Material::Lambertian {
+                albedo,
+                texture: texture.map(f),
+            },
+            Material::Metal {
+                albedo,
+                fuzz,
+                texture,
+                fuzz_texture,
+            } => Material::Metal {
+                albedo,
+                fuzz,
+                texture: texture.map(&mut f),
+                fuzz_texture: fuzz_texture.map(f),
+            },
+            Material::Dielectric { ior } => Material::Dielectric { ior },
+            Material::DiffuseLight {
+                color,
+                intensity,
+                texture,
+            } => Material::DiffuseLight {
+                color,
+                intensity,
+                texture: texture.map(f),
+            },
+            Material::Isotropic { albedo, texture } => Material::Isotropic {
+                albedo,
+                texture: texture.map(f),
+            },
+        }
+    }
+}
+
+impl Material {
+    pub(crate) fn lower(&self) -> GpuMaterial {
+        let index = |texture: Option<TextureId>| texture.map_or(NO_TEXTURE, |texture| texture.0);
+        let untextured = GpuMaterial {
+            texture: NO_TEXTURE,
+            fuzz_texture: NO_TEXTURE,
+            ..Default::default()
+        };
+
+        match *self {
+            Material::Lambertian { albedo, texture } => GpuMaterial {
+                albedo,
+                kind: LAMBERTIAN,
+                texture: index(texture),
+                ..untextured
+            },
+            Material::Metal {
+                albedo,
+                fuzz,
+                texture,
+                fuzz_texture,
+            } => GpuMaterial {
+                albedo,
+                kind: METAL,
+                fuzz,
+                texture: index(texture),
+                fuzz_texture: index(fuzz_texture),
+                ..untextured
+            },
+            Material::Dielectric { ior } => GpuMaterial {
+                albedo: Vec3::ONE,
+                kind: DIELECTRIC,
+                ior,
+                ..untextured
+            },
+            Material::DiffuseLight { texture, .. } => GpuMaterial {
+                kind: DIFFUSE_LIGHT,
+                emission: self.emission(),
+                texture: index(texture),
+                ..untextured
+            },
+            Material::Isotropic { albedo, texture } => GpuMaterial {
+                albedo,
+                kind: ISOTROPIC,
+                texture: index(texture),
+                ..untextured
+            },
+        }
+    }
+}
+
+impl MaterialId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl MaterialError {
+    /// Name of the offending field
+    pub fn field(&self) -> &'static str {
+        match self {
+            MaterialError::InvalidAlbedo(_) => "albedo",
+            MaterialError::InvalidFuzz(_) => "fuzz",
+            MaterialError::InvalidIor(_) => "ior",
+            MaterialError::InvalidColor(_) => "color",
+            MaterialError::InvalidIntensity(_) => "intensity",
+            MaterialError::UnknownTexture(_) => "texture",
+        }
+    }
+}
+
+impl fmt::Display for MaterialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaterialError::InvalidAlbedo(albedo) => write!(
+                f,
+                "albedo components must be between 0 and 1, got ({}, {}, {})",
+                albedo.x, albedo.y, albedo.z
+            ),
+            MaterialError::InvalidFuzz(fuzz) => {
+                write!(f, "fuzz must be between 0 and 1, got {}", fuzz)
+            }
+            MaterialError::InvalidIor(ior) => {
+                write!(
+                    f,
+                    "the index of refraction must be greater than 0, got {}",
+                    ior
+                )
+            }
+            MaterialError::InvalidColor(color) => write!(
+                f,
+                "color components must be between 0 and 1, got ({}, {}, {})",
+                color.x, color.y, color.z
+            ),
+            MaterialError::InvalidIntensity(intensity) => {
+                write!(f, "the intensity can't be negative, got {}", intensity)
+            }
+            MaterialError::UnknownTexture(texture) => {
+                write!(f, "the scene has no texture {}", texture.0)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MaterialError {}
@@ -0,0 +1,191 @@
+use crate::bvh::Aabb;
+use crate::material::MaterialId;
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+use bytemuck::{Pod, Zeroable};
+use std::fmt;
+
+/// Indexed triangle mesh, as passed to `Scene::add_mesh`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    /// Per vertex normals, empty to shade the triangles flat
+    pub normals: Vec<Vec3>,
+    /// Per vertex texture coordinates, empty if the mesh has none
+    pub uvs: Vec<[f32; 2]>,
+    /// Counter-clockwise triangles, seen from the side their normal points to
+    pub indices: Vec<[u32; 3]>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeshError {
+    /// Positions must be finite
+    InvalidPosition(usize),
+    /// `normals` must be empty or hold one normal per position
+    NormalCount { expected: usize, found: usize },
+    /// `uvs` must be empty or hold one pair per position
+    UvCount { expected: usize, found: usize },
+    /// Triangle referencing a vertex past the end of `positions`
+    IndexOutOfBounds {
+        triangle: usize,
+        index: u32,
+        vertex_count: usize,
+    },
+    /// The material isn't part of the scene
+    UnknownMaterial(MaterialId),
+}
+
+/// Mesh vertex as laid out in `compute.glsl` (std430), the texture coordinates fill the padding
+/// of the vectors
+#[derive(Pod, Zeroable, Copy, Clone, Default)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub u: f32,
+    /// Zero when the mesh has no normals
+    pub normal: Vec3,
+    pub v: f32,
+}
+
+/// Triangle as laid out in `compute.glsl` (std430)
+#[derive(Pod, Zeroable, Copy, Clone, Default)]
+#[repr(C)]
+pub struct Triangle {
+    /// Indices into `Scene::vertices`
+    pub vertices: [u32; 3],
+    /// Index into `Scene::materials`
+    pub material: u32,
+}
+
+impl Mesh {
+    pub fn validate(&self) -> Result<(), MeshError> {
+        let vertex_count = self.positions.len();
+
+        if let Some(i) = self
+            .positions
+            .iter()
+            .position(|p| ![p.x, p.y, p.z].iter().all(|c| c.is_finite()))
+        {
+            return Err(MeshError::InvalidPosition(i));
+        }
+        if !self.normals.is_empty() && self.normals.len() != vertex_count {
+            return Err(MeshError::NormalCount {
+                expected: vertex_count,
+                found: self.normals.len(),
+            });
+        }
+        if !self.uvs.is_empty() && self.uvs.len() != vertex_count {
+            return Err(MeshError::UvCount {
+                expected: vertex_count,
+                found: self.uvs.len(),
+            });
+        }
+
+        for (triangle, indices) in self.indices.iter().enumerate() {
+            if let Some(&index) = indices.iter().find(|&&i| i as usize >= vertex_count) {
+                return Err(MeshError::IndexOutOfBounds {
+                    triangle,
+                    index,
+                    vertex_count,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves the mesh, keeping the normals perpendicular to the surface and the winding
+    /// counter-clockwise
+    pub fn transform(&mut self, transform: &Transform) {
+        for position in self.positions.iter_mut() {
+            *position = transform.point(*position);
+        }
+        for normal in self.normals.iter_mut() {
+            *normal = transform.normal(*normal);
+        }
+
+        // Mirroring flips the winding, which has to stay counter-clockwise
+        if transform.determinant() < 0.0 {
+            for indices in self.indices.iter_mut() {
+                indices.swap(1, 2);
+            }
+        }
+    }
+
+    /// The vertices in the layout of the GPU
+    pub(crate) fn vertices(&self) -> impl Iterator<Item = Vertex> + '_ {
+        self.positions.iter().enumerate().map(|(i, &position)| {
+            let [u, v] = self.uvs.get(i).copied().unwrap_or_default();
+            Vertex {
+                position,
+                u,
+                normal: match self.normals.get(i) {
+                    Some(normal) if !normal.is_near_zero() => normal.unit(),
+                    _ => Vec3::ZERO,
+                },
+                v,
+            }
+        })
+    }
+}
+
+impl Triangle {
+    pub fn new(vertices: [u32; 3], material: MaterialId) -> Triangle {
+        Triangle {
+            vertices,
+            material: material.0,
+        }
+    }
+
+    /// `vertices` is the vertex buffer the indices point into
+    pub fn bounds(&self, vertices: &[Vertex]) -> Aabb {
+        self.vertices.iter().fold(Aabb::EMPTY, |bounds, &i| {
+            bounds.grow(&vertices[i as usize].position)
+        })
+    }
+}
+
+impl MeshError {
+    /// Name of the offending field
+    pub fn field(&self) -> &'static str {
+        match self {
+            MeshError::InvalidPosition(_) => "positions",
+            MeshError::NormalCount { .. } => "normals",
+            MeshError::UvCount { .. } => "uvs",
+            MeshError::IndexOutOfBounds { .. } => "indices",
+            MeshError::UnknownMaterial(_) => "material",
+        }
+    }
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::InvalidPosition(i) => write!(f, "position {} must be finite", i),
+            MeshError::NormalCount { expected, found } => write!(
+                f,
+                "expected {} normals (one per position) but found {}",
+                expected, found
+            ),
+            MeshError::UvCount { expected, found } => write!(
+                f,
+                "expected {} uvs (one per position) but found {}",
+                expected, found
+            ),
+            MeshError::IndexOutOfBounds {
+                triangle,
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "triangle {} references vertex {} but there are only {} positions",
+                triangle, index, vertex_count
+            ),
+            MeshError::UnknownMaterial(id) => {
+                write!(f, "the scene has no material {}", id.index())
+            }
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
@@ -0,0 +1,560 @@
+//! Wavefront OBJ import, with the materials of its MTL libraries mapped onto `Material`.
+
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::vec3::Vec3;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The content of an OBJ file, as returned by `load`
+pub struct ObjModel {
+    /// One mesh per material used by the faces
+    pub meshes: Vec<ObjMesh>,
+    /// Materials of the MTL libraries, by name
+    pub materials: BTreeMap<String, Material>,
+    /// Statements that were skipped, the model is still usable
+    pub warnings: Vec<ObjWarning>,
+}
+
+pub struct ObjMesh {
+    /// Name given to `usemtl`, `None` for the faces before the first one or when the material
+    /// isn't in any of the libraries
+    pub material: Option<String>,
+    pub mesh: Mesh,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjWarning {
+    pub path: PathBuf,
+    /// `None` for warnings about the file as a whole
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Corner of a face, as indices into the vertex attributes of the file
+#[derive(Copy, Clone)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct ObjTriangle {
+    corners: [Corner; 3],
+    /// 0 when smoothing is off
+    smoothing_group: u32,
+}
+
+/// Where the normal of a mesh vertex comes from, part of the key vertices are shared by
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum NormalSource {
+    /// A `vn` of the file
+    Given(usize),
+    /// Averaged over the faces of the smoothing group sharing the position
+    Smooth(u32),
+    /// The normal of a single (triangle) face
+    Flat(usize),
+    /// The mesh has no normals, the renderer shades it flat
+    None,
+}
+
+/// MTL statements `Material` can represent
+#[derive(Default)]
+struct MtlMaterial {
+    diffuse: Option<Vec3>,
+    specular: Option<Vec3>,
+    emission: Option<Vec3>,
+    shininess: Option<f32>,
+    ior: Option<f32>,
+    dissolve: Option<f32>,
+    illum: Option<u32>,
+}
+
+/// Counts the skipped statements of a file so each kind is reported once
+struct Warnings {
+    path: PathBuf,
+    /// First line and number of occurrences by keyword
+    unsupported: BTreeMap<String, (usize, usize)>,
+    other: Vec<ObjWarning>,
+}
+
+/// Albedo of materials without `Kd`, and of faces without a material
+const DEFAULT_ALBEDO: f32 = 0.8;
+
+/// Loads an OBJ file and the MTL libraries it references.
+///
+/// Polygons are triangulated as fans, so they should be convex. Faces without `vn` normals are
+/// smoothed over their smoothing group (`s`), or shaded flat when smoothing is off.
+pub fn load(path: &Path) -> Result<ObjModel, ObjError> {
+    let source = read(path)?;
+    let mut warnings = Warnings::new(path);
+
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+
+    let mut materials = BTreeMap::new();
+    // Triangles by the material they use, in the order the materials first appear
+    let mut groups: Vec<(Option<String>, Vec<ObjTriangle>)> = vec![(None, Vec::new())];
+    let mut current = 0;
+    let mut smoothing_group = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let parse_error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+
+        let mut tokens = line.split('#').next().unwrap_or("").split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&arguments).map_err(parse_error)?),
+            "vn" => normals.push(parse_vec3(&arguments).map_err(parse_error)?),
+            "vt" => {
+                let uv = parse_floats(&arguments, 1).map_err(parse_error)?;
+                uvs.push([uv[0], uv.get(1).copied().unwrap_or(0.0)]);
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    warnings.push(Some(line_number), "face with less than 3 vertices ignored");
+                    continue;
+                }
+
+                let corners = arguments
+                    .iter()
+                    .map(|corner| parse_corner(corner, positions.len(), uvs.len(), normals.len()))
+                    .collect::<Result<Vec<Corner>, String>>()
+                    .map_err(parse_error)?;
+
+                let triangles = &mut groups[current].1;
+                for i in 1..corners.len() - 1 {
+                    triangles.push(ObjTriangle {
+                        corners: [corners[0], corners[i], corners[i + 1]],
+                        smoothing_group,
+                    });
+                }
+            }
+            "s" => {
+                smoothing_group = match arguments.first() {
+                    Some(&"off") | None => 0,
+                    Some(group) => group
+                        .parse()
+                        .map_err(|_| parse_error(format!("invalid smoothing group '{}'", group)))?,
+                }
+            }
+            "usemtl" => {
+                let name = arguments.join(" ");
+                current = match groups.iter().position(|(m, _)| m.as_deref() == Some(&name)) {
+                    Some(index) => index,
+                    None => {
+                        groups.push((Some(name), Vec::new()));
+                        groups.len() - 1
+                    }
+                };
+            }
+            "mtllib" => {
+                for library in arguments.iter() {
+                    let library_path = path.with_file_name(library);
+                    match load_mtl(&library_path) {
+                        Ok((library, library_warnings)) => {
+                            materials.extend(library);
+                            warnings.other.extend(library_warnings);
+                        }
+                        // The geometry is still worth rendering with the default material
+                        Err(why) => warnings.push(Some(line_number), &why.to_string()),
+                    }
+                }
+            }
+            // Names only, the meshes are split by material
+            "o" | "g" => {}
+            _ => warnings.unsupported(keyword, line_number),
+        }
+    }
+
+    for (material, _) in groups.iter_mut() {
+        if let Some(name) = material
+            .as_ref()
+            .filter(|name| !materials.contains_key(*name))
+        {
+            warnings.push(
+                None,
+                &format!(
+                    "material '{}' isn't in any library, using the default",
+                    name
+                ),
+            );
+            *material = None;
+        }
+    }
+
+    let meshes = groups
+        .into_iter()
+        .filter(|(_, triangles)| !triangles.is_empty())
+        .map(|(material, triangles)| ObjMesh {
+            material,
+            mesh: build_mesh(&triangles, &positions, &uvs, &normals),
+        })
+        .collect();
+
+    Ok(ObjModel {
+        meshes,
+        materials,
+        warnings: warnings.finish(),
+    })
+}
+
+/// Material for the faces of a model without one
+pub fn default_material() -> Material {
+    Material::Lambertian {
+        albedo: Vec3::ONE * DEFAULT_ALBEDO,
+        texture: None,
+    }
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn parse_floats(arguments: &[&str], min_count: usize) -> Result<Vec<f32>, String> {
+    if arguments.len() < min_count {
+        return Err(format!(
+            "expected at least {} numbers, found {}",
+            min_count,
+            arguments.len()
+        ));
+    }
+
+    arguments
+        .iter()
+        .map(|a| {
+            a.parse::<f32>()
+                .map_err(|_| format!("'{}' is not a number", a))
+        })
+        .collect()
+}
+
+fn parse_vec3(arguments: &[&str]) -> Result<Vec3, String> {
+    let values = parse_floats(arguments, 3)?;
+    Ok(Vec3::new(values[0], values[1], values[2]))
+}
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`, indices start at 1 and negative ones count back
+/// from the last attribute
+fn parse_corner(
+    corner: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<Corner, String> {
+    let index = |index: &str, count: usize| -> Result<usize, String> {
+        let parsed: i64 = index
+            .parse()
+            .map_err(|_| format!("'{}' is not an index", index))?;
+        let resolved = if parsed < 0 {
+            count as i64 + parsed
+        } else {
+            parsed - 1
+        };
+
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(format!(
+                "index {} is out of range, there are {} of them so far",
+                parsed, count
+            ));
+        }
+        Ok(resolved as usize)
+    };
+    let optional_index = |i: Option<&str>, count| match i {
+        Some(i) if !i.is_empty() => index(i, count).map(Some),
+        _ => Ok(None),
+    };
+
+    let mut indices = corner.split('/');
+    Ok(Corner {
+        position: index(indices.next().unwrap_or(""), position_count)?,
+        uv: optional_index(indices.next(), uv_count)?,
+        normal: optional_index(indices.next(), normal_count)?,
+    })
+}
+
+fn build_mesh(
+    triangles: &[ObjTriangle],
+    positions: &[Vec3],
+    uvs: &[[f32; 2]],
+    normals: &[Vec3],
+) -> Mesh {
+    let corners = || triangles.iter().flat_map(|t| t.corners.iter());
+    let has_uvs = corners().any(|c| c.uv.is_some());
+    let has_normals =
+        corners().any(|c| c.normal.is_some()) || triangles.iter().any(|t| t.smoothing_group != 0);
+
+    // Not normalized, so larger faces weigh more in the smoothed normals
+    let face_normals: Vec<Vec3> = triangles
+        .iter()
+        .map(|t| {
+            let [a, b, c] = t.corners.map(|c| positions[c.position]);
+            (b - a).cross(&(c - a))
+        })
+        .collect();
+
+    let mut smoothed: HashMap<(usize, u32), Vec3> = HashMap::new();
+    for (triangle, face_normal) in triangles.iter().zip(face_normals.iter()) {
+        for corner in triangle.corners.iter() {
+            if corner.normal.is_none() && triangle.smoothing_group != 0 {
+                *smoothed
+                    .entry((corner.position, triangle.smoothing_group))
+                    .or_insert(Vec3::ZERO) += *face_normal;
+            }
+        }
+    }
+
+    let mut mesh = Mesh::default();
+    let mut vertex_ids: HashMap<(usize, Option<usize>, NormalSource), u32> = HashMap::new();
+
+    for (i, triangle) in triangles.iter().enumerate() {
+        let indices = triangle.corners.map(|corner| {
+            let normal_source = match (corner.normal, triangle.smoothing_group) {
+                _ if !has_normals => NormalSource::None,
+                (Some(normal), _) => NormalSource::Given(normal),
+                (None, 0) => NormalSource::Flat(i),
+                (None, group) => NormalSource::Smooth(group),
+            };
+
+            let key = (corner.position, corner.uv, normal_source);
+            *vertex_ids.entry(key).or_insert_with(|| {
+                mesh.positions.push(positions[corner.position]);
+                if has_uvs {
+                    mesh.uvs.push(corner.uv.map_or([0.0; 2], |uv| uvs[uv]));
+                }
+                match normal_source {
+                    NormalSource::Given(normal) => mesh.normals.push(normals[normal]),
+                    NormalSource::Smooth(group) => {
+                        mesh.normals.push(smoothed[&(corner.position, group)])
+                    }
+                    NormalSource::Flat(face) => mesh.normals.push(face_normals[face]),
+                    NormalSource::None => {}
+                }
+                (mesh.positions.len() - 1) as u32
+            })
+        });
+
+        mesh.indices.push(indices);
+    }
+
+    mesh
+}
+
+fn load_mtl(path: &Path) -> Result<(BTreeMap<String, Material>, Vec<ObjWarning>), ObjError> {
+    let source = read(path)?;
+    let mut warnings = Warnings::new(path);
+
+    let mut parsed: Vec<(String, MtlMaterial)> = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let parse_error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+
+        let mut tokens = line.split('#').next().unwrap_or("").split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            parsed.push((arguments.join(" "), MtlMaterial::default()));
+            continue;
+        }
+
+        let material = match parsed.last_mut() {
+            Some((_, material)) => material,
+            None => {
+                warnings.push(
+                    Some(line_number),
+                    "statement before the first newmtl ignored",
+                );
+                continue;
+            }
+        };
+
+        let scalar = |arguments: &[&str]| {
+            parse_floats(arguments, 1)
+                .map(|values| values[0])
+                .map_err(parse_error)
+        };
+
+        match keyword {
+            "Kd" => material.diffuse = Some(parse_vec3(&arguments).map_err(parse_error)?),
+            "Ks" => material.specular = Some(parse_vec3(&arguments).map_err(parse_error)?),
+            "Ke" => material.emission = Some(parse_vec3(&arguments).map_err(parse_error)?),
+            "Ns" => material.shininess = Some(scalar(&arguments)?),
+            "Ni" => material.ior = Some(scalar(&arguments)?),
+            "d" => material.dissolve = Some(scalar(&arguments)?),
+            "Tr" => material.dissolve = Some(1.0 - scalar(&arguments)?),
+            "illum" => material.illum = Some(scalar(&arguments)? as u32),
+            _ => warnings.unsupported(keyword, line_number),
+        }
+    }
+
+    let materials = parsed
+        .into_iter()
+        .map(|(name, material)| (name, material.to_material()))
+        .collect();
+
+    Ok((materials, warnings.finish()))
+}
+
+impl MtlMaterial {
+    /// Emissive materials become lights, transparent ones (`d` below 1, `Tr` above 0 or a
+    /// refracting `illum`) glass, and the ones more specular than diffuse (or `illum 3`) metal.
+    /// Everything else is diffuse.
+    fn to_material(&self) -> Material {
+        let clamp = |color: Vec3| {
+            Vec3::new(
+                color.x.clamp(0.0, 1.0),
+                color.y.clamp(0.0, 1.0),
+                color.z.clamp(0.0, 1.0),
+            )
+        };
+        let brightness = |color: Option<Vec3>| color.map_or(0.0, |c| c.x.max(c.y).max(c.z));
+
+        let diffuse = self.diffuse.unwrap_or(Vec3::ONE * DEFAULT_ALBEDO);
+        let emission = brightness(self.emission);
+        let transparent = matches!(self.dissolve, Some(d) if d < 1.0)
+            || matches!(self.illum, Some(4) | Some(6) | Some(7));
+
+        if emission > 0.0 {
+            Material::DiffuseLight {
+                color: clamp(self.emission.unwrap_or(Vec3::ZERO) / emission),
+                intensity: emission,
+                texture: None,
+            }
+        } else if transparent {
+            Material::Dielectric {
+                ior: self.ior.filter(|ior| *ior > 0.0).unwrap_or(1.5),
+            }
+        } else if self.illum == Some(3) || brightness(self.specular) > brightness(Some(diffuse)) {
+            // Phong exponent to roughness
+            let shininess = self.shininess.unwrap_or(0.0).max(0.0);
+            Material::Metal {
+                albedo: clamp(self.specular.unwrap_or(Vec3::ONE)),
+                fuzz: f32::sqrt(2.0 / (shininess + 2.0)).clamp(0.0, 1.0),
+                texture: None,
+                fuzz_texture: None,
+            }
+        } else {
+            Material::Lambertian {
+                albedo: clamp(diffuse),
+                texture: None,
+            }
+        }
+    }
+}
+
+impl Warnings {
+    fn new(path: &Path) -> Warnings {
+        Warnings {
+            path: path.to_path_buf(),
+            unsupported: BTreeMap::new(),
+            other: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, line: Option<usize>, message: &str) {
+        self.other.push(ObjWarning {
+            path: self.path.clone(),
+            line,
+            message: message.to_string(),
+        });
+    }
+
+    fn unsupported(&mut self, keyword: &str, line: usize) {
+        self.unsupported
+            .entry(keyword.to_string())
+            .or_insert((line, 0))
+            .1 += 1;
+    }
+
+    fn finish(self) -> Vec<ObjWarning> {
+        let path = self.path;
+        let unsupported = self
+            .unsupported
+            .into_iter()
+            .map(|(keyword, (line, count))| ObjWarning {
+                path: path.clone(),
+                line: Some(line),
+                message: match count {
+                    1 => format!("unsupported statement '{}' ignored", keyword),
+                    _ => format!(
+                        "unsupported statement '{}' ignored ({} times)",
+                        keyword, count
+                    ),
+                },
+            });
+
+        self.other.into_iter().chain(unsupported).collect()
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => {
+                write!(f, "{}: failed to read model: {}", path.display(), source)
+            }
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for ObjWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+/// How Lambertian surfaces pick the direction of the next bounce. The directions and densities
+/// below follow the third book, `compute.glsl` mirrors them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum LightSampling {
+    /// Scatters like the first book, lights are only found by chance
+    Off,
+    /// Half of the bounces head for a random light, the other half follow the cosine of the
+    /// surface. Both are weighted by the balance heuristic.
+    Balance,
+    /// Like `Balance`, weighted by the power heuristic which favors the likelier of the two
+    #[default]
+    Power,
+}
+
+/// Orthonormal basis around `w`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+// Must match the constants in compute.glsl
+const OFF: u32 = 0;
+const BALANCE: u32 = 1;
+const POWER: u32 = 2;
+
+impl LightSampling {
+    pub const NAMES: [&'static str; 3] = ["off", "balance", "power"];
+
+    /// Value of `Config::light_sampling`
+    pub(crate) fn lower(self) -> u32 {
+        match self {
+            LightSampling::Off => OFF,
+            LightSampling::Balance => BALANCE,
+            LightSampling::Power => POWER,
+        }
+    }
+
+    pub(crate) fn from_config(value: u32) -> LightSampling {
+        match value {
+            OFF => LightSampling::Off,
+            BALANCE => LightSampling::Balance,
+            _ => LightSampling::Power,
+        }
+    }
+
+    /// Multiplies the scattering density of a direction picked by one of the two halves of the
+    /// mixture, `picked` is the density of that half and `other` the density of the other. The
+    /// result is the weight of the sample, 0 when neither half could have picked it.
+    pub fn weight(self, scattering: f32, picked: f32, other: f32) -> f32 {
+        let (picked, other) = (0.5 * picked, 0.5 * other);
+        let weight = match self {
+            LightSampling::Power => scattering * picked / (picked * picked + other * other),
+            _ => scattering / (picked + other),
+        };
+        if weight.is_finite() {
+            weight
+        } else {
+            0.0
+        }
+    }
+}
+
+impl FromStr for LightSampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(LightSampling::Off),
+            "balance" => Ok(LightSampling::Balance),
+            "power" => Ok(LightSampling::Power),
+            _ => Err(format!(
+                "unknown light sampling '{}', expected one of: {}",
+                s,
+                LightSampling::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl Onb {
+    /// `w` doesn't have to be normalized
+    pub fn new(w: Vec3) -> Onb {
+        let w = w.unit();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit();
+        let u = w.cross(&v);
+        Onb { u, v, w }
+    }
+
+    /// From the basis to world space
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
+
+/// Direction around +z with a density of `cosine_pdf`, from two uniform numbers in 0..1
+pub fn random_cosine_direction(r1: f32, r2: f32) -> Vec3 {
+    let phi = 2.0 * PI * r1;
+    let (sin, cos) = phi.sin_cos();
+    Vec3::new(cos * r2.sqrt(), sin * r2.sqrt(), (1.0 - r2).sqrt())
+}
+
+/// Density of the cosine-weighted hemisphere around `normal`, `normal` and `dir` are unit vectors
+pub fn cosine_pdf(normal: Vec3, dir: Vec3) -> f32 {
+    normal.dot(&dir).max(0.0) / PI
+}
+
+/// Direction around +z towards a sphere of `radius` at `distance_squared`, uniform over the
+/// cone of directions hitting it. Uniform over the whole sphere of directions from inside.
+pub fn random_to_sphere(radius: f32, distance_squared: f32, r1: f32, r2: f32) -> Vec3 {
+    let cos_theta_max = sphere_cos_theta_max(radius, distance_squared);
+    let z = 1.0 + r2 * (cos_theta_max - 1.0);
+    let phi = 2.0 * PI * r1;
+    let (sin, cos) = phi.sin_cos();
+    let radial = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(cos * radial, sin * radial, z)
+}
+
+/// Density of `random_to_sphere` for the directions hitting the sphere
+pub fn sphere_pdf(radius: f32, distance_squared: f32) -> f32 {
+    let cos_theta_max = sphere_cos_theta_max(radius, distance_squared);
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+/// Density of picking the direction towards a point of a surface of `area` picked uniformly,
+/// seen `distance_squared` away with the angle `cosine` to its normal
+pub fn area_pdf(area: f32, distance_squared: f32, cosine: f32) -> f32 {
+    distance_squared / (cosine.abs() * area)
+}
+
+/// -1 from inside the sphere, so that the cone covers every direction
+fn sphere_cos_theta_max(radius: f32, distance_squared: f32) -> f32 {
+    if distance_squared <= radius * radius {
+        return -1.0;
+    }
+    (1.0 - radius * radius / distance_squared).max(0.0).sqrt()
+}
@@ -0,0 +1,156 @@
+use pbr::ProgressBar;
+use std::io::Stdout;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// When and how `raytrace_progressive` refines the image.
+///
+/// Rendering stops at whichever comes first: `target_samples`, `time_budget`,
+/// `noise_threshold` or the pass callback returning `ControlFlow::Break`.
+#[derive(Clone, Debug)]
+pub struct ProgressiveOptions {
+    /// Samples per pixel added by each pass
+    pub samples_per_pass: u32,
+    /// Samples per pixel to stop at, `Config::sample_count` when `None`
+    pub target_samples: Option<u32>,
+    /// Stops after the first pass that ends past this much time
+    pub time_budget: Option<Duration>,
+    /// Stops once `Pass::noise` drops to this value
+    pub noise_threshold: Option<f32>,
+}
+
+/// The state of the image after a pass, handed to the pass callback
+pub struct Pass<'a> {
+    /// Number of passes done so far, starting at 1
+    pub index: u32,
+    /// Samples per pixel accumulated so far
+    pub samples: u32,
+    pub elapsed: Duration,
+    /// Average standard error of the pixel luminance (linear, before gamma correction), `None`
+    /// until each pixel has at least two samples. Only estimated with a `noise_threshold`, the
+    /// accumulation buffer isn't read back otherwise.
+    pub noise: Option<f32>,
+    /// The image so far, laid out like the output of `raytrace`
+    pub pixels: &'a [f32],
+}
+
+/// A renderer able to add samples to its accumulation buffer, see `render`
+pub(crate) trait PassRenderer {
+    type Error;
+
+    /// Called before the first pass with the number of samples per pixel the render heads for
+    fn begin(&mut self, target_samples: u32);
+
+    /// Adds samples `sample_offset..sample_offset + sample_count` to every pixel, an offset of
+    /// 0 starts a new image
+    fn render_pass(&mut self, sample_offset: u32, sample_count: u32) -> Result<(), Self::Error>;
+
+    /// The linear image after the last pass
+    fn pixels(&self) -> Result<Vec<f32>, Self::Error>;
+
+    /// Per pixel sum of the color samples (xyz) and of their squared luminance (w)
+    fn accumulation(&self) -> Result<Vec<[f32; 4]>, Self::Error>;
+
+    /// Called once rendering stopped
+    fn finish(&mut self);
+}
+
+impl Default for ProgressiveOptions {
+    fn default() -> Self {
+        ProgressiveOptions {
+            samples_per_pass: 4,
+            target_samples: None,
+            time_budget: None,
+            noise_threshold: None,
+        }
+    }
+}
+
+/// Luminance weights (Rec. 709), must match `LUMINANCE` in compute.glsl
+pub(crate) const LUMINANCE: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+pub(crate) fn luminance(color: [f32; 3]) -> f32 {
+    color[0] * LUMINANCE[0] + color[1] * LUMINANCE[1] + color[2] * LUMINANCE[2]
+}
+
+/// A bar counting pixel samples, `total` of them
+pub(crate) fn progress_bar(total: u64) -> ProgressBar<Stdout> {
+    let mut progress_bar = ProgressBar::new(total);
+    progress_bar.format("╢▌▌░╟");
+    progress_bar.show_counter = false;
+    progress_bar.show_speed = false;
+    progress_bar
+}
+
+/// See `Pass::noise`
+fn noise(accumulation: &[[f32; 4]], samples: u32) -> Option<f32> {
+    if samples < 2 || accumulation.is_empty() {
+        return None;
+    }
+
+    let n = samples as f32;
+    let total: f32 = accumulation
+        .iter()
+        .map(|pixel| {
+            let mean = luminance([pixel[0], pixel[1], pixel[2]]) / n;
+            let variance = (pixel[3] / n - mean * mean).max(0.0) * n / (n - 1.0);
+            f32::sqrt(variance / n)
+        })
+        .sum();
+
+    Some(total / accumulation.len() as f32)
+}
+
+/// Runs passes until one of the stop conditions of `options` is met
+pub(crate) fn render<R, F>(
+    renderer: &mut R,
+    default_target: u32,
+    options: &ProgressiveOptions,
+    mut on_pass: F,
+) -> Result<Vec<f32>, R::Error>
+where
+    R: PassRenderer,
+    F: FnMut(&Pass) -> ControlFlow<()>,
+{
+    let start = Instant::now();
+    let target = options.target_samples.unwrap_or(default_target).max(1);
+    let samples_per_pass = options.samples_per_pass.max(1);
+
+    let mut index = 0;
+    let mut samples = 0;
+
+    renderer.begin(target);
+
+    loop {
+        let sample_count = samples_per_pass.min(target - samples);
+        renderer.render_pass(samples, sample_count)?;
+
+        index += 1;
+        samples += sample_count;
+
+        let pixels = renderer.pixels()?;
+        let noise = match options.noise_threshold {
+            Some(_) => noise(&renderer.accumulation()?, samples),
+            None => None,
+        };
+        let elapsed = start.elapsed();
+
+        let pass = Pass {
+            index,
+            samples,
+            elapsed,
+            noise,
+            pixels: &pixels,
+        };
+
+        let stop = on_pass(&pass).is_break()
+            || samples >= target
+            || matches!(options.time_budget, Some(budget) if elapsed >= budget)
+            || matches!((noise, options.noise_threshold), (Some(noise), Some(threshold)) if noise <= threshold);
+
+        if stop {
+            renderer.finish();
+            return Ok(pixels);
+        }
+    }
+}
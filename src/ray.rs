@@ -0,0 +1,19 @@
+use crate::vec3::Vec3;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub dir: Vec3,
+    /// When the ray was sent, within the shutter interval of the camera
+    pub time: f32,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, dir: Vec3, time: f32) -> Ray {
+        Ray { origin, dir, time }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + t * self.dir
+    }
+}
@@ -1,10 +1,23 @@
+use crate::bvh::{Aabb, BvhNode};
+pub use crate::camera::Camera;
+use crate::error::RaytracerError;
+use crate::instance::GpuInstance;
+use crate::material::{GpuMaterial, MaterialId};
+use crate::mesh::{Triangle, Vertex};
+use crate::progressive::{self, Pass, PassRenderer, ProgressiveOptions};
+use crate::rect::Rect;
+use crate::sampler::{self, Sampler};
+use crate::scene::Scene;
+use crate::texture::{self, GpuTexture};
 use crate::vec3::Vec3;
+use crate::volume::GpuVolume;
 use bytemuck::{Pod, Zeroable};
 use display_json::DebugAsJsonPretty;
-use pbr::ProgressBar;
 use serde::Serialize;
 use std::io::Stdout;
+use std::ops::ControlFlow;
 use std::sync::Arc;
+use std::time::Duration;
 
 use vulkano::{
     buffer::{BufferUsage, CpuAccessibleBuffer},
@@ -15,38 +28,16 @@ use vulkano::{
         allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
     },
     device::{
-        physical::PhysicalDeviceType, Device, DeviceCreateInfo, DeviceExtensions, Queue,
-        QueueCreateInfo,
+        physical::{PhysicalDevice, PhysicalDeviceType},
+        Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo,
     },
     instance::{Instance, InstanceCreateInfo},
     memory::allocator::StandardMemoryAllocator,
     pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
-    sync::{self, GpuFuture},
+    sync::{self, FlushError, GpuFuture},
     VulkanLibrary,
 };
 
-#[derive(Copy, Clone, Zeroable, Pod, Default, Serialize, DebugAsJsonPretty)]
-#[repr(C)]
-pub struct Camera {
-    pub origin: Vec3,
-    pub _0: f32,
-    pub lower_left_corner: Vec3,
-    pub _1: f32,
-    pub horizontal: Vec3,
-    pub _2: f32,
-    pub vertical: Vec3,
-    pub _3: f32,
-    pub up: Vec3,
-    pub _4: f32,
-    pub u: Vec3,
-    pub _5: f32,
-    pub v: Vec3,
-    pub _6: f32,
-    pub w: Vec3,
-    pub _7: f32,
-    pub lens_radius: f32,
-}
-
 #[derive(Pod, Zeroable, Copy, Clone, Default, DebugAsJsonPretty, Serialize)]
 #[repr(C)]
 pub struct Config {
@@ -55,26 +46,86 @@ pub struct Config {
     pub max_bounces: u32,
     pub width: u32,
     pub height: u32,
+    pub num_triangles: u32,
+    pub num_rects: u32,
+    pub num_instances: u32,
+    pub num_volumes: u32,
+    /// Length of `Scene::lights`
+    pub num_lights: u32,
+    /// `LightSampling` of the render, `Off` when 0
+    pub light_sampling: u32,
+    /// Seeds the random numbers of every sample, see `Pcg`
+    pub seed: u32,
 
-    pub _0: f32,
-    pub _1: f32,
-    pub _2: f32,
     pub camera: Camera,
+
+    /// Color of rays escaping the scene straight down, blended into `background_top` as they
+    /// point upwards. Both are the same for a constant background.
+    pub background_bottom: Vec3,
+    #[serde(skip)]
+    pub(crate) _3: f32,
+    pub background_top: Vec3,
+    #[serde(skip)]
+    pub(crate) _4: f32,
+
+    /// See `Fog`, a density of 0 disables it
+    pub fog_color: Vec3,
+    pub fog_density: f32,
+
+    /// `Sampler` of the render
+    pub sampler_mode: u32,
+    /// Nodes of the BVH, set by `Raytracer::new`. There are none when every primitive belongs to
+    /// objects that no instance or volume places, and nothing to traverse.
+    #[serde(skip)]
+    pub(crate) num_bvh_nodes: u32,
+    #[serde(skip)]
+    pub(crate) _6: u32,
+    #[serde(skip)]
+    pub(crate) _7: u32,
 }
 
 #[derive(Pod, Zeroable, Copy, Clone, Default)]
 #[repr(C)]
 pub struct Sphere {
+    /// Center at time 0
+    pub center: Vec3,
     pub radius: f32,
-    pub mat_type: u32,
-    pub fuzz_or_ir: f32,
-    pub _0: f32,
+    /// Distance the center moves from time 0 to time 1, zero for spheres that stay in place
+    pub velocity: Vec3,
+
+    /// Index into `Scene::materials`
+    pub material: u32,
+}
 
-    pub albedo: Vec3,
-    pub _1: f32,
+impl Sphere {
+    pub fn new(center: Vec3, radius: f32, material: MaterialId) -> Sphere {
+        Sphere {
+            center,
+            radius,
+            material: material.0,
+            ..Default::default()
+        }
+    }
 
-    pub center: Vec3,
-    pub _2: f32,
+    /// Sphere moving in a straight line from `center0` at time 0 to `center1` at time 1
+    pub fn moving(center0: Vec3, center1: Vec3, radius: f32, material: MaterialId) -> Sphere {
+        Sphere {
+            velocity: center1 - center0,
+            ..Sphere::new(center0, radius, material)
+        }
+    }
+
+    pub fn center(&self, time: f32) -> Vec3 {
+        self.center + time * self.velocity
+    }
+
+    /// Covers the whole path of the sphere from time 0 to time 1
+    pub fn bounds(&self) -> Aabb {
+        let extent = Vec3::new(self.radius, self.radius, self.radius);
+        let start = Aabb::new(self.center - extent, self.center + extent);
+        let end = self.center(1.0);
+        start.union(&Aabb::new(end - extent, end + extent))
+    }
 }
 
 pub struct Raytracer {
@@ -84,17 +135,34 @@ pub struct Raytracer {
     pipeline: Arc<ComputePipeline>,
     descriptor_set: Arc<PersistentDescriptorSet>,
 
-    data_buffer: Arc<CpuAccessibleBuffer<[u32]>>,
+    data_buffer: Arc<CpuAccessibleBuffer<[f32]>>,
+    accumulation_buffer: Arc<CpuAccessibleBuffer<[[f32; 4]]>>,
     config_buffer: Arc<CpuAccessibleBuffer<Config>>,
     scene_buffer: Arc<CpuAccessibleBuffer<[Sphere]>>,
+    vertex_buffer: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    triangle_buffer: Arc<CpuAccessibleBuffer<[Triangle]>>,
+    rect_buffer: Arc<CpuAccessibleBuffer<[Rect]>>,
+    instance_buffer: Arc<CpuAccessibleBuffer<[GpuInstance]>>,
+    volume_buffer: Arc<CpuAccessibleBuffer<[GpuVolume]>>,
+    light_buffer: Arc<CpuAccessibleBuffer<[u32]>>,
+    blue_noise_buffer: Arc<CpuAccessibleBuffer<[f32]>>,
+    material_buffer: Arc<CpuAccessibleBuffer<[GpuMaterial]>>,
+    bvh_buffer: Arc<CpuAccessibleBuffer<[BvhNode]>>,
+    primitive_buffer: Arc<CpuAccessibleBuffer<[u32]>>,
+    texture_buffer: Arc<CpuAccessibleBuffer<[GpuTexture]>>,
+    texel_buffer: Arc<CpuAccessibleBuffer<[u32]>>,
 
     total_pixels: u32,
+    sample_count: u32,
     samples_per_dispatch: u32,
-    pixels_per_dispatch: u32,
 
+    timeout: Option<Duration>,
     progress_bar: pbr::ProgressBar<Stdout>,
 }
 
+/// Generous enough for a dispatch on integrated GPUs, short enough to notice a hung device
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
 mod cs {
     vulkano_shaders::shader! {
         ty: "compute",
@@ -106,10 +174,33 @@ mod cs {
     }
 }
 
+/// Creates a storage buffer filled from `data`, an empty `data` results in a single zeroed
+/// element as Vulkan doesn't allow empty buffers
+fn storage_buffer<T, I>(
+    memory_allocator: &StandardMemoryAllocator,
+    data: I,
+) -> Result<Arc<CpuAccessibleBuffer<[T]>>, RaytracerError>
+where
+    T: Pod + Send + Sync,
+    I: ExactSizeIterator<Item = T>,
+{
+    let usage = BufferUsage {
+        storage_buffer: true,
+        ..BufferUsage::empty()
+    };
+
+    let buffer = if data.len() == 0 {
+        CpuAccessibleBuffer::from_iter(memory_allocator, usage, false, [T::zeroed()])?
+    } else {
+        CpuAccessibleBuffer::from_iter(memory_allocator, usage, false, data)?
+    };
+
+    Ok(buffer)
+}
+
 impl Raytracer {
-    pub fn new(config: Config, spheres: Vec<Sphere>) -> Raytracer {
-        // Create instance
-        let library = VulkanLibrary::new().unwrap();
+    fn create_instance() -> Result<Arc<Instance>, RaytracerError> {
+        let library = VulkanLibrary::new()?;
         let instance = Instance::new(
             library,
             InstanceCreateInfo {
@@ -117,18 +208,35 @@ impl Raytracer {
                 enumerate_portability: true,
                 ..Default::default()
             },
-        )
-        .unwrap();
+        )?;
 
-        // Choose which physical device to use
-        let device_extensions = DeviceExtensions {
+        Ok(instance)
+    }
+
+    fn device_extensions() -> DeviceExtensions {
+        DeviceExtensions {
             khr_storage_buffer_storage_class: true,
             ..DeviceExtensions::empty()
-        };
-        let (physical_device, queue_family_index) = instance
+        }
+    }
+
+    /// Picks the most capable device that supports the extensions and has a compute queue,
+    /// `device_index` restricts the choice to a single device
+    fn select_physical_device(
+        instance: &Arc<Instance>,
+        device_extensions: &DeviceExtensions,
+        device_index: Option<usize>,
+    ) -> Result<(Arc<PhysicalDevice>, u32), RaytracerError> {
+        let physical_devices = instance
             .enumerate_physical_devices()
-            .unwrap()
-            .filter(|p| p.supported_extensions().contains(&device_extensions))
+            .map_err(RaytracerError::DeviceEnumeration)?;
+        let device_count = physical_devices.len();
+
+        physical_devices
+            .enumerate()
+            .filter(|(i, _)| device_index.map_or(true, |index| index == *i))
+            .map(|(_, p)| p)
+            .filter(|p| p.supported_extensions().contains(device_extensions))
             .filter_map(|p| {
                 // The Vulkan specs guarantee that a compliant implementation must provide at least one queue
                 // that supports compute operations.
@@ -145,7 +253,58 @@ impl Raytracer {
                 PhysicalDeviceType::Other => 4,
                 _ => 5,
             })
-            .unwrap();
+            .ok_or(match device_index {
+                Some(index) => RaytracerError::UnsuitableDevice {
+                    index,
+                    device_count,
+                },
+                None => RaytracerError::NoComputeDevice,
+            })
+    }
+
+    pub fn new(config: Config, scene: Scene) -> Result<Raytracer, RaytracerError> {
+        Raytracer::with_device(config, scene, None)
+    }
+
+    /// Names of the physical devices, in the order expected by `with_device`
+    pub fn list_devices() -> Result<Vec<String>, RaytracerError> {
+        let devices = Raytracer::create_instance()?
+            .enumerate_physical_devices()
+            .map_err(RaytracerError::DeviceEnumeration)?
+            .map(|p| {
+                format!(
+                    "{} (type: {:?})",
+                    p.properties().device_name,
+                    p.properties().device_type
+                )
+            })
+            .collect();
+
+        Ok(devices)
+    }
+
+    /// Like `new` but renders on the physical device at `device_index` instead of picking the
+    /// most capable one
+    pub fn with_device(
+        config: Config,
+        scene: Scene,
+        device_index: Option<usize>,
+    ) -> Result<Raytracer, RaytracerError> {
+        // The shader indexes the output with a uint, three floats per pixel
+        let data_len = config.width as u64 * config.height as u64 * 3;
+        if data_len > u32::MAX as u64 {
+            return Err(RaytracerError::ImageTooLarge {
+                width: config.width,
+                height: config.height,
+            });
+        }
+
+        let instance = Raytracer::create_instance()?;
+
+        // Choose which physical device to use
+        let device_extensions = Raytracer::device_extensions();
+        let (physical_device, queue_family_index) =
+            Raytracer::select_physical_device(&instance, &device_extensions, device_index)?;
 
         println!(
             "Using device: {} (type: {:?})",
@@ -164,11 +323,12 @@ impl Raytracer {
                 }],
                 ..Default::default()
             },
-        )
-        .unwrap();
+        )?;
 
         // Pick the first queue
-        let queue = queues.next().unwrap();
+        let queue = queues
+            .next()
+            .expect("a queue was requested from the compute family");
         //
         // Create Allocators
         let memory_allocator = StandardMemoryAllocator::new_default(device.clone());
@@ -177,21 +337,39 @@ impl Raytracer {
             StandardCommandBufferAllocator::new(device.clone(), Default::default());
 
         // Create bufferrs
-        let data_buffer: Arc<CpuAccessibleBuffer<[u32]>> = {
+        let data_buffer: Arc<CpuAccessibleBuffer<[f32]>> = {
             unsafe {
                 CpuAccessibleBuffer::uninitialized_array(
                     &memory_allocator,
-                    (config.width * config.height * 3) as u64,
+                    data_len,
                     BufferUsage {
                         storage_buffer: true,
                         ..BufferUsage::empty()
                     },
                     false,
-                )
-                .unwrap()
+                )?
+            }
+        };
+
+        let accumulation_buffer: Arc<CpuAccessibleBuffer<[[f32; 4]]>> = {
+            unsafe {
+                CpuAccessibleBuffer::uninitialized_array(
+                    &memory_allocator,
+                    data_len / 3,
+                    BufferUsage {
+                        storage_buffer: true,
+                        ..BufferUsage::empty()
+                    },
+                    false,
+                )?
             }
         };
 
+        let bvh = scene.build_bvh();
+        let config = Config {
+            num_bvh_nodes: bvh.nodes().len() as u32,
+            ..config
+        };
         let config_buffer = {
             CpuAccessibleBuffer::from_data(
                 &memory_allocator,
@@ -201,38 +379,63 @@ impl Raytracer {
                 },
                 false,
                 config,
-            )
-            .unwrap()
+            )?
         };
 
-        let scene_buffer = {
-            CpuAccessibleBuffer::from_iter(
-                &memory_allocator,
-                BufferUsage {
-                    storage_buffer: true,
-                    ..BufferUsage::empty()
-                },
-                false,
-                spheres,
-            )
-            .unwrap()
+        let scene_buffer = storage_buffer(&memory_allocator, scene.spheres().iter().copied())?;
+        let vertex_buffer = storage_buffer(&memory_allocator, scene.vertices().iter().copied())?;
+        let triangle_buffer = storage_buffer(&memory_allocator, scene.triangles().iter().copied())?;
+        let rect_buffer = storage_buffer(&memory_allocator, scene.rects().iter().copied())?;
+        let instance_buffer = storage_buffer(
+            &memory_allocator,
+            scene
+                .instances()
+                .iter()
+                .map(|instance| instance.lower(bvh.roots()[instance.object().index() + 1])),
+        )?;
+        let volume_buffer = storage_buffer(
+            &memory_allocator,
+            scene
+                .volumes()
+                .iter()
+                .map(|volume| volume.lower(bvh.roots()[volume.object().index() + 1])),
+        )?;
+        let light_buffer = storage_buffer(&memory_allocator, scene.lights().into_iter())?;
+        let blue_noise = match Sampler::from_config(config.sampler_mode) {
+            Sampler::BlueNoise => sampler::blue_noise(),
+            _ => Vec::new(),
         };
+        let blue_noise_buffer = storage_buffer(&memory_allocator, blue_noise.into_iter())?;
+        let material_buffer = storage_buffer(
+            &memory_allocator,
+            scene.materials().iter().map(|material| material.lower()),
+        )?;
+        let bvh_buffer = storage_buffer(&memory_allocator, bvh.nodes().iter().copied())?;
+        let primitive_buffer = storage_buffer(&memory_allocator, bvh.order().iter().copied())?;
+        let (textures, texels) = texture::lower(scene.textures());
+        let texture_buffer = storage_buffer(&memory_allocator, textures.into_iter())?;
+        let texel_buffer = storage_buffer(&memory_allocator, texels.into_iter())?;
 
         // Create shader & pipeline
         let pipeline = {
-            let shader = cs::load(device.clone()).unwrap();
+            let shader = cs::load(device.clone())?;
             ComputePipeline::new(
                 device.clone(),
-                shader.entry_point("main").unwrap(),
+                shader
+                    .entry_point("main")
+                    .expect("compute.glsl defines a main function"),
                 &(),
                 None,
                 |_| {},
-            )
-            .unwrap()
+            )?
         };
 
         // Bind buffers
-        let layout = pipeline.layout().set_layouts().get(0).unwrap();
+        let layout = pipeline
+            .layout()
+            .set_layouts()
+            .get(0)
+            .expect("compute.glsl declares descriptor set 0");
         let set = PersistentDescriptorSet::new(
             &descriptor_set_allocator,
             layout.clone(),
@@ -240,13 +443,24 @@ impl Raytracer {
                 WriteDescriptorSet::buffer(0, data_buffer.clone()),
                 WriteDescriptorSet::buffer(1, config_buffer.clone()),
                 WriteDescriptorSet::buffer(2, scene_buffer.clone()),
+                WriteDescriptorSet::buffer(3, material_buffer.clone()),
+                WriteDescriptorSet::buffer(4, bvh_buffer.clone()),
+                WriteDescriptorSet::buffer(5, accumulation_buffer.clone()),
+                WriteDescriptorSet::buffer(6, vertex_buffer.clone()),
+                WriteDescriptorSet::buffer(7, triangle_buffer.clone()),
+                WriteDescriptorSet::buffer(8, primitive_buffer.clone()),
+                WriteDescriptorSet::buffer(9, texture_buffer.clone()),
+                WriteDescriptorSet::buffer(10, texel_buffer.clone()),
+                WriteDescriptorSet::buffer(11, rect_buffer.clone()),
+                WriteDescriptorSet::buffer(12, instance_buffer.clone()),
+                WriteDescriptorSet::buffer(13, volume_buffer.clone()),
+                WriteDescriptorSet::buffer(14, light_buffer.clone()),
+                WriteDescriptorSet::buffer(15, blue_noise_buffer.clone()),
             ],
-        )
-        .unwrap();
+        )?;
 
         let total_pixels = config.width * config.height;
-        let samples_per_dispatch = (1024 * 10000) / config.max_bounces;
-        let pixels_per_dispatch = samples_per_dispatch / config.sample_count;
+        let samples_per_dispatch = (1024 * 10000) / config.max_bounces.max(1);
 
         println!(
             "Dimensions: [{} x {}] -> {}",
@@ -254,12 +468,10 @@ impl Raytracer {
         );
         println!("Sample count: {}", config.sample_count);
         println!("Max bounces: {}", config.max_bounces);
+        println!("{}", bvh.stats());
         println!("Samples per dispatch: {}", samples_per_dispatch);
-        println!("Pixels per dispatch: {}", pixels_per_dispatch);
 
-        let mut progress_bar = ProgressBar::new((total_pixels / pixels_per_dispatch) as u64);
-        progress_bar.format("╢▌▌░╟");
-        return Raytracer {
+        Ok(Raytracer {
             device: device.clone(),
             queue: queue.clone(),
             command_buffer_allocator: command_buffer_allocator,
@@ -267,41 +479,75 @@ impl Raytracer {
             descriptor_set: set.clone(),
 
             data_buffer: data_buffer.clone(),
+            accumulation_buffer: accumulation_buffer.clone(),
             config_buffer: config_buffer.clone(),
             scene_buffer: scene_buffer.clone(),
+            vertex_buffer: vertex_buffer.clone(),
+            triangle_buffer: triangle_buffer.clone(),
+            rect_buffer: rect_buffer.clone(),
+            instance_buffer: instance_buffer.clone(),
+            volume_buffer: volume_buffer.clone(),
+            light_buffer: light_buffer.clone(),
+            blue_noise_buffer: blue_noise_buffer.clone(),
+            material_buffer: material_buffer.clone(),
+            bvh_buffer: bvh_buffer.clone(),
+            primitive_buffer: primitive_buffer.clone(),
+            texture_buffer: texture_buffer.clone(),
+            texel_buffer: texel_buffer.clone(),
 
             total_pixels,
+            sample_count: config.sample_count,
             samples_per_dispatch,
-            pixels_per_dispatch,
 
-            progress_bar,
-        };
+            timeout: Some(DEFAULT_TIMEOUT),
+            progress_bar: progressive::progress_bar(0),
+        })
     }
-    pub fn raytrace(&mut self) -> Vec<u32> {
-        let mut i = 0;
 
-        while i + self.pixels_per_dispatch < self.total_pixels {
-            self.dispatch(i, self.pixels_per_dispatch);
-            i += self.pixels_per_dispatch;
-        }
+    /// Sets how long a single dispatch may run before `raytrace` gives up, `None` waits forever
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
 
-        if i < self.total_pixels {
-            self.dispatch(i, self.total_pixels - i);
-        }
+    /// Renders every pixel with `Config::sample_count` samples in a single pass.
+    ///
+    /// Returns the linear RGB radiance of each pixel starting from the bottom row, see
+    /// `image::write` to save it.
+    pub fn raytrace(&mut self) -> Result<Vec<f32>, RaytracerError> {
+        let options = ProgressiveOptions {
+            samples_per_pass: self.sample_count,
+            ..Default::default()
+        };
+
+        self.raytrace_progressive(&options, |_| ControlFlow::Continue(()))
+    }
 
-        self.progress_bar.finish_print("Finished");
-        return self.data_buffer.read().unwrap().to_vec();
+    /// Renders in passes of `options.samples_per_pass` samples per pixel, calling `on_pass` with
+    /// the image so far after each one, until a stop condition of `options` is met
+    pub fn raytrace_progressive<F>(
+        &mut self,
+        options: &ProgressiveOptions,
+        on_pass: F,
+    ) -> Result<Vec<f32>, RaytracerError>
+    where
+        F: FnMut(&Pass) -> ControlFlow<()>,
+    {
+        let sample_count = self.sample_count;
+        progressive::render(self, sample_count, options, on_pass)
     }
 
-    fn dispatch(&mut self, index: u32, num_pixels: u32) {
-        let mut builder = match AutoCommandBufferBuilder::primary(
+    fn dispatch(
+        &mut self,
+        index: u32,
+        num_pixels: u32,
+        sample_offset: u32,
+        sample_count: u32,
+    ) -> Result<(), RaytracerError> {
+        let mut builder = AutoCommandBufferBuilder::primary(
             &self.command_buffer_allocator,
             self.queue.clone().queue_family_index(),
             CommandBufferUsage::OneTimeSubmit,
-        ) {
-            Err(why) => panic!("Failed to one time submit: {}", why),
-            Ok(val) => val,
-        };
+        )?;
 
         builder
             .bind_pipeline_compute(self.pipeline.clone())
@@ -314,26 +560,65 @@ impl Raytracer {
             .push_constants(
                 self.pipeline.layout().clone(),
                 0,
-                cs::ty::PushConstantData { index },
+                cs::ty::PushConstantData {
+                    index,
+                    sample_offset,
+                    sample_count,
+                },
             )
-            .dispatch([num_pixels, 1, 1])
-            .unwrap();
-
-        let command_buffer = builder.build().unwrap();
-
-        let future = match sync::now(self.device.clone())
-            .then_execute(self.queue.clone(), command_buffer)
-            .unwrap()
-            .then_signal_fence_and_flush()
-        {
-            Err(why) => panic!("Failed to future: {}", why),
-            Ok(future) => future,
-        };
-        match future.wait(None) {
-            Err(why) => panic!("Flush error: {}", why),
+            .dispatch([num_pixels, 1, 1])?;
+
+        let command_buffer = builder.build()?;
+
+        let future = sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?;
+
+        match future.wait(self.timeout) {
+            Err(FlushError::Timeout) => Err(RaytracerError::Timeout(
+                self.timeout
+                    .expect("waiting without a timeout can't time out"),
+            )),
+            Err(why) => Err(why.into()),
             Ok(_) => {
-                self.progress_bar.inc();
+                self.progress_bar
+                    .add(num_pixels as u64 * sample_count as u64);
+                Ok(())
             }
-        };
+        }
+    }
+}
+
+impl PassRenderer for Raytracer {
+    type Error = RaytracerError;
+
+    fn begin(&mut self, target_samples: u32) {
+        self.progress_bar =
+            progressive::progress_bar(self.total_pixels as u64 * target_samples as u64);
+    }
+
+    fn render_pass(&mut self, sample_offset: u32, sample_count: u32) -> Result<(), RaytracerError> {
+        let pixels_per_dispatch = (self.samples_per_dispatch / sample_count).max(1);
+
+        let mut i = 0;
+        while i < self.total_pixels {
+            let num_pixels = pixels_per_dispatch.min(self.total_pixels - i);
+            self.dispatch(i, num_pixels, sample_offset, sample_count)?;
+            i += num_pixels;
+        }
+
+        Ok(())
+    }
+
+    fn pixels(&self) -> Result<Vec<f32>, RaytracerError> {
+        Ok(self.data_buffer.read()?.to_vec())
+    }
+
+    fn accumulation(&self) -> Result<Vec<[f32; 4]>, RaytracerError> {
+        Ok(self.accumulation_buffer.read()?.to_vec())
+    }
+
+    fn finish(&mut self) {
+        self.progress_bar.finish_println("Finished\n");
     }
 }
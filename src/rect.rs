@@ -0,0 +1,113 @@
+use crate::bvh::Aabb;
+use crate::material::MaterialId;
+use crate::vec3::Vec3;
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+
+/// Rectangles are given this much thickness so their bounds never have a zero extent
+const THICKNESS: f32 = 1e-4;
+
+/// Plane an axis-aligned rectangle lies in, named after the two axes it spans
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum Plane {
+    Xy,
+    Xz,
+    Yz,
+}
+
+/// Axis-aligned rectangle as laid out in `compute.glsl` (std430), the `xy_rect`, `xz_rect` and
+/// `yz_rect` of the second book
+#[derive(Pod, Zeroable, Copy, Clone, Default)]
+#[repr(C)]
+pub struct Rect {
+    /// Corners along the two axes the rectangle spans, x and y for an xy rectangle. The texture
+    /// coordinates go from (0, 0) at `min` to (1, 1) at `max`.
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    /// Coordinate along `axis`
+    pub k: f32,
+    /// Index of the axis perpendicular to the rectangle
+    pub axis: u32,
+
+    /// Index into `Scene::materials`
+    pub material: u32,
+    /// The outward normal points towards -`axis` when nonzero, +`axis` otherwise
+    pub flip: u32,
+}
+
+impl Plane {
+    /// Indices of the two axes the plane spans, then of the one perpendicular to it
+    pub fn axes(self) -> [usize; 3] {
+        match self {
+            Plane::Xy => [0, 1, 2],
+            Plane::Xz => [0, 2, 1],
+            Plane::Yz => [1, 2, 0],
+        }
+    }
+}
+
+impl Rect {
+    /// Rectangle at `k` along the axis perpendicular to `plane`, facing towards +axis
+    pub fn new(plane: Plane, min: [f32; 2], max: [f32; 2], k: f32, material: MaterialId) -> Rect {
+        Rect {
+            min,
+            max,
+            k,
+            axis: plane.axes()[2] as u32,
+            material: material.0,
+            flip: 0,
+        }
+    }
+
+    /// The same rectangle facing the other way
+    pub fn flipped(self) -> Rect {
+        Rect {
+            flip: (self.flip == 0) as u32,
+            ..self
+        }
+    }
+
+    /// The six faces of the box between `min` and `max`, facing outwards
+    pub fn cuboid(min: Vec3, max: Vec3, material: MaterialId) -> [Rect; 6] {
+        let face = |plane: Plane, k| {
+            let [a, b, _] = plane.axes();
+            Rect::new(plane, [min[a], min[b]], [max[a], max[b]], k, material)
+        };
+
+        [
+            face(Plane::Xy, max.z),
+            face(Plane::Xy, min.z).flipped(),
+            face(Plane::Xz, max.y),
+            face(Plane::Xz, min.y).flipped(),
+            face(Plane::Yz, max.x),
+            face(Plane::Yz, min.x).flipped(),
+        ]
+    }
+
+    pub fn plane(&self) -> Plane {
+        match self.axis {
+            0 => Plane::Yz,
+            1 => Plane::Xz,
+            _ => Plane::Xy,
+        }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        let mut normal = [0.0; 3];
+        normal[self.plane().axes()[2]] = if self.flip == 0 { 1.0 } else { -1.0 };
+        Vec3::from(normal)
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let [a, b, axis] = self.plane().axes();
+        let mut min = [0.0; 3];
+        let mut max = [0.0; 3];
+        min[a] = self.min[0];
+        min[b] = self.min[1];
+        min[axis] = self.k - THICKNESS;
+        max[a] = self.max[0];
+        max[b] = self.max[1];
+        max[axis] = self.k + THICKNESS;
+        Aabb::new(Vec3::from(min), Vec3::from(max))
+    }
+}
@@ -0,0 +1,45 @@
+/// Random numbers of a single sample, the 32 bit PCG (RXS-M-XS) generator. Every pixel and
+/// sample index gets its own stream, so renders only depend on `Config::seed` and not on the
+/// order the pixels are rendered in. `compute.glsl` mirrors it bit for bit.
+#[derive(Clone, Debug)]
+pub struct Pcg {
+    state: u32,
+}
+
+impl Pcg {
+    /// Stream of sample `sample` of the pixel at `index` (counted from the bottom row)
+    pub fn new(seed: u32, index: u32, sample: u32) -> Pcg {
+        Pcg {
+            state: hash(seed.wrapping_add(hash(sample.wrapping_add(hash(index))))),
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state = step(self.state);
+        output(self.state)
+    }
+
+    /// Uniform in 0..1, from the top 24 bits so that every value is exact
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+
+    /// Uniform in `0..n`, `n` must be greater than 0
+    pub fn below(&mut self, n: u32) -> u32 {
+        ((self.next_f32() * n as f32) as u32).min(n - 1)
+    }
+}
+
+fn step(state: u32) -> u32 {
+    state.wrapping_mul(747796405).wrapping_add(2891336453)
+}
+
+fn output(state: u32) -> u32 {
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// A single round of the generator, scrambles the seeds
+pub(crate) fn hash(x: u32) -> u32 {
+    output(step(x))
+}
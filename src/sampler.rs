@@ -0,0 +1,467 @@
+use crate::raytracer::Config;
+use crate::rng::{self, Pcg};
+use crate::vec3::Vec3;
+use serde::Deserialize;
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+/// How the numbers of the dimensions of a sample are picked: the position in the pixel, on the
+/// lens, the time and the scattering at every bounce. `compute.glsl` mirrors every mode.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Sampler {
+    /// Independent numbers from the `Pcg` of the sample
+    Random,
+    /// Correlated multi-jittered sampling: the samples of a pixel fall in distinct strata of
+    /// every dimension and, for pairs of dimensions, in distinct cells of a grid. The strata are
+    /// laid out for `Config::sample_count` samples, further samples start another set.
+    Stratified,
+    /// The Halton sequence with the digits shuffled per pixel and dimension, which keeps the
+    /// dimensions of large bases from lining up. Dimensions past the first 32 primes fall back
+    /// to `Random`.
+    Halton,
+    /// 2D Sobol points, Owen scrambled and shuffled per pixel and pair of dimensions
+    #[default]
+    Sobol,
+    /// The points of `Sobol` shared by every pixel, shifted by a blue noise mask so that the
+    /// error of neighbouring pixels differs as much as possible
+    BlueNoise,
+}
+
+/// The numbers of one sample of a pixel, for the `Sampler` of the render. Dimensions 0 and 1
+/// are the position in the pixel, 2 and 3 on the lens and 4 the time, then every bounce takes
+/// three: a pair for the direction and one more for the choices of the material.
+pub struct PixelSample<'a> {
+    sampler: Sampler,
+    seed: u32,
+    x: u32,
+    y: u32,
+    pixel: u32,
+    index: u32,
+    sample_count: u32,
+    blue_noise: &'a [f32],
+    rng: Pcg,
+}
+
+// Must match the constants in compute.glsl
+const RANDOM: u32 = 0;
+const STRATIFIED: u32 = 1;
+const HALTON: u32 = 2;
+const SOBOL: u32 = 3;
+const BLUE_NOISE: u32 = 4;
+
+pub(crate) const PIXEL_DIMENSION: u32 = 0;
+pub(crate) const LENS_DIMENSION: u32 = 2;
+pub(crate) const TIME_DIMENSION: u32 = 4;
+pub(crate) const BOUNCE_DIMENSION: u32 = 5;
+
+/// Width and height of the mask of `blue_noise`, repeated over the image
+pub const BLUE_NOISE_SIZE: usize = 64;
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+impl Sampler {
+    pub const NAMES: [&'static str; 5] = ["random", "stratified", "halton", "sobol", "blue-noise"];
+
+    /// Value of `Config::sampler_mode`
+    pub fn lower(self) -> u32 {
+        match self {
+            Sampler::Random => RANDOM,
+            Sampler::Stratified => STRATIFIED,
+            Sampler::Halton => HALTON,
+            Sampler::Sobol => SOBOL,
+            Sampler::BlueNoise => BLUE_NOISE,
+        }
+    }
+
+    pub(crate) fn from_config(value: u32) -> Sampler {
+        match value {
+            RANDOM => Sampler::Random,
+            STRATIFIED => Sampler::Stratified,
+            HALTON => Sampler::Halton,
+            BLUE_NOISE => Sampler::BlueNoise,
+            _ => Sampler::Sobol,
+        }
+    }
+}
+
+impl FromStr for Sampler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "random" => Ok(Sampler::Random),
+            "stratified" => Ok(Sampler::Stratified),
+            "halton" => Ok(Sampler::Halton),
+            "sobol" => Ok(Sampler::Sobol),
+            "blue-noise" => Ok(Sampler::BlueNoise),
+            _ => Err(format!(
+                "unknown sampler '{}', expected one of: {}",
+                s,
+                Sampler::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl<'a> PixelSample<'a> {
+    /// Sample `index` of the pixel at `x`, `y` (from the bottom row). `blue_noise` is the mask
+    /// of `blue_noise`, only read by `Sampler::BlueNoise`.
+    pub fn new(config: &Config, blue_noise: &'a [f32], x: u32, y: u32, index: u32) -> Self {
+        let pixel = y * config.width + x;
+        PixelSample {
+            sampler: Sampler::from_config(config.sampler_mode),
+            seed: config.seed,
+            x,
+            y,
+            pixel,
+            index,
+            sample_count: config.sample_count.max(1),
+            blue_noise,
+            rng: Pcg::new(config.seed, pixel, index),
+        }
+    }
+
+    /// Number in 0..1 for `dimension`
+    pub fn get_1d(&mut self, dimension: u32) -> f32 {
+        match self.sampler {
+            Sampler::Random => self.rng.next_f32(),
+            Sampler::Stratified => {
+                let (index, pattern) = self.stratum(dimension);
+                let n = self.sample_count;
+                let stratum = permute(index, n, pattern.wrapping_mul(0x68bc21eb));
+                (stratum as f32 + jitter(index, pattern.wrapping_mul(0x967a889b))) / n as f32
+            }
+            Sampler::Halton => self.halton(dimension),
+            Sampler::Sobol => self.sobol(dimension)[0],
+            Sampler::BlueNoise => self.shifted_sobol(dimension)[0],
+        }
+    }
+
+    /// Pair of numbers in 0..1 for `dimension` and the next one
+    pub fn get_2d(&mut self, dimension: u32) -> [f32; 2] {
+        match self.sampler {
+            Sampler::Random => {
+                let x = self.rng.next_f32();
+                [x, self.rng.next_f32()]
+            }
+            Sampler::Stratified => {
+                let (index, pattern) = self.stratum(dimension);
+                multi_jittered(index, self.sample_count, pattern)
+            }
+            Sampler::Halton => [self.halton(dimension), self.halton(dimension + 1)],
+            Sampler::Sobol => self.sobol(dimension),
+            Sampler::BlueNoise => self.shifted_sobol(dimension),
+        }
+    }
+
+    /// Numbers of bounce `bounce`: a pair for the direction and one for the choices
+    pub fn get_bounce(&mut self, bounce: u32) -> [f32; 3] {
+        let dimension = BOUNCE_DIMENSION + 3 * bounce;
+        let [u, v] = self.get_2d(dimension);
+        [u, v, self.get_1d(dimension + 2)]
+    }
+
+    /// Generator of the sample for what isn't sampled by dimension, like distances in volumes
+    pub fn rng(&mut self) -> &mut Pcg {
+        &mut self.rng
+    }
+
+    /// Index among the `sample_count` strata and the pattern they're shuffled by
+    fn stratum(&self, dimension: u32) -> (u32, u32) {
+        let set = self.index / self.sample_count;
+        let pattern = pixel_pattern(self.seed, self.pixel, dimension);
+        (
+            self.index % self.sample_count,
+            rng::hash(pattern.wrapping_add(set)),
+        )
+    }
+
+    fn halton(&mut self, dimension: u32) -> f32 {
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => {
+                let pattern = pixel_pattern(self.seed, self.pixel, dimension);
+                radical_inverse(self.index, base, pattern)
+            }
+            None => self.rng.next_f32(),
+        }
+    }
+
+    fn sobol(&self, dimension: u32) -> [f32; 2] {
+        owen_sobol(self.index, pixel_pattern(self.seed, self.pixel, dimension))
+    }
+
+    fn shifted_sobol(&self, dimension: u32) -> [f32; 2] {
+        let size = BLUE_NOISE_SIZE as u32;
+        let pattern = pattern(self.seed, dimension);
+        let [u, v] = owen_sobol(self.index, pattern);
+
+        // Both numbers read the mask at a different offset, the same for every pixel
+        let offset = rng::hash(pattern);
+        let mask = |offset: u32| {
+            let x = (self.x + (offset & 0xffff)) % size;
+            let y = (self.y + (offset >> 16)) % size;
+            self.blue_noise[(y * size + x) as usize]
+        };
+        [wrap(u + mask(offset)), wrap(v + mask(rng::hash(offset)))]
+    }
+}
+
+/// Point of the unit disk on the xy plane for a pair of numbers in 0..1, Shirley and Chiu's
+/// concentric mapping which keeps the strata of the square
+pub(crate) fn square_to_disk([u, v]: [f32; 2]) -> Vec3 {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::ZERO;
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, (PI / 4.0) * (b / a))
+    } else {
+        (b, (PI / 2.0) - (PI / 4.0) * (a / b))
+    };
+    let (sin, cos) = theta.sin_cos();
+    Vec3::new(r * cos, r * sin, 0.0)
+}
+
+/// Point of the unit sphere for a pair of numbers in 0..1, uniform over its surface
+pub(crate) fn square_to_sphere([u, v]: [f32; 2]) -> Vec3 {
+    let z = 1.0 - 2.0 * u;
+    let radial = (1.0 - z * z).max(0.0).sqrt();
+    let (sin, cos) = (2.0 * PI * v).sin_cos();
+    Vec3::new(radial * cos, radial * sin, z)
+}
+
+/// Seed shared by every pixel for `dimension`
+fn pattern(seed: u32, dimension: u32) -> u32 {
+    rng::hash(seed.wrapping_add(rng::hash(dimension)))
+}
+
+fn pixel_pattern(seed: u32, pixel: u32, dimension: u32) -> u32 {
+    rng::hash(pattern(seed, dimension).wrapping_add(rng::hash(pixel)))
+}
+
+/// The top 24 bits in 0..1
+fn to_unit(x: u32) -> f32 {
+    (x >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+/// Back into 0..1 after an offset, `x` is in 0..2
+fn wrap(x: f32) -> f32 {
+    let x = if x >= 1.0 { x - 1.0 } else { x };
+    x.min(1.0 - f32::EPSILON / 2.0)
+}
+
+/// Permutation of `0..length` picked by `pattern`, from Kensler's "Correlated Multi-Jittered
+/// Sampling"
+fn permute(mut i: u32, length: u32, pattern: u32) -> u32 {
+    let p = pattern;
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+
+    (i.wrapping_add(p)) % length
+}
+
+/// Offset in 0..1 within a stratum, Kensler's `randfloat`
+fn jitter(mut i: u32, p: u32) -> f32 {
+    i ^= p;
+    i ^= i >> 17;
+    i ^= i >> 10;
+    i = i.wrapping_mul(0xb36534e5);
+    i ^= i >> 12;
+    i ^= i >> 21;
+    i = i.wrapping_mul(0x93fc4795);
+    i ^= 0xdf6e307f;
+    i ^= i >> 17;
+    i = i.wrapping_mul(1 | p >> 18);
+    to_unit(i)
+}
+
+/// Sample `index` of `count` over a grid of about `count` cells, one per row and column of
+/// the finer grid within them
+fn multi_jittered(index: u32, count: u32, pattern: u32) -> [f32; 2] {
+    let m = ((count as f32).sqrt() as u32).max(1);
+    let n = count.div_ceil(m);
+
+    let s = permute(index, count, pattern.wrapping_mul(0x51633e2d));
+    let (column, row) = (s % m, s / m);
+    let sx = permute(column, m, pattern.wrapping_mul(0x68bc21eb));
+    let sy = permute(row, n, pattern.wrapping_mul(0x02e5be93));
+    let jx = jitter(s, pattern.wrapping_mul(0x967a889b));
+    let jy = jitter(s, pattern.wrapping_mul(0x368cc8b7));
+
+    [
+        (column as f32 + (sy as f32 + jx) / n as f32) / m as f32,
+        (row as f32 + (sx as f32 + jy) / m as f32) / n as f32,
+    ]
+}
+
+/// Digits of `index` in `base` mirrored around the point, each position shuffled by its own
+/// permutation. The zeros past the last digit are shuffled too, as long as they add anything.
+fn radical_inverse(mut index: u32, base: u32, pattern: u32) -> f32 {
+    let inverse = 1.0 / base as f32;
+    let (mut scale, mut result) = (1.0, 0.0);
+    let mut position = 0;
+    loop {
+        scale *= inverse;
+        if index == 0 && scale < 1.0 / (1u32 << 24) as f32 {
+            break;
+        }
+
+        let digit = permute(
+            index % base,
+            base,
+            rng::hash(pattern.wrapping_add(position)),
+        );
+        result += scale * digit as f32;
+        index /= base;
+        position += 1;
+    }
+    result.min(1.0 - f32::EPSILON / 2.0)
+}
+
+/// The first two dimensions of the Sobol sequence
+fn sobol(index: u32) -> [u32; 2] {
+    let (mut i, mut v, mut y) = (index, 1u32 << 31, 0);
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    [index.reverse_bits(), y]
+}
+
+/// Owen scrambling of the bits of `x`, from Burley's "Practical Hash-based Owen Scrambling"
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+/// Sobol point `index` of the sequence shuffled and scrambled by `pattern`
+fn owen_sobol(index: u32, pattern: u32) -> [f32; 2] {
+    let [x, y] = sobol(owen_scramble(index, pattern));
+    [
+        to_unit(owen_scramble(x, rng::hash(pattern))),
+        to_unit(owen_scramble(y, rng::hash(pattern ^ 1))),
+    ]
+}
+
+/// Threshold mask of `BLUE_NOISE_SIZE`² values in 0..1 from Ulichney's void-and-cluster
+/// method, the same every time. Neighbouring values are as far apart as possible.
+pub fn blue_noise() -> Vec<f32> {
+    const N: usize = BLUE_NOISE_SIZE;
+    let count = N * N;
+
+    // Energy a point adds around it, a Gaussian of the distance wrapping around the edges
+    let sigma: f32 = 1.5;
+    let kernel: Vec<f32> = (0..count)
+        .map(|i| {
+            let (x, y) = (i % N, i / N);
+            let (dx, dy) = (x.min(N - x) as f32, y.min(N - y) as f32);
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+
+    let mut points = vec![false; count];
+    let mut energy = vec![0.0f32; count];
+    let toggle = |points: &mut [bool], energy: &mut [f32], i: usize| {
+        let sign = if points[i] { -1.0 } else { 1.0 };
+        points[i] = !points[i];
+        let (x, y) = (i % N, i / N);
+        for (j, e) in energy.iter_mut().enumerate() {
+            let (dx, dy) = ((j % N + N - x) % N, (j / N + N - y) % N);
+            *e += sign * kernel[dy * N + dx];
+        }
+    };
+    // The point in the densest area, or the pixel without a point in the emptiest one
+    let extreme = |points: &[bool], energy: &[f32], point: bool| -> usize {
+        let candidates = (0..count).filter(|&i| points[i] == point);
+        if point {
+            candidates.max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        } else {
+            candidates.min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        }
+        .unwrap()
+    };
+
+    // A tenth of the pixels at random, moved from the tightest cluster to the largest void
+    // until that doesn't change anything
+    let initial = count / 10;
+    let mut rng = Pcg::new(0, 0, 0);
+    let mut placed = 0;
+    while placed < initial {
+        let i = rng.below(count as u32) as usize;
+        if !points[i] {
+            toggle(&mut points, &mut energy, i);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = extreme(&points, &energy, true);
+        toggle(&mut points, &mut energy, cluster);
+        let void = extreme(&points, &energy, false);
+        toggle(&mut points, &mut energy, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    // Ranks of the initial points, removing the tightest cluster first
+    let mut rank = vec![0; count];
+    let (initial_points, initial_energy) = (points.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = extreme(&points, &energy, true);
+        toggle(&mut points, &mut energy, cluster);
+        rank[cluster] = r;
+    }
+
+    // Then the others, filling the largest void first. The pixel with the most empty pixels
+    // around it is also the one with the fewest points, so this covers the last half as well.
+    let (mut points, mut energy) = (initial_points, initial_energy);
+    for r in initial..count {
+        let void = extreme(&points, &energy, false);
+        toggle(&mut points, &mut energy, void);
+        rank[void] = r;
+    }
+
+    rank.iter()
+        .map(|&r| (r as f32 + 0.5) / count as f32)
+        .collect()
+}
@@ -0,0 +1,2083 @@
+use crate::bvh::{Aabb, Bvh};
+use crate::camera::{Camera, CameraError};
+use crate::gltf::{self, GltfError, GltfWarning};
+use crate::instance::{Instance, InstanceError, Object, ObjectId};
+use crate::material::{Material, MaterialError, MaterialId};
+use crate::mesh::{Mesh, MeshError, Triangle, Vertex};
+use crate::obj::{self, ObjError, ObjWarning};
+use crate::pdf::LightSampling;
+use crate::raytracer::{Config, Sphere};
+use crate::rect::{Plane, Rect};
+use crate::sampler::Sampler;
+use crate::texture::{self, ColorSpace, Image, Texture, TextureError, TextureId, WrapMode};
+use crate::tonemap::{ToneMapping, TransferFunction};
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+use crate::volume::{Fog, Volume, VolumeError};
+use rand::Rng;
+use ron::extensions::Extensions;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Everything needed to render an image, as written in a `.ron` or `.json` scene file. glTF
+/// files are scenes too, see `SceneDescription::load`.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    pub camera: CameraDescription,
+
+    #[serde(default)]
+    pub settings: RenderSettings,
+
+    /// What rays escaping the scene see, the sky gradient of the books by default
+    #[serde(default)]
+    pub background: Background,
+
+    /// Fog between the camera and the surfaces, none by default
+    #[serde(default)]
+    pub fog: Option<Fog>,
+
+    /// How the linear image is turned into display colors for PNG and PPM output
+    #[serde(default)]
+    pub tone_mapping: ToneMapping,
+
+    /// Textures by name, referenced by the materials
+    #[serde(default)]
+    pub textures: BTreeMap<String, TextureDescription>,
+
+    /// Materials by name, shared by any number of objects
+    pub materials: BTreeMap<String, Material<String>>,
+
+    pub spheres: Vec<SphereDescription>,
+
+    #[serde(default)]
+    pub rects: Vec<RectDescription>,
+
+    #[serde(default)]
+    pub boxes: Vec<BoxDescription>,
+
+    #[serde(default)]
+    pub meshes: Vec<MeshDescription>,
+
+    /// Meshes loaded from OBJ or glTF files
+    #[serde(default)]
+    pub models: Vec<ModelDescription>,
+
+    /// Geometry by name, only rendered through `instances`
+    #[serde(default)]
+    pub objects: BTreeMap<String, ObjectDescription>,
+
+    #[serde(default)]
+    pub instances: Vec<InstanceDescription>,
+
+    /// Smoke, mist and other media filling objects
+    #[serde(default)]
+    pub volumes: Vec<VolumeDescription>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+
+    #[serde(default = "default_vup")]
+    pub vup: Vec3,
+
+    /// Vertical field of view in degrees
+    pub vfov: f32,
+
+    #[serde(default)]
+    pub aperture: f32,
+
+    /// Distance to the plane in focus, focuses on `look_at` when omitted
+    #[serde(default)]
+    pub focus_dist: Option<f32>,
+
+    /// When the shutter opens and closes, within 0..=1. Moving spheres are blurred along the
+    /// part of their path in between.
+    #[serde(default)]
+    pub time0: f32,
+    #[serde(default = "default_time1")]
+    pub time1: f32,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub sample_count: u32,
+    pub max_bounces: u32,
+    pub light_sampling: LightSampling,
+    /// Renders with the same seed are identical, other seeds give independent noise
+    pub seed: u32,
+    pub sampler: Sampler,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum Background {
+    /// The same color in every direction, black for scenes only lit by their lights
+    Constant(Vec3),
+    /// Blends from `bottom` to `top` as rays point upwards
+    Gradient { bottom: Vec3, top: Vec3 },
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SphereDescription {
+    /// Center at time 0
+    pub center: Vec3,
+    pub radius: f32,
+    /// Name of an entry of `SceneDescription::materials`
+    pub material: String,
+
+    /// Center at time 1 of a moving sphere, or the distance it moves by: at most one of them
+    #[serde(default)]
+    pub center1: Option<Vec3>,
+    #[serde(default)]
+    pub velocity: Option<Vec3>,
+}
+
+/// See `Rect`
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RectDescription {
+    pub plane: Plane,
+    /// Corners along the two axes of `plane`, x and y for an xy rectangle
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    /// Coordinate along the remaining axis
+    pub k: f32,
+    /// Name of an entry of `SceneDescription::materials`
+    pub material: String,
+
+    /// Faces towards -z for an xy rectangle rather than +z, and so on
+    #[serde(default)]
+    pub flip: bool,
+}
+
+/// Axis-aligned box made of six rectangles facing outwards
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BoxDescription {
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Name of an entry of `SceneDescription::materials`
+    pub material: String,
+}
+
+/// See `Texture`, checkers reference other entries of `SceneDescription::textures` by name
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum TextureDescription {
+    /// PNG file, relative to the scene file
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        wrap: WrapMode,
+        #[serde(default)]
+        color_space: ColorSpace,
+    },
+    Solid {
+        color: Vec3,
+    },
+    Checker {
+        even: String,
+        odd: String,
+        #[serde(default = "default_texture_scale")]
+        scale: f32,
+    },
+    Noise {
+        #[serde(default = "default_texture_scale")]
+        scale: f32,
+    },
+    Turbulence {
+        #[serde(default = "default_texture_scale")]
+        scale: f32,
+        #[serde(default = "default_depth")]
+        depth: u32,
+    },
+    Marble {
+        #[serde(default = "default_texture_scale")]
+        scale: f32,
+        #[serde(default = "default_depth")]
+        depth: u32,
+    },
+}
+
+/// Triangle mesh written inline in the scene file, see `Mesh`
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeshDescription {
+    pub positions: Vec<Vec3>,
+    #[serde(default)]
+    pub normals: Vec<Vec3>,
+    #[serde(default)]
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<[u32; 3]>,
+    /// Name of an entry of `SceneDescription::materials`
+    pub material: String,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelDescription {
+    /// OBJ, glTF or GLB file, relative to the scene file
+    pub path: PathBuf,
+
+    /// Name of an entry of `SceneDescription::materials` replacing the materials of the model
+    #[serde(default)]
+    pub material: Option<String>,
+
+    #[serde(default = "default_scale")]
+    pub scale: Vec3,
+
+    /// Degrees around the x, then y, then z axis
+    #[serde(default)]
+    pub rotate: Vec3,
+
+    #[serde(default)]
+    pub translate: Vec3,
+}
+
+/// Geometry in its own object space, placed in the scene by `InstanceDescription`s. Materials
+/// are the ones of the scene.
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ObjectDescription {
+    pub spheres: Vec<SphereDescription>,
+    pub rects: Vec<RectDescription>,
+    pub boxes: Vec<BoxDescription>,
+    pub meshes: Vec<MeshDescription>,
+    pub models: Vec<ModelDescription>,
+}
+
+/// Copy of an object, scaled, then rotated and translated
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstanceDescription {
+    /// Name of an entry of `SceneDescription::objects`
+    pub object: String,
+
+    #[serde(default = "default_scale")]
+    pub scale: Vec3,
+
+    /// Degrees around the x, then y, then z axis
+    #[serde(default)]
+    pub rotate: Vec3,
+
+    /// Axis to rotate `angle` degrees around, instead of `rotate`
+    #[serde(default)]
+    pub axis: Option<Vec3>,
+    #[serde(default)]
+    pub angle: f32,
+
+    #[serde(default)]
+    pub translate: Vec3,
+}
+
+/// See `Volume`
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VolumeDescription {
+    /// Name of an entry of `SceneDescription::objects`, the boundary of the volume in world space
+    pub object: String,
+    pub density: f32,
+    /// Name of an `Isotropic` entry of `SceneDescription::materials`
+    pub material: String,
+}
+
+/// The primitives of a scene or of one of its objects
+struct Geometry<'a> {
+    spheres: &'a [SphereDescription],
+    rects: &'a [RectDescription],
+    boxes: &'a [BoxDescription],
+    meshes: &'a [MeshDescription],
+    models: &'a [ModelDescription],
+}
+
+/// The objects and materials to render, as consumed by `Raytracer::new` and `CpuRaytracer::new`.
+///
+/// Objects reference materials through the `MaterialId` returned by `add_material`, so a
+/// material can be shared by many objects.
+#[derive(Clone, Default)]
+pub struct Scene {
+    spheres: Vec<Sphere>,
+    rects: Vec<Rect>,
+    vertices: Vec<Vertex>,
+    triangles: Vec<Triangle>,
+    materials: Vec<Material>,
+    textures: Vec<Texture>,
+    objects: Vec<Object>,
+    instances: Vec<Instance>,
+    volumes: Vec<Volume>,
+    /// Set while `add_object` gathers the primitives of an object
+    building_object: bool,
+}
+
+/// Something a model file holds that couldn't be imported, the rest of the model still is
+#[derive(Clone, Debug)]
+pub enum ModelWarning {
+    Obj(ObjWarning),
+    Gltf(GltfWarning),
+}
+
+/// Why `Scene` refused a sphere, rectangle or box
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeometryError {
+    /// The material isn't part of the scene
+    UnknownMaterial(MaterialId),
+    /// The radius must be finite and greater than 0
+    InvalidRadius(f32),
+    /// The corners must be finite, `min` less than `max` along every axis
+    InvalidCorners,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    UnknownFormat {
+        path: PathBuf,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        field: String,
+        message: String,
+    },
+    Invalid {
+        path: Option<PathBuf>,
+        field: String,
+        message: String,
+    },
+    Obj(ObjError),
+    Gltf(GltfError),
+    Texture(TextureError),
+}
+
+/// serde_json appends the position to its messages, we report it separately
+fn json_message(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    let position = format!(" at line {} column {}", error.line(), error.column());
+
+    match message.strip_suffix(&position) {
+        Some(stripped) => stripped.to_string(),
+        None => message,
+    }
+}
+
+fn default_vup() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+fn default_time1() -> f32 {
+    1.0
+}
+
+fn read(path: &Path) -> Result<String, SceneError> {
+    fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn default_scale() -> Vec3 {
+    Vec3::ONE
+}
+
+fn default_texture_scale() -> f32 {
+    1.0
+}
+
+fn default_depth() -> u32 {
+    texture::DEFAULT_DEPTH
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 1080,
+            height: 720,
+            sample_count: 32,
+            max_bounces: 4,
+            light_sampling: LightSampling::default(),
+            seed: 0,
+            sampler: Sampler::default(),
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Gradient {
+            bottom: Vec3::ONE,
+            top: Vec3::new(0.5, 0.7, 0.9),
+        }
+    }
+}
+
+impl Background {
+    /// The bottom and top colors of `Config`
+    pub fn colors(&self) -> (Vec3, Vec3) {
+        match *self {
+            Background::Constant(color) => (color, color),
+            Background::Gradient { bottom, top } => (bottom, top),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => {
+                write!(f, "{}: failed to read scene: {}", path.display(), source)
+            }
+            SceneError::UnknownFormat { path } => write!(
+                f,
+                "{}: unknown scene format (expected a .ron, .json, .gltf or .glb file)",
+                path.display()
+            ),
+            SceneError::Parse {
+                path,
+                line,
+                column,
+                field,
+                message,
+            } => write!(
+                f,
+                "{}:{}:{}: {}: {}",
+                path.display(),
+                line,
+                column,
+                field,
+                message
+            ),
+            SceneError::Invalid {
+                path: Some(path),
+                field,
+                message,
+            } => write!(f, "{}: {}: {}", path.display(), field, message),
+            SceneError::Invalid {
+                path: None,
+                field,
+                message,
+            } => write!(f, "{}: {}", field, message),
+            SceneError::Obj(why) => write!(f, "{}", why),
+            SceneError::Gltf(why) => write!(f, "{}", why),
+            SceneError::Texture(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Obj(source) => Some(source),
+            SceneError::Gltf(source) => Some(source),
+            SceneError::Texture(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ModelWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelWarning::Obj(warning) => write!(f, "{}", warning),
+            ModelWarning::Gltf(warning) => write!(f, "{}", warning),
+        }
+    }
+}
+
+impl fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeometryError::UnknownMaterial(id) => {
+                write!(f, "the scene has no material {}", id.index())
+            }
+            GeometryError::InvalidRadius(radius) => {
+                write!(f, "the radius must be greater than 0, got {}", radius)
+            }
+            GeometryError::InvalidCorners => {
+                write!(f, "min must be less than max along every axis")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GeometryError {}
+
+impl SceneError {
+    /// Points an `Invalid` error at the scene file the values came from
+    pub fn with_path(self, path: &Path) -> SceneError {
+        match self {
+            SceneError::Invalid { field, message, .. } => SceneError::Invalid {
+                path: Some(path.to_path_buf()),
+                field,
+                message,
+            },
+            e => e,
+        }
+    }
+}
+
+impl SceneDescription {
+    /// Loads a scene file, the format is picked from the file extension.
+    ///
+    /// A glTF file becomes a scene rendering it from its first camera (see `gltf::load_camera`),
+    /// with the default settings and the image height following the aspect ratio of the camera.
+    ///
+    /// The values aren't validated yet so that they can still be overridden, `build` does it.
+    pub fn load(path: &Path) -> Result<SceneDescription, SceneError> {
+        let mut scene = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => Self::parse_ron(path, &read(path)?)?,
+            Some("json") => Self::parse_json(path, &read(path)?)?,
+            Some("gltf") | Some("glb") => Self::from_gltf(path)?,
+            _ => {
+                return Err(SceneError::UnknownFormat {
+                    path: path.to_path_buf(),
+                })
+            }
+        };
+
+        // Model and image paths are relative to the scene file
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let object_models = scene.objects.values_mut().flat_map(|o| o.models.iter_mut());
+        for model in scene.models.iter_mut().chain(object_models) {
+            model.path = directory.join(&model.path);
+        }
+        for texture in scene.textures.values_mut() {
+            if let TextureDescription::Image { path, .. } = texture {
+                *path = directory.join(&path);
+            }
+        }
+
+        Ok(scene)
+    }
+
+    fn from_gltf(path: &Path) -> Result<SceneDescription, SceneError> {
+        let camera = gltf::load_camera(path).map_err(SceneError::Gltf)?;
+
+        let mut settings = RenderSettings::default();
+        if let Some(aspect_ratio) = camera.aspect_ratio.filter(|a| a.is_finite() && *a > 0.0) {
+            settings.height = ((settings.width as f32 / aspect_ratio).round() as u32).max(2);
+        }
+
+        Ok(SceneDescription {
+            camera: CameraDescription {
+                look_from: camera.look_from,
+                look_at: camera.look_at,
+                vup: camera.vup,
+                vfov: camera.vfov,
+                aperture: 0.0,
+                focus_dist: None,
+                time0: 0.0,
+                time1: default_time1(),
+            },
+            settings,
+            background: Background::default(),
+            fog: None,
+            tone_mapping: ToneMapping::default(),
+            textures: BTreeMap::new(),
+            materials: BTreeMap::new(),
+            spheres: Vec::new(),
+            rects: Vec::new(),
+            boxes: Vec::new(),
+            meshes: Vec::new(),
+            // Joined with the directory of `path` by `load`
+            models: vec![ModelDescription {
+                path: path.file_name().map(PathBuf::from).unwrap_or_default(),
+                material: None,
+                scale: Vec3::ONE,
+                rotate: Vec3::ZERO,
+                translate: Vec3::ZERO,
+            }],
+            objects: BTreeMap::new(),
+            instances: Vec::new(),
+            volumes: Vec::new(),
+        })
+    }
+
+    fn parse_ron(path: &Path, source: &str) -> Result<SceneDescription, SceneError> {
+        let parse_error =
+            |position: ron::error::Position, field: String, message: String| SceneError::Parse {
+                path: path.to_path_buf(),
+                line: position.line,
+                column: position.col,
+                field,
+                message,
+            };
+
+        // Lets optional fields be written without wrapping them in `Some(..)`
+        let options = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+
+        let mut deserializer = ron::Deserializer::from_str_with_options(source, options)
+            .map_err(|e| parse_error(e.position, String::from("."), e.code.to_string()))?;
+
+        let scene: SceneDescription =
+            serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+                let field = e.path().to_string();
+                let spanned = deserializer.span_error(e.into_inner());
+                parse_error(spanned.position, field, spanned.code.to_string())
+            })?;
+
+        deserializer.end().map_err(|e| {
+            let spanned = deserializer.span_error(e);
+            parse_error(
+                spanned.position,
+                String::from("."),
+                spanned.code.to_string(),
+            )
+        })?;
+
+        Ok(scene)
+    }
+
+    fn parse_json(path: &Path, source: &str) -> Result<SceneDescription, SceneError> {
+        let mut deserializer = serde_json::Deserializer::from_str(source);
+
+        let scene: SceneDescription =
+            serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+                let field = e.path().to_string();
+                let inner = e.into_inner();
+                SceneError::Parse {
+                    path: path.to_path_buf(),
+                    line: inner.line(),
+                    column: inner.column(),
+                    field,
+                    message: json_message(&inner),
+                }
+            })?;
+
+        deserializer.end().map_err(|e| SceneError::Parse {
+            path: path.to_path_buf(),
+            line: e.line(),
+            column: e.column(),
+            field: String::from("."),
+            message: json_message(&e),
+        })?;
+
+        Ok(scene)
+    }
+
+    fn geometry(&self) -> Geometry<'_> {
+        Geometry {
+            spheres: &self.spheres,
+            rects: &self.rects,
+            boxes: &self.boxes,
+            meshes: &self.meshes,
+            models: &self.models,
+        }
+    }
+
+    /// Checks the values serde can't, `build` already does this
+    pub fn validate(&self) -> Result<(), SceneError> {
+        let invalid = |field: &str, message: &str| {
+            Err(SceneError::Invalid {
+                path: None,
+                field: field.to_string(),
+                message: message.to_string(),
+            })
+        };
+
+        // The camera spreads the pixels over `width - 1` and `height - 1` steps
+        if self.settings.width < 2 || self.settings.height < 2 {
+            return invalid("settings", "width and height must be at least 2");
+        }
+        if self.settings.sample_count == 0 {
+            return invalid("settings.sample_count", "must be greater than 0");
+        }
+        if self.settings.max_bounces < 2 {
+            return invalid("settings.max_bounces", "must be at least 2");
+        }
+
+        if let Err(why) = self.camera.build(self.settings.aspect_ratio()) {
+            let field = match why {
+                CameraError::NoViewDirection => "camera.look_at",
+                CameraError::DegenerateUp => "camera.vup",
+                CameraError::InvalidFov(_) => "camera.vfov",
+                CameraError::InvalidAspectRatio(_) => "settings",
+                CameraError::NegativeAperture(_) => "camera.aperture",
+                CameraError::InvalidFocusDistance(_) => "camera.focus_dist",
+                CameraError::InvalidShutter(..) => "camera",
+            };
+            return invalid(field, &why.to_string());
+        }
+
+        let (bottom, top) = self.background.colors();
+        let valid_color = |c: Vec3| [c.x, c.y, c.z].iter().all(|c| c.is_finite() && *c >= 0.0);
+        if !valid_color(bottom) || !valid_color(top) {
+            return invalid("background", "color components can't be negative");
+        }
+        if let Some(fog) = &self.fog {
+            if !valid_color(fog.color) {
+                return invalid("fog.color", "components can't be negative");
+            }
+            if !(fog.density.is_finite() && fog.density >= 0.0) {
+                return invalid("fog.density", "can't be negative");
+            }
+        }
+
+        let tone_mapping = &self.tone_mapping;
+        if !tone_mapping.exposure.is_finite() {
+            return invalid("tone_mapping.exposure", "must be finite");
+        }
+        if !(tone_mapping.white_point.is_finite() && tone_mapping.white_point > 0.0) {
+            return invalid("tone_mapping.white_point", "must be greater than 0");
+        }
+        if let TransferFunction::Gamma(gamma) = tone_mapping.transfer {
+            if !(gamma.is_finite() && gamma > 0.0) {
+                return invalid("tone_mapping.transfer", "the gamma must be greater than 0");
+            }
+        }
+
+        for (name, texture) in self.textures.iter() {
+            if let Err(why) = texture.validate() {
+                return invalid(
+                    &format!("textures.{}.{}", name, why.field()),
+                    &why.to_string(),
+                );
+            }
+            for (field, reference) in texture.references() {
+                if !self.textures.contains_key(reference) {
+                    return invalid(
+                        &format!("textures.{}.{}", name, field),
+                        &format!("there is no texture named '{}'", reference),
+                    );
+                }
+            }
+        }
+        if let Some(name) = texture_cycle(&self.textures) {
+            return invalid(
+                &format!("textures.{}", name),
+                "checkers can't contain themselves",
+            );
+        }
+
+        for (name, material) in self.materials.iter() {
+            if let Err(why) = material.validate() {
+                return invalid(
+                    &format!("materials.{}.{}", name, why.field()),
+                    &why.to_string(),
+                );
+            }
+            for (field, texture) in material.textures() {
+                if !self.textures.contains_key(texture) {
+                    return invalid(
+                        &format!("materials.{}.{}", name, field),
+                        &format!("there is no texture named '{}'", texture),
+                    );
+                }
+            }
+        }
+
+        self.validate_geometry("", &self.geometry())?;
+
+        let finite = |v: Vec3| [v.x, v.y, v.z].iter().all(|c| c.is_finite());
+        for (name, object) in self.objects.iter() {
+            let geometry = object.geometry();
+            if geometry.is_empty() {
+                return invalid(
+                    &format!("objects.{}", name),
+                    "must hold at least one primitive",
+                );
+            }
+            self.validate_geometry(&format!("objects.{}.", name), &geometry)?;
+        }
+
+        for (i, instance) in self.instances.iter().enumerate() {
+            if !self.objects.contains_key(&instance.object) {
+                return invalid(
+                    &format!("instances[{}].object", i),
+                    &format!("there is no object named '{}'", instance.object),
+                );
+            }
+            let scale = instance.scale;
+            if !finite(scale) || [scale.x, scale.y, scale.z].contains(&0.0) {
+                return invalid(
+                    &format!("instances[{}].scale", i),
+                    "components must be finite and can't be 0",
+                );
+            }
+            if !finite(instance.rotate) {
+                return invalid(&format!("instances[{}].rotate", i), "must be finite");
+            }
+            if let Some(axis) = instance.axis {
+                if !finite(axis) || axis.is_near_zero() {
+                    return invalid(
+                        &format!("instances[{}].axis", i),
+                        "must be finite and can't be 0",
+                    );
+                }
+                if instance.rotate != Vec3::ZERO {
+                    return invalid(
+                        &format!("instances[{}]", i),
+                        "give either rotate or an axis and angle, not both",
+                    );
+                }
+            }
+            if !instance.angle.is_finite() {
+                return invalid(&format!("instances[{}].angle", i), "must be finite");
+            }
+            if !finite(instance.translate) {
+                return invalid(&format!("instances[{}].translate", i), "must be finite");
+            }
+        }
+
+        for (i, volume) in self.volumes.iter().enumerate() {
+            if !self.objects.contains_key(&volume.object) {
+                return invalid(
+                    &format!("volumes[{}].object", i),
+                    &format!("there is no object named '{}'", volume.object),
+                );
+            }
+            if !(volume.density.is_finite() && volume.density > 0.0) {
+                return invalid(&format!("volumes[{}].density", i), "must be greater than 0");
+            }
+            match self.materials.get(&volume.material) {
+                None => {
+                    return invalid(
+                        &format!("volumes[{}].material", i),
+                        &format!("there is no material named '{}'", volume.material),
+                    )
+                }
+                Some(Material::Isotropic { .. }) => {}
+                Some(_) => {
+                    return invalid(
+                        &format!("volumes[{}].material", i),
+                        "volumes scatter light with an Isotropic material",
+                    )
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the primitives of the scene or of an object, `prefix` is prepended to the fields
+    fn validate_geometry(&self, prefix: &str, geometry: &Geometry) -> Result<(), SceneError> {
+        let invalid = |field: &str, message: &str| {
+            Err(SceneError::Invalid {
+                path: None,
+                field: field.to_string(),
+                message: message.to_string(),
+            })
+        };
+
+        let finite = |v: Vec3| [v.x, v.y, v.z].iter().all(|c| c.is_finite());
+        for (i, sphere) in geometry.spheres.iter().enumerate() {
+            if !finite(sphere.center) {
+                return invalid(
+                    &format!("{}spheres[{}].center", prefix, i),
+                    "must be finite",
+                );
+            }
+            if sphere.center1.is_some_and(|center1| !finite(center1)) {
+                return invalid(
+                    &format!("{}spheres[{}].center1", prefix, i),
+                    "must be finite",
+                );
+            }
+            if sphere.velocity.is_some_and(|velocity| !finite(velocity)) {
+                return invalid(
+                    &format!("{}spheres[{}].velocity", prefix, i),
+                    "must be finite",
+                );
+            }
+            if !(sphere.radius.is_finite() && sphere.radius > 0.0) {
+                return invalid(
+                    &format!("{}spheres[{}].radius", prefix, i),
+                    "must be finite and greater than 0",
+                );
+            }
+            if !self.materials.contains_key(&sphere.material) {
+                return invalid(
+                    &format!("{}spheres[{}].material", prefix, i),
+                    &format!("there is no material named '{}'", sphere.material),
+                );
+            }
+            if sphere.center1.is_some() && sphere.velocity.is_some() {
+                return invalid(
+                    &format!("{}spheres[{}]", prefix, i),
+                    "give either center1 or velocity, not both",
+                );
+            }
+        }
+
+        for (i, rect) in geometry.rects.iter().enumerate() {
+            if !rect.k.is_finite() {
+                return invalid(&format!("{}rects[{}].k", prefix, i), "must be finite");
+            }
+            if !(0..2).all(|a| rect.min[a].is_finite() && rect.max[a].is_finite()) {
+                return invalid(
+                    &format!("{}rects[{}]", prefix, i),
+                    "the corners must be finite",
+                );
+            }
+            if !(0..2).all(|a| rect.min[a] < rect.max[a]) {
+                return invalid(
+                    &format!("{}rects[{}].max", prefix, i),
+                    "must be greater than min along both axes",
+                );
+            }
+            if !self.materials.contains_key(&rect.material) {
+                return invalid(
+                    &format!("{}rects[{}].material", prefix, i),
+                    &format!("there is no material named '{}'", rect.material),
+                );
+            }
+        }
+
+        for (i, cuboid) in geometry.boxes.iter().enumerate() {
+            if !finite(cuboid.min) || !finite(cuboid.max) {
+                return invalid(
+                    &format!("{}boxes[{}]", prefix, i),
+                    "the corners must be finite",
+                );
+            }
+            if !(0..3).all(|a| cuboid.min[a] < cuboid.max[a]) {
+                return invalid(
+                    &format!("{}boxes[{}].max", prefix, i),
+                    "must be greater than min along every axis",
+                );
+            }
+            if !self.materials.contains_key(&cuboid.material) {
+                return invalid(
+                    &format!("{}boxes[{}].material", prefix, i),
+                    &format!("there is no material named '{}'", cuboid.material),
+                );
+            }
+        }
+
+        for (i, mesh) in geometry.meshes.iter().enumerate() {
+            if let Err(why) = mesh.to_mesh().validate() {
+                return invalid(
+                    &format!("{}meshes[{}].{}", prefix, i, why.field()),
+                    &why.to_string(),
+                );
+            }
+            if !self.materials.contains_key(&mesh.material) {
+                return invalid(
+                    &format!("{}meshes[{}].material", prefix, i),
+                    &format!("there is no material named '{}'", mesh.material),
+                );
+            }
+        }
+
+        for (i, model) in geometry.models.iter().enumerate() {
+            if ModelFormat::from_path(&model.path).is_none() {
+                return invalid(
+                    &format!("{}models[{}].path", prefix, i),
+                    "unknown model format (expected a .obj, .gltf or .glb file)",
+                );
+            }
+            if !finite(model.scale) || [model.scale.x, model.scale.y, model.scale.z].contains(&0.0)
+            {
+                return invalid(
+                    &format!("{}models[{}].scale", prefix, i),
+                    "components must be finite and can't be 0",
+                );
+            }
+            if !finite(model.rotate) {
+                return invalid(&format!("{}models[{}].rotate", prefix, i), "must be finite");
+            }
+            if !finite(model.translate) {
+                return invalid(
+                    &format!("{}models[{}].translate", prefix, i),
+                    "must be finite",
+                );
+            }
+            match &model.material {
+                Some(material) if !self.materials.contains_key(material) => {
+                    return invalid(
+                        &format!("{}models[{}].material", prefix, i),
+                        &format!("there is no material named '{}'", material),
+                    )
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validates and lowers the description into the scene consumed by `Raytracer::new`, loading
+    /// the models. Their warnings are dropped, see `build_with_warnings`.
+    pub fn build(&self) -> Result<(Config, Scene), SceneError> {
+        let (config, scene, _) = self.build_with_warnings()?;
+        Ok((config, scene))
+    }
+
+    /// `build`, also returning what the models held that couldn't be imported
+    pub fn build_with_warnings(&self) -> Result<(Config, Scene, Vec<ModelWarning>), SceneError> {
+        self.validate()?;
+
+        let settings = &self.settings;
+        let camera = self
+            .camera
+            .build(settings.aspect_ratio())
+            .expect("the camera was validated");
+
+        let mut scene = Scene::new();
+        let mut texture_ids = HashMap::new();
+        for name in self.textures.keys() {
+            self.build_texture(name, &mut scene, &mut texture_ids)?;
+        }
+
+        let mut material_ids = HashMap::new();
+        for (name, material) in self.materials.iter() {
+            let material = material
+                .clone()
+                .map_textures(|texture| texture_ids[texture.as_str()]);
+            let id = scene
+                .add_material(material)
+                .expect("the materials were validated");
+            material_ids.insert(name.as_str(), id);
+        }
+
+        let mut warnings = Vec::new();
+        self.build_geometry(&self.geometry(), &mut scene, &material_ids, &mut warnings)?;
+
+        let mut object_ids = HashMap::new();
+        for (name, object) in self.objects.iter() {
+            let id = scene.add_object(|scene| {
+                self.build_geometry(&object.geometry(), scene, &material_ids, &mut warnings)
+            })?;
+            object_ids.insert(name.as_str(), id);
+        }
+
+        for (i, instance) in self.instances.iter().enumerate() {
+            scene
+                .add_instance(object_ids[instance.object.as_str()], instance.transform())
+                .map_err(|why| SceneError::Invalid {
+                    path: None,
+                    field: format!("instances[{}]", i),
+                    message: why.to_string(),
+                })?;
+        }
+
+        for (i, volume) in self.volumes.iter().enumerate() {
+            scene
+                .add_volume(
+                    object_ids[volume.object.as_str()],
+                    volume.density,
+                    material_ids[volume.material.as_str()],
+                )
+                .map_err(|why| SceneError::Invalid {
+                    path: None,
+                    field: format!("volumes[{}]", i),
+                    message: why.to_string(),
+                })?;
+        }
+
+        let (background_bottom, background_top) = self.background.colors();
+        let fog = self.fog.unwrap_or(Fog {
+            color: Vec3::ZERO,
+            density: 0.0,
+        });
+
+        let config = Config {
+            num_spheres: scene.spheres().len() as u32,
+            num_triangles: scene.triangles().len() as u32,
+            num_rects: scene.rects().len() as u32,
+            num_instances: scene.instances().len() as u32,
+            num_volumes: scene.volumes().len() as u32,
+            num_lights: scene.lights().len() as u32,
+            light_sampling: settings.light_sampling.lower(),
+            seed: settings.seed,
+            sampler_mode: settings.sampler.lower(),
+            sample_count: settings.sample_count,
+            max_bounces: settings.max_bounces,
+            width: settings.width,
+            height: settings.height,
+
+            camera,
+            background_bottom,
+            background_top,
+            fog_color: fog.color,
+            fog_density: fog.density,
+            ..Default::default()
+        };
+
+        Ok((config, scene, warnings))
+    }
+
+    /// Adds the primitives of the scene or of an object to `scene`
+    fn build_geometry(
+        &self,
+        geometry: &Geometry,
+        scene: &mut Scene,
+        material_ids: &HashMap<&str, MaterialId>,
+        warnings: &mut Vec<ModelWarning>,
+    ) -> Result<(), SceneError> {
+        for sphere in geometry.spheres.iter() {
+            scene
+                .add_moving_sphere(
+                    sphere.center,
+                    sphere.center1(),
+                    sphere.radius,
+                    material_ids[sphere.material.as_str()],
+                )
+                .expect("the spheres were validated");
+        }
+
+        for rect in geometry.rects.iter() {
+            let material = material_ids[rect.material.as_str()];
+            let mut added = Rect::new(rect.plane, rect.min, rect.max, rect.k, material);
+            if rect.flip {
+                added = added.flipped();
+            }
+            scene.add_rect(added).expect("the rects were validated");
+        }
+
+        for cuboid in geometry.boxes.iter() {
+            scene
+                .add_box(
+                    cuboid.min,
+                    cuboid.max,
+                    material_ids[cuboid.material.as_str()],
+                )
+                .expect("the boxes were validated");
+        }
+
+        for mesh in geometry.meshes.iter() {
+            scene
+                .add_mesh(&mesh.to_mesh(), material_ids[mesh.material.as_str()])
+                .expect("the meshes were validated");
+        }
+
+        for model in geometry.models.iter() {
+            self.build_model(model, scene, material_ids, warnings)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds the texture `name` to `scene` after the textures it references, once
+    fn build_texture<'a>(
+        &'a self,
+        name: &'a str,
+        scene: &mut Scene,
+        texture_ids: &mut HashMap<&'a str, TextureId>,
+    ) -> Result<TextureId, SceneError> {
+        if let Some(&id) = texture_ids.get(name) {
+            return Ok(id);
+        }
+
+        let texture = &self.textures[name];
+        for (_, reference) in texture.references() {
+            self.build_texture(reference, scene, texture_ids)?;
+        }
+        let id = texture
+            .to_texture(|name| texture_ids[name])
+            .and_then(|texture| scene.add_texture(texture))
+            .map_err(SceneError::Texture)?;
+        texture_ids.insert(name, id);
+        Ok(id)
+    }
+
+    fn build_model(
+        &self,
+        model: &ModelDescription,
+        scene: &mut Scene,
+        material_ids: &HashMap<&str, MaterialId>,
+        warnings: &mut Vec<ModelWarning>,
+    ) -> Result<(), SceneError> {
+        let content = ModelContent::load(&model.path)?;
+        warnings.extend(content.warnings);
+        let transform = model.transform();
+
+        let invalid = |field: String, message: String| SceneError::Invalid {
+            path: Some(model.path.clone()),
+            field,
+            message,
+        };
+
+        // The materials of the model and their textures are only added once a mesh uses them
+        let mut model_material_ids = vec![None; content.materials.len()];
+        let mut model_texture_ids = vec![None; content.textures.len()];
+        let mut default_material = None;
+
+        for (material, mut mesh) in content.meshes {
+            let material = match (&model.material, material) {
+                (Some(name), _) => material_ids[name.as_str()],
+                (None, Some(i)) => match model_material_ids[i] {
+                    Some(id) => id,
+                    None => {
+                        let (name, material) = &content.materials[i];
+                        let mut textures = Vec::new();
+                        for (_, &texture) in material.textures() {
+                            let id = match model_texture_ids[texture] {
+                                Some(id) => id,
+                                None => scene
+                                    .add_texture(content.textures[texture].clone())
+                                    .map_err(SceneError::Texture)?,
+                            };
+                            model_texture_ids[texture] = Some(id);
+                            textures.push(id);
+                        }
+                        let mut textures = textures.into_iter();
+                        let material = material.map_textures(|_| {
+                            textures.next().expect("one id per texture of the material")
+                        });
+                        let id = scene.add_material(material).map_err(|why| {
+                            invalid(
+                                format!("materials.{}.{}", name, why.field()),
+                                why.to_string(),
+                            )
+                        })?;
+                        model_material_ids[i] = Some(id);
+                        id
+                    }
+                },
+                (None, None) => *default_material.get_or_insert_with(|| {
+                    scene
+                        .add_material(obj::default_material())
+                        .expect("the default material is valid")
+                }),
+            };
+
+            mesh.transform(&transform);
+            scene
+                .add_mesh(&mesh, material)
+                .map_err(|why| invalid(why.field().to_string(), why.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// The final scene of the first book: four big spheres on top of a grid of random small ones
+    pub fn random<R: Rng>(rng: &mut R) -> SceneDescription {
+        let mut rand = || rng.gen::<f32>();
+
+        let mut materials = BTreeMap::new();
+        let mut sphere = |center, radius, name: &str, material| {
+            materials.insert(name.to_string(), material);
+            SphereDescription {
+                center,
+                radius,
+                material: name.to_string(),
+                center1: None,
+                velocity: None,
+            }
+        };
+
+        let mut spheres = vec![
+            sphere(
+                Vec3::new(0.0, -1000.0, -1.0),
+                1000.0,
+                "ground",
+                Material::Lambertian {
+                    albedo: Vec3::new(0.5, 0.5, 0.5),
+                    texture: None,
+                },
+            ),
+            sphere(
+                Vec3::new(0.0, 1.0, 0.0),
+                1.0,
+                "glass",
+                Material::Dielectric { ior: 1.5 },
+            ),
+            sphere(
+                Vec3::new(-4.0, 1.0, 0.0),
+                1.0,
+                "brown",
+                Material::Lambertian {
+                    albedo: Vec3::new(0.4, 0.2, 0.1),
+                    texture: None,
+                },
+            ),
+            sphere(
+                Vec3::new(4.0, 1.0, 0.0),
+                1.0,
+                "mirror",
+                Material::Metal {
+                    albedo: Vec3::new(0.7, 0.6, 0.5),
+                    fuzz: 0.0,
+                    texture: None,
+                    fuzz_texture: None,
+                },
+            ),
+        ];
+
+        for a in -11..11 {
+            for b in -11..11 {
+                let mat = rand();
+                let center = Vec3::new(a as f32 + 0.9 * rand(), 0.2, b as f32 + 0.9 * rand());
+
+                if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                    if mat < 0.95 {
+                        let material = if mat < 0.8 {
+                            Material::Lambertian {
+                                albedo: Vec3::new(rand(), rand(), rand()),
+                                texture: None,
+                            }
+                        } else {
+                            Material::Metal {
+                                albedo: Vec3::new(
+                                    0.5 + rand() / 2.0,
+                                    0.5 + rand() / 2.0,
+                                    0.5 + rand() / 2.0,
+                                ),
+                                fuzz: rand() / 2.0,
+                                texture: None,
+                                fuzz_texture: None,
+                            }
+                        };
+
+                        let name = format!("small_{}_{}", a, b);
+                        spheres.push(sphere(center, 0.2, &name, material));
+                    } else {
+                        spheres.push(sphere(
+                            center,
+                            0.2,
+                            "glass",
+                            Material::Dielectric { ior: 1.5 },
+                        ));
+                    }
+                }
+            }
+        }
+
+        SceneDescription {
+            camera: CameraDescription {
+                look_from: Vec3::new(13.0, 2.0, 3.0),
+                look_at: Vec3::new(0.0, 0.0, 0.0),
+                vup: default_vup(),
+                vfov: 20.0,
+                aperture: 0.1,
+                focus_dist: Some(10.0),
+                time0: 0.0,
+                time1: default_time1(),
+            },
+            settings: RenderSettings::default(),
+            background: Background::default(),
+            fog: None,
+            tone_mapping: ToneMapping::default(),
+            textures: BTreeMap::new(),
+            materials,
+            spheres,
+            rects: Vec::new(),
+            boxes: Vec::new(),
+            meshes: Vec::new(),
+            models: Vec::new(),
+            objects: BTreeMap::new(),
+            instances: Vec::new(),
+            volumes: Vec::new(),
+        }
+    }
+
+    /// The Cornell box of the second book: red and green side walls, a white floor, ceiling and
+    /// back wall, a light in the ceiling and two rotated white boxes
+    pub fn cornell_box() -> SceneDescription {
+        let lambertian = |r, g, b| Material::Lambertian {
+            albedo: Vec3::new(r, g, b),
+            texture: None,
+        };
+        let materials = BTreeMap::from([
+            (String::from("red"), lambertian(0.65, 0.05, 0.05)),
+            (String::from("white"), lambertian(0.73, 0.73, 0.73)),
+            (String::from("green"), lambertian(0.12, 0.45, 0.15)),
+            (
+                String::from("light"),
+                Material::DiffuseLight {
+                    color: Vec3::ONE,
+                    intensity: 15.0,
+                    texture: None,
+                },
+            ),
+        ]);
+
+        // Every wall faces the inside of the box
+        let rect = |plane, min, max, k, material: &str, flip| RectDescription {
+            plane,
+            min,
+            max,
+            k,
+            material: material.to_string(),
+            flip,
+        };
+        let wall = |plane, k, material, flip| rect(plane, [0.0; 2], [555.0; 2], k, material, flip);
+        let rects = vec![
+            wall(Plane::Yz, 555.0, "green", true),
+            wall(Plane::Yz, 0.0, "red", false),
+            rect(
+                Plane::Xz,
+                [213.0, 227.0],
+                [343.0, 332.0],
+                554.0,
+                "light",
+                true,
+            ),
+            wall(Plane::Xz, 0.0, "white", false),
+            wall(Plane::Xz, 555.0, "white", true),
+            wall(Plane::Xy, 555.0, "white", true),
+        ];
+
+        // The boxes are turned around their corner on the floor
+        let cuboid = |height| ObjectDescription {
+            boxes: vec![BoxDescription {
+                min: Vec3::ZERO,
+                max: Vec3::new(165.0, height, 165.0),
+                material: String::from("white"),
+            }],
+            ..Default::default()
+        };
+        let objects = BTreeMap::from([
+            (String::from("tall_box"), cuboid(330.0)),
+            (String::from("short_box"), cuboid(165.0)),
+        ]);
+        let instance = |object: &str, degrees, translate| InstanceDescription {
+            object: object.to_string(),
+            scale: Vec3::ONE,
+            rotate: Vec3::new(0.0, degrees, 0.0),
+            axis: None,
+            angle: 0.0,
+            translate,
+        };
+        let instances = vec![
+            instance("tall_box", 15.0, Vec3::new(265.0, 0.0, 295.0)),
+            instance("short_box", -18.0, Vec3::new(130.0, 0.0, 65.0)),
+        ];
+
+        SceneDescription {
+            camera: CameraDescription {
+                look_from: Vec3::new(278.0, 278.0, -800.0),
+                look_at: Vec3::new(278.0, 278.0, 0.0),
+                vup: default_vup(),
+                vfov: 40.0,
+                aperture: 0.0,
+                focus_dist: None,
+                time0: 0.0,
+                time1: default_time1(),
+            },
+            settings: RenderSettings {
+                width: 600,
+                height: 600,
+                sample_count: 128,
+                max_bounces: 8,
+                light_sampling: LightSampling::default(),
+                seed: 0,
+                sampler: Sampler::default(),
+            },
+            background: Background::Constant(Vec3::ZERO),
+            fog: None,
+            tone_mapping: ToneMapping::default(),
+            textures: BTreeMap::new(),
+            materials,
+            spheres: Vec::new(),
+            rects,
+            boxes: Vec::new(),
+            meshes: Vec::new(),
+            models: Vec::new(),
+            objects,
+            instances,
+            volumes: Vec::new(),
+        }
+    }
+}
+
+impl SphereDescription {
+    /// Where the center is at time 1, the same as at time 0 for spheres that don't move
+    pub fn center1(&self) -> Vec3 {
+        match (self.center1, self.velocity) {
+            (Some(center1), _) => center1,
+            (None, Some(velocity)) => self.center + velocity,
+            (None, None) => self.center,
+        }
+    }
+}
+
+impl TextureDescription {
+    /// Checks the values serde can't, the references are checked by `SceneDescription::validate`
+    pub fn validate(&self) -> Result<(), TextureError> {
+        match *self {
+            TextureDescription::Image { .. } => Ok(()),
+            TextureDescription::Solid { color } => texture::check_color(color),
+            TextureDescription::Checker { scale, .. } | TextureDescription::Noise { scale } => {
+                texture::check_scale(scale)
+            }
+            TextureDescription::Turbulence { scale, depth }
+            | TextureDescription::Marble { scale, depth } => {
+                texture::check_scale(scale).and(texture::check_depth(depth))
+            }
+        }
+    }
+
+    /// Loads the image of the texture, `texture_id` resolves the names of the textures checkers
+    /// alternate between
+    pub fn to_texture(
+        &self,
+        texture_id: impl Fn(&str) -> TextureId,
+    ) -> Result<Texture, TextureError> {
+        Ok(match *self {
+            TextureDescription::Image {
+                ref path,
+                wrap,
+                color_space,
+            } => Texture::Image {
+                image: Arc::new(Image::load_png(path)?),
+                wrap,
+                color_space,
+            },
+            TextureDescription::Solid { color } => Texture::Solid { color },
+            TextureDescription::Checker {
+                ref even,
+                ref odd,
+                scale,
+            } => Texture::Checker {
+                even: texture_id(even),
+                odd: texture_id(odd),
+                scale,
+            },
+            TextureDescription::Noise { scale } => Texture::Noise { scale },
+            TextureDescription::Turbulence { scale, depth } => Texture::Turbulence { scale, depth },
+            TextureDescription::Marble { scale, depth } => Texture::Marble { scale, depth },
+        })
+    }
+
+    /// Names of the textures a checker alternates between, with their fields
+    fn references(&self) -> Vec<(&'static str, &str)> {
+        match self {
+            TextureDescription::Checker { even, odd, .. } => vec![("even", even), ("odd", odd)],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A texture containing itself through checkers, if any
+fn texture_cycle(textures: &BTreeMap<String, TextureDescription>) -> Option<&str> {
+    // `false` while the texture is being visited, `true` once its references are known to be fine
+    fn visit<'a>(
+        name: &'a str,
+        textures: &'a BTreeMap<String, TextureDescription>,
+        visited: &mut HashMap<&'a str, bool>,
+    ) -> bool {
+        match visited.get(name) {
+            Some(done) => return !done,
+            None => visited.insert(name, false),
+        };
+        let references = textures
+            .get(name)
+            .map(|t| t.references())
+            .unwrap_or_default();
+        if references
+            .into_iter()
+            .any(|(_, reference)| visit(reference, textures, visited))
+        {
+            return true;
+        }
+        visited.insert(name, true);
+        false
+    }
+
+    let mut visited = HashMap::new();
+    textures
+        .keys()
+        .map(String::as_str)
+        .find(|name| visit(name, textures, &mut visited))
+}
+
+impl MeshDescription {
+    pub fn to_mesh(&self) -> Mesh {
+        Mesh {
+            positions: self.positions.clone(),
+            normals: self.normals.clone(),
+            uvs: self.uvs.clone(),
+            indices: self.indices.clone(),
+        }
+    }
+}
+
+impl ObjectDescription {
+    fn geometry(&self) -> Geometry<'_> {
+        Geometry {
+            spheres: &self.spheres,
+            rects: &self.rects,
+            boxes: &self.boxes,
+            meshes: &self.meshes,
+            models: &self.models,
+        }
+    }
+}
+
+impl InstanceDescription {
+    /// Scales, then rotates and translates
+    pub fn transform(&self) -> Transform {
+        let rotation = match self.axis {
+            Some(axis) => Transform::rotate_axis(axis, self.angle),
+            None => Transform::rotate(self.rotate),
+        };
+
+        Transform::scale(self.scale)
+            .then(&rotation)
+            .then(&Transform::translate(self.translate))
+    }
+}
+
+impl Geometry<'_> {
+    fn is_empty(&self) -> bool {
+        self.spheres.is_empty()
+            && self.rects.is_empty()
+            && self.boxes.is_empty()
+            && self.meshes.is_empty()
+            && self.models.is_empty()
+    }
+}
+
+impl ModelDescription {
+    /// Scales, then rotates and translates
+    pub fn transform(&self) -> Transform {
+        Transform::scale(self.scale)
+            .then(&Transform::rotate(self.rotate))
+            .then(&Transform::translate(self.translate))
+    }
+}
+
+#[derive(Copy, Clone)]
+enum ModelFormat {
+    Obj,
+    Gltf,
+}
+
+impl ModelFormat {
+    fn from_path(path: &Path) -> Option<ModelFormat> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("obj") => Some(ModelFormat::Obj),
+            Some("gltf") | Some("glb") => Some(ModelFormat::Gltf),
+            _ => None,
+        }
+    }
+}
+
+/// The meshes of a model file whatever its format, with the materials they use by index
+struct ModelContent {
+    meshes: Vec<(Option<usize>, Mesh)>,
+    /// Names are only used to report errors, the textures are indices into `textures`
+    materials: Vec<(String, Material<usize>)>,
+    textures: Vec<Texture>,
+    warnings: Vec<ModelWarning>,
+}
+
+impl ModelContent {
+    fn load(path: &Path) -> Result<ModelContent, SceneError> {
+        match ModelFormat::from_path(path).expect("the model format was validated") {
+            ModelFormat::Obj => {
+                let obj = obj::load(path).map_err(SceneError::Obj)?;
+
+                let indices: HashMap<&String, usize> = obj
+                    .materials
+                    .keys()
+                    .enumerate()
+                    .map(|(i, name)| (name, i))
+                    .collect();
+                let meshes = obj
+                    .meshes
+                    .into_iter()
+                    .map(|mesh| (mesh.material.map(|name| indices[&name]), mesh.mesh))
+                    .collect();
+
+                Ok(ModelContent {
+                    meshes,
+                    materials: obj
+                        .materials
+                        .into_iter()
+                        .map(|(name, material)| {
+                            let material = material.map_textures(|_| -> usize {
+                                unreachable!("OBJ materials have no textures")
+                            });
+                            (name, material)
+                        })
+                        .collect(),
+                    textures: Vec::new(),
+                    warnings: obj.warnings.into_iter().map(ModelWarning::Obj).collect(),
+                })
+            }
+            ModelFormat::Gltf => {
+                let gltf = gltf::load(path).map_err(SceneError::Gltf)?;
+
+                Ok(ModelContent {
+                    meshes: gltf
+                        .meshes
+                        .into_iter()
+                        .map(|mesh| (mesh.material, mesh.mesh))
+                        .collect(),
+                    materials: gltf
+                        .materials
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, material)| (i.to_string(), material))
+                        .collect(),
+                    textures: gltf.textures,
+                    warnings: gltf.warnings.into_iter().map(ModelWarning::Gltf).collect(),
+                })
+            }
+        }
+    }
+}
+
+impl CameraDescription {
+    pub fn build(&self, aspect_ratio: f32) -> Result<Camera, CameraError> {
+        Camera::look_at(
+            self.look_from,
+            self.look_at,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+        )?
+        .shutter(self.time0, self.time1)
+    }
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    pub fn add_material(&mut self, material: Material) -> Result<MaterialId, MaterialError> {
+        material.validate()?;
+        for (_, &texture) in material.textures() {
+            if texture.index() >= self.textures.len() {
+                return Err(MaterialError::UnknownTexture(texture));
+            }
+        }
+
+        self.materials.push(material);
+        Ok(MaterialId((self.materials.len() - 1) as u32))
+    }
+
+    /// Checkers can only reference textures added before them, so they can't contain themselves
+    pub fn add_texture(&mut self, texture: Texture) -> Result<TextureId, TextureError> {
+        texture.validate()?;
+        if let Texture::Checker { even, odd, .. } = texture {
+            for id in [even, odd] {
+                if id.index() >= self.textures.len() {
+                    return Err(TextureError::UnknownTexture(id));
+                }
+            }
+        }
+
+        self.textures.push(texture);
+        Ok(TextureId((self.textures.len() - 1) as u32))
+    }
+
+    pub fn add_sphere(
+        &mut self,
+        center: Vec3,
+        radius: f32,
+        material: MaterialId,
+    ) -> Result<(), GeometryError> {
+        self.add_moving_sphere(center, center, radius, material)
+    }
+
+    /// Adds a sphere moving from `center0` at time 0 to `center1` at time 1
+    pub fn add_moving_sphere(
+        &mut self,
+        center0: Vec3,
+        center1: Vec3,
+        radius: f32,
+        material: MaterialId,
+    ) -> Result<(), GeometryError> {
+        if !(radius.is_finite() && radius > 0.0) {
+            return Err(GeometryError::InvalidRadius(radius));
+        }
+        self.check_material(material)?;
+
+        self.spheres
+            .push(Sphere::moving(center0, center1, radius, material));
+        Ok(())
+    }
+
+    pub fn add_rect(&mut self, rect: Rect) -> Result<(), GeometryError> {
+        if !(0..2).all(|a| {
+            rect.min[a].is_finite() && rect.max[a].is_finite() && rect.min[a] < rect.max[a]
+        }) {
+            return Err(GeometryError::InvalidCorners);
+        }
+        self.check_material(MaterialId(rect.material))?;
+
+        self.rects.push(rect);
+        Ok(())
+    }
+
+    /// Adds the six rectangles of the box between `min` and `max`
+    pub fn add_box(
+        &mut self,
+        min: Vec3,
+        max: Vec3,
+        material: MaterialId,
+    ) -> Result<(), GeometryError> {
+        if !(0..3).all(|a| min[a].is_finite() && max[a].is_finite() && min[a] < max[a]) {
+            return Err(GeometryError::InvalidCorners);
+        }
+        self.check_material(material)?;
+
+        self.rects.extend(Rect::cuboid(min, max, material));
+        Ok(())
+    }
+
+    /// Ids of another scene may point past the materials of this one
+    fn check_material(&self, material: MaterialId) -> Result<(), GeometryError> {
+        if material.index() >= self.materials.len() {
+            return Err(GeometryError::UnknownMaterial(material));
+        }
+        Ok(())
+    }
+
+    /// Gathers the primitives `build` adds into an object, which is only rendered through
+    /// `add_instance`. They're removed again when `build` fails.
+    ///
+    /// Panics when called from `build`, objects can't contain other objects or instances.
+    pub fn add_object<F, E>(&mut self, build: F) -> Result<ObjectId, E>
+    where
+        F: FnOnce(&mut Scene) -> Result<(), E>,
+    {
+        assert!(!self.building_object, "objects can't contain other objects");
+
+        let start = (self.spheres.len(), self.triangles.len(), self.rects.len());
+        let vertex_count = self.vertices.len();
+
+        self.building_object = true;
+        let result = build(self);
+        self.building_object = false;
+
+        if let Err(why) = result {
+            self.spheres.truncate(start.0);
+            self.triangles.truncate(start.1);
+            self.rects.truncate(start.2);
+            self.vertices.truncate(vertex_count);
+            return Err(why);
+        }
+
+        self.objects.push(Object {
+            spheres: start.0..self.spheres.len(),
+            triangles: start.1..self.triangles.len(),
+            rects: start.2..self.rects.len(),
+        });
+        Ok(ObjectId((self.objects.len() - 1) as u32))
+    }
+
+    /// Places a copy of `object` in the scene, `transform` maps it from object to world space
+    pub fn add_instance(
+        &mut self,
+        object: ObjectId,
+        transform: Transform,
+    ) -> Result<(), InstanceError> {
+        assert!(!self.building_object, "objects can't contain instances");
+
+        match self.objects.get(object.index()) {
+            None => return Err(InstanceError::UnknownObject(object)),
+            Some(o) if o.is_empty() => return Err(InstanceError::EmptyObject(object)),
+            Some(_) => {}
+        }
+
+        let instance =
+            Instance::new(object, transform).ok_or(InstanceError::InvalidTransform(transform))?;
+        self.instances.push(instance);
+        Ok(())
+    }
+
+    /// Fills `object` with a medium of `density` scattering light with `material`, which must be
+    /// `Isotropic`. The object only bounds the volume, its surface isn't rendered unless it's
+    /// also instanced, and must be closed with its faces pointing outwards.
+    pub fn add_volume(
+        &mut self,
+        object: ObjectId,
+        density: f32,
+        material: MaterialId,
+    ) -> Result<(), VolumeError> {
+        assert!(!self.building_object, "objects can't contain volumes");
+
+        match self.objects.get(object.index()) {
+            None => return Err(VolumeError::UnknownObject(object)),
+            Some(o) if o.is_empty() => return Err(VolumeError::EmptyObject(object)),
+            Some(_) => {}
+        }
+        match self.materials.get(material.index()) {
+            None => return Err(VolumeError::UnknownMaterial(material)),
+            Some(Material::Isotropic { .. }) => {}
+            Some(_) => return Err(VolumeError::NotIsotropic(material)),
+        }
+
+        let volume =
+            Volume::new(object, density, material).ok_or(VolumeError::InvalidDensity(density))?;
+        self.volumes.push(volume);
+        Ok(())
+    }
+
+    /// Adds the triangles of `mesh`, all of them using `material`
+    pub fn add_mesh(&mut self, mesh: &Mesh, material: MaterialId) -> Result<(), MeshError> {
+        mesh.validate()?;
+        if self.check_material(material).is_err() {
+            return Err(MeshError::UnknownMaterial(material));
+        }
+
+        let first_vertex = self.vertices.len() as u32;
+        self.vertices.extend(mesh.vertices());
+        self.triangles.extend(
+            mesh.indices
+                .iter()
+                .map(|indices| Triangle::new(indices.map(|i| first_vertex + i), material)),
+        );
+
+        Ok(())
+    }
+
+    pub fn spheres(&self) -> &[Sphere] {
+        &self.spheres
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    /// Vertices of every mesh, referenced by `triangles`
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    pub fn volumes(&self) -> &[Volume] {
+        &self.volumes
+    }
+
+    /// The spheres and rectangles outside objects with a `DiffuseLight` material, numbered like
+    /// the primitives of `build_bvh`. Lambertian surfaces send some of their rays towards them.
+    pub fn lights(&self) -> Vec<u32> {
+        let is_light = |material: u32| {
+            matches!(
+                self.materials[material as usize],
+                Material::DiffuseLight { .. }
+            )
+        };
+        let in_object = |i: usize, range: fn(&Object) -> &Range<usize>| {
+            self.objects.iter().any(|object| range(object).contains(&i))
+        };
+
+        let spheres = (0..self.spheres.len())
+            .filter(|&i| is_light(self.spheres[i].material) && !in_object(i, |o| &o.spheres))
+            .map(|i| i as u32);
+        let rects_start = self.spheres.len() + self.triangles.len();
+        let rects = (0..self.rects.len())
+            .filter(|&i| is_light(self.rects[i].material) && !in_object(i, |o| &o.rects))
+            .map(|i| (rects_start + i) as u32);
+
+        spheres.chain(rects).collect()
+    }
+
+    pub fn material(&self, id: MaterialId) -> &Material {
+        &self.materials[id.index()]
+    }
+
+    pub fn textures(&self) -> &[Texture] {
+        &self.textures
+    }
+
+    pub fn texture(&self, id: TextureId) -> &Texture {
+        &self.textures[id.index()]
+    }
+
+    /// Builds a two-level BVH. Primitive `i` of `bvh.order()` is sphere `i` if
+    /// `i < spheres().len()`, then come the triangles, the rectangles, the instances and the
+    /// volumes, in this order.
+    ///
+    /// The first tree holds the primitives outside objects, the instances and the volumes, which
+    /// lead to the tree of object `j` starting at node `bvh.roots()[j + 1]`. The object trees are
+    /// only built when there are instances or volumes.
+    pub fn build_bvh(&self) -> Bvh {
+        let mut bounds: Vec<_> = self
+            .spheres
+            .iter()
+            .map(Sphere::bounds)
+            .chain(self.triangles.iter().map(|t| t.bounds(&self.vertices)))
+            .chain(self.rects.iter().map(Rect::bounds))
+            .collect();
+        let primitive_count = bounds.len() as u32;
+
+        let triangles_start = self.spheres.len();
+        let rects_start = triangles_start + self.triangles.len();
+        let objects: Vec<Vec<u32>> = self
+            .objects
+            .iter()
+            .map(|object| {
+                object
+                    .spheres
+                    .clone()
+                    .chain(object.triangles.clone().map(|i| triangles_start + i))
+                    .chain(object.rects.clone().map(|i| rects_start + i))
+                    .map(|i| i as u32)
+                    .collect()
+            })
+            .collect();
+
+        let mut in_object = vec![false; bounds.len()];
+        for &i in objects.iter().flatten() {
+            in_object[i as usize] = true;
+        }
+
+        let object_bounds: Vec<Aabb> = objects
+            .iter()
+            .map(|primitives| {
+                primitives
+                    .iter()
+                    .fold(Aabb::EMPTY, |object, &i| object.union(&bounds[i as usize]))
+            })
+            .collect();
+        bounds.extend(self.instances.iter().map(|instance| {
+            object_bounds[instance.object().index()].transform(instance.transform())
+        }));
+        bounds.extend(
+            self.volumes
+                .iter()
+                .map(|volume| object_bounds[volume.object().index()]),
+        );
+
+        let world: Vec<u32> = (0..primitive_count)
+            .filter(|&i| !in_object[i as usize])
+            .chain(primitive_count..bounds.len() as u32)
+            .collect();
+        let mut bvh = Bvh::build_subset(&world, &bounds);
+        if !self.instances.is_empty() || !self.volumes.is_empty() {
+            for primitives in objects.iter() {
+                bvh.append(Bvh::build_subset(primitives, &bounds));
+            }
+        }
+        bvh
+    }
+}
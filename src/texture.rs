@@ -0,0 +1,526 @@
+use crate::vec3::Vec3;
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Texture looked up by the materials of a `Scene`, see `Scene::add_texture`. Images are mapped
+/// through the texture coordinates of the surface, the other textures are evaluated at the hit
+/// point in world space.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Texture {
+    /// Filtered bilinearly, `(0, 0)` is the bottom left corner of the image
+    Image {
+        image: Arc<Image>,
+        wrap: WrapMode,
+        color_space: ColorSpace,
+    },
+    /// The same color everywhere
+    Solid { color: Vec3 },
+    /// 3D checkerboard of cubes with sides of `scale`, alternating between two textures
+    Checker {
+        even: TextureId,
+        odd: TextureId,
+        scale: f32,
+    },
+    /// Perlin noise, between black and white, `scale` is the frequency
+    Noise { scale: f32 },
+    /// Sum of `depth` octaves of Perlin noise, at most `MAX_DEPTH`
+    Turbulence { scale: f32, depth: u32 },
+    /// Stripes along z, distorted by turbulence
+    Marble { scale: f32, depth: u32 },
+}
+
+/// How texture coordinates outside of 0..=1 are mapped onto the image
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum WrapMode {
+    /// Tiles the image
+    #[default]
+    Repeat,
+    /// Tiles the image, flipping every other tile
+    Mirror,
+    /// Stretches the pixels on the border of the image
+    Clamp,
+}
+
+/// How the 8-bit values of an image are turned into linear colors
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum ColorSpace {
+    /// Colors, as painted or photographed
+    #[default]
+    Srgb,
+    /// Data such as masks, used as is
+    Linear,
+}
+
+/// 8-bit RGBA image, stored top row first
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    texels: Vec<[u8; 4]>,
+}
+
+/// Index of a texture in a `Scene`, returned by `Scene::add_texture`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(pub(crate) u32);
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Decode {
+        path: PathBuf,
+        source: png::DecodingError,
+    },
+    /// Images need at least one texel and one texel per pixel
+    InvalidSize {
+        width: u32,
+        height: u32,
+        texels: usize,
+    },
+    /// Color components must be between 0 and 1
+    InvalidColor(Vec3),
+    /// The scale must be finite and greater than 0
+    InvalidScale(f32),
+    /// Turbulence can't sum more than `MAX_DEPTH` octaves
+    InvalidDepth(u32),
+    /// Checkers can only reference textures added before them
+    UnknownTexture(TextureId),
+}
+
+/// `Texture` as laid out in `compute.glsl` (std430), the fields unused by a kind are zero. The
+/// padding keeps the size a multiple of the 16 byte alignment of `color`.
+#[derive(Copy, Clone, Zeroable, Pod, Default)]
+#[repr(C)]
+pub(crate) struct GpuTexture {
+    color: Vec3,
+    kind: u32,
+    /// Index of the top left texel in the texel buffer
+    first_texel: u32,
+    width: u32,
+    height: u32,
+    wrap: u32,
+    color_space: u32,
+    even: u32,
+    odd: u32,
+    scale: f32,
+    depth: u32,
+    _0: u32,
+    _1: u32,
+    _2: u32,
+}
+
+/// Octaves of turbulence when the scene file doesn't say
+pub(crate) const DEFAULT_DEPTH: u32 = 7;
+
+/// Octaves of turbulence past which the next ones are too fine to show, and each pixel would
+/// still loop over them on the GPU
+pub const MAX_DEPTH: u32 = 16;
+
+// Must match the constants in compute.glsl
+const IMAGE: u32 = 0;
+const SOLID: u32 = 1;
+const CHECKER: u32 = 2;
+const NOISE: u32 = 3;
+const TURBULENCE: u32 = 4;
+const MARBLE: u32 = 5;
+const WRAP_REPEAT: u32 = 0;
+const WRAP_MIRROR: u32 = 1;
+const WRAP_CLAMP: u32 = 2;
+const COLOR_SPACE_SRGB: u32 = 0;
+const COLOR_SPACE_LINEAR: u32 = 1;
+
+/// Material slots without a texture, must match `NO_TEXTURE` in compute.glsl
+pub(crate) const NO_TEXTURE: u32 = u32::MAX;
+
+impl Image {
+    pub fn new(width: u32, height: u32, texels: Vec<[u8; 4]>) -> Result<Image, TextureError> {
+        if width == 0 || height == 0 || texels.len() != width as usize * height as usize {
+            return Err(TextureError::InvalidSize {
+                width,
+                height,
+                texels: texels.len(),
+            });
+        }
+
+        Ok(Image {
+            width,
+            height,
+            texels,
+        })
+    }
+
+    /// Loads a PNG of any bit depth or color type, 16-bit channels are reduced to 8 bits
+    pub fn load_png(path: &Path) -> Result<Image, TextureError> {
+        let file = File::open(path).map_err(|source| TextureError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let decode_error = |source| TextureError::Decode {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(decode_error)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(decode_error)?;
+
+        let texels = buffer[..info.line_size * info.height as usize]
+            .chunks_exact(info.line_size)
+            .flat_map(|row| {
+                let channels = info.color_type.samples();
+                row[..info.width as usize * channels]
+                    .chunks_exact(channels)
+                    .map(rgba)
+            })
+            .collect();
+
+        Image::new(info.width, info.height, texels)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Texel in column `x` of row `y`, counting rows from the top
+    pub fn texel(&self, x: u32, y: u32) -> [u8; 4] {
+        self.texels[(y * self.width + x) as usize]
+    }
+}
+
+/// Texel of 1 (gray), 2 (gray and alpha), 3 (RGB) or 4 (RGBA) channels as RGBA
+pub(crate) fn rgba(texel: &[u8]) -> [u8; 4] {
+    match *texel {
+        [l] => [l, l, l, 255],
+        [l, a] => [l, l, l, a],
+        [r, g, b] => [r, g, b, 255],
+        [r, g, b, a] => [r, g, b, a],
+        _ => panic!("texels have 1 to 4 channels, not {}", texel.len()),
+    }
+}
+
+impl WrapMode {
+    /// Maps the texel coordinate `i` into 0..size, mirrors `wrap` in compute.glsl
+    fn apply(&self, i: f32, size: u32) -> u32 {
+        let size = size as f32;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2.0 * size);
+                if i < size {
+                    i
+                } else {
+                    2.0 * size - 1.0 - i
+                }
+            }
+            WrapMode::Clamp => i,
+        };
+        i.clamp(0.0, size - 1.0) as u32
+    }
+}
+
+impl ColorSpace {
+    fn decode(&self, texel: [u8; 4]) -> Vec3 {
+        let [r, g, b, _] = texel.map(|c| c as f32 / 255.0);
+        match self {
+            ColorSpace::Srgb => {
+                let linear = |c: f32| {
+                    if c <= 0.04045 {
+                        c / 12.92
+                    } else {
+                        ((c + 0.055) / 1.055).powf(2.4)
+                    }
+                };
+                Vec3::new(linear(r), linear(g), linear(b))
+            }
+            ColorSpace::Linear => Vec3::new(r, g, b),
+        }
+    }
+}
+
+impl Texture {
+    pub fn validate(&self) -> Result<(), TextureError> {
+        match *self {
+            Texture::Solid { color } => check_color(color),
+            Texture::Checker { scale, .. } | Texture::Noise { scale } => check_scale(scale),
+            Texture::Turbulence { scale, depth } | Texture::Marble { scale, depth } => {
+                check_scale(scale).and(check_depth(depth))
+            }
+            Texture::Image { .. } => Ok(()),
+        }
+    }
+
+    /// Color at the texture coordinates `uv` of the hit `point`, mirrors `texture_value` in
+    /// compute.glsl. `textures` are the textures of the scene, which checkers reference.
+    pub fn value(&self, uv: [f32; 2], point: Vec3, textures: &[Texture]) -> Vec3 {
+        match self {
+            Texture::Image {
+                image,
+                wrap,
+                color_space,
+            } => {
+                // Texel centers sit at half coordinates, rows are stored top down
+                let x = uv[0] * image.width as f32 - 0.5;
+                let y = (1.0 - uv[1]) * image.height as f32 - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+
+                let texel = |x: f32, y: f32| {
+                    color_space.decode(
+                        image.texel(wrap.apply(x, image.width), wrap.apply(y, image.height)),
+                    )
+                };
+                let top = (1.0 - fx) * texel(x0, y0) + fx * texel(x0 + 1.0, y0);
+                let bottom = (1.0 - fx) * texel(x0, y0 + 1.0) + fx * texel(x0 + 1.0, y0 + 1.0);
+                (1.0 - fy) * top + fy * bottom
+            }
+            Texture::Solid { color } => *color,
+            Texture::Checker { even, odd, scale } => {
+                let cell = |c: f32| (c / scale).floor() as i32;
+                let sum = cell(point.x)
+                    .wrapping_add(cell(point.y))
+                    .wrapping_add(cell(point.z));
+                let texture = if sum & 1 == 0 { even } else { odd };
+                textures[texture.index()].value(uv, point, textures)
+            }
+            Texture::Noise { scale } => Vec3::ONE * 0.5 * (1.0 + noise(*scale * point)),
+            Texture::Turbulence { scale, depth } => Vec3::ONE * turbulence(*scale * point, *depth),
+            Texture::Marble { scale, depth } => {
+                Vec3::ONE * 0.5 * (1.0 + (scale * point.z + 10.0 * turbulence(point, *depth)).sin())
+            }
+        }
+    }
+}
+
+/// Colors of `Texture::Solid`, shared with the scene validation
+pub(crate) fn check_color(color: Vec3) -> Result<(), TextureError> {
+    match [color.x, color.y, color.z]
+        .iter()
+        .all(|c| (0.0..=1.0).contains(c))
+    {
+        true => Ok(()),
+        false => Err(TextureError::InvalidColor(color)),
+    }
+}
+
+pub(crate) fn check_scale(scale: f32) -> Result<(), TextureError> {
+    match scale > 0.0 && scale.is_finite() {
+        true => Ok(()),
+        false => Err(TextureError::InvalidScale(scale)),
+    }
+}
+
+pub(crate) fn check_depth(depth: u32) -> Result<(), TextureError> {
+    match depth <= MAX_DEPTH {
+        true => Ok(()),
+        false => Err(TextureError::InvalidDepth(depth)),
+    }
+}
+
+/// One iteration of Bob Jenkins' One-At-A-Time hash, mirrors `hash` in compute.glsl
+fn hash(mut x: u32) -> u32 {
+    x = x.wrapping_add(x << 10);
+    x ^= x >> 6;
+    x = x.wrapping_add(x << 3);
+    x ^= x >> 11;
+    x = x.wrapping_add(x << 15);
+    x
+}
+
+/// Dot product of `offset` with the gradient of the lattice point `corner`, one of the 12 edges
+/// of a cube as in Ken Perlin's improved noise. Mirrors `gradient` in compute.glsl.
+fn gradient(corner: [i32; 3], offset: Vec3) -> f32 {
+    let [i, j, k] = corner.map(|c| c as u32);
+    let h = hash(i ^ hash(j ^ hash(k))) & 15;
+
+    let u = if h < 8 { offset.x } else { offset.y };
+    let v = match h {
+        0..=3 => offset.y,
+        12 | 14 => offset.x,
+        _ => offset.z,
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// Perlin noise, roughly between -1 and 1, mirrors `noise` in compute.glsl
+fn noise(p: Vec3) -> f32 {
+    let cell = [p.x.floor(), p.y.floor(), p.z.floor()];
+    let f = p - Vec3::from(cell);
+    // Hermite smoothing hides the lattice
+    let w = [f.x, f.y, f.z].map(|f| f * f * (3.0 - 2.0 * f));
+
+    let mut result = 0.0;
+    for corner in 0..8 {
+        let c = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+        let mut weight = 1.0;
+        for axis in 0..3 {
+            weight *= if c[axis] == 1 { w[axis] } else { 1.0 - w[axis] };
+        }
+        let lattice = [0, 1, 2].map(|axis| cell[axis] as i32 + c[axis]);
+        let offset = f - Vec3::new(c[0] as f32, c[1] as f32, c[2] as f32);
+        result += weight * gradient(lattice, offset);
+    }
+    result
+}
+
+/// Sum of `depth` octaves of noise, each at twice the frequency and half the weight of the
+/// previous one. Mirrors `turbulence` in compute.glsl.
+fn turbulence(mut p: Vec3, depth: u32) -> f32 {
+    let mut accumulated = 0.0;
+    let mut weight = 1.0;
+    for _ in 0..depth {
+        accumulated += weight * noise(p);
+        weight *= 0.5;
+        p = 2.0 * p;
+    }
+    accumulated.abs()
+}
+
+impl TextureId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Lays the textures out for the GPU: their descriptions and the texels of every image, packed
+/// as RGBA8
+pub(crate) fn lower(textures: &[Texture]) -> (Vec<GpuTexture>, Vec<u32>) {
+    let mut gpu_textures = Vec::with_capacity(textures.len());
+    let mut texels = Vec::new();
+
+    for texture in textures {
+        match *texture {
+            Texture::Image {
+                ref image,
+                wrap,
+                color_space,
+            } => {
+                gpu_textures.push(GpuTexture {
+                    kind: IMAGE,
+                    first_texel: texels.len() as u32,
+                    width: image.width,
+                    height: image.height,
+                    wrap: match wrap {
+                        WrapMode::Repeat => WRAP_REPEAT,
+                        WrapMode::Mirror => WRAP_MIRROR,
+                        WrapMode::Clamp => WRAP_CLAMP,
+                    },
+                    color_space: match color_space {
+                        ColorSpace::Srgb => COLOR_SPACE_SRGB,
+                        ColorSpace::Linear => COLOR_SPACE_LINEAR,
+                    },
+                    ..Default::default()
+                });
+                texels.extend(image.texels.iter().map(|&texel| u32::from_le_bytes(texel)));
+            }
+            Texture::Solid { color } => gpu_textures.push(GpuTexture {
+                kind: SOLID,
+                color,
+                ..Default::default()
+            }),
+            Texture::Checker { even, odd, scale } => gpu_textures.push(GpuTexture {
+                kind: CHECKER,
+                even: even.0,
+                odd: odd.0,
+                scale,
+                ..Default::default()
+            }),
+            Texture::Noise { scale } => gpu_textures.push(GpuTexture {
+                kind: NOISE,
+                scale,
+                ..Default::default()
+            }),
+            Texture::Turbulence { scale, depth } => gpu_textures.push(GpuTexture {
+                kind: TURBULENCE,
+                scale,
+                depth,
+                ..Default::default()
+            }),
+            Texture::Marble { scale, depth } => gpu_textures.push(GpuTexture {
+                kind: MARBLE,
+                scale,
+                depth,
+                ..Default::default()
+            }),
+        }
+    }
+
+    (gpu_textures, texels)
+}
+
+impl TextureError {
+    /// Name of the offending field
+    pub fn field(&self) -> &'static str {
+        match self {
+            TextureError::Io { .. } | TextureError::Decode { .. } => "path",
+            TextureError::InvalidSize { .. } => "texels",
+            TextureError::InvalidColor(_) => "color",
+            TextureError::InvalidScale(_) => "scale",
+            TextureError::InvalidDepth(_) => "depth",
+            TextureError::UnknownTexture(_) => "texture",
+        }
+    }
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Io { path, source } => {
+                write!(f, "{}: failed to read texture: {}", path.display(), source)
+            }
+            TextureError::Decode { path, source } => {
+                write!(f, "{}: failed to decode PNG: {}", path.display(), source)
+            }
+            TextureError::InvalidSize {
+                width,
+                height,
+                texels,
+            } => write!(
+                f,
+                "a {}x{} image needs {} texels, got {}",
+                width,
+                height,
+                *width as usize * *height as usize,
+                texels
+            ),
+            TextureError::InvalidColor(color) => write!(
+                f,
+                "color components must be between 0 and 1, got ({}, {}, {})",
+                color.x, color.y, color.z
+            ),
+            TextureError::InvalidScale(scale) => {
+                write!(f, "the scale must be greater than 0, got {}", scale)
+            }
+            TextureError::InvalidDepth(depth) => write!(
+                f,
+                "the depth can't be greater than {}, got {}",
+                MAX_DEPTH, depth
+            ),
+            TextureError::UnknownTexture(texture) => {
+                write!(f, "the scene has no texture {}", texture.0)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io { source, .. } => Some(source),
+            TextureError::Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
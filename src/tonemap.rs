@@ -0,0 +1,223 @@
+use crate::progressive::luminance;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Turns the linear radiance of the renderers into display colors: exposure, then the tone
+/// mapping `operator`, then the `transfer` function, then (optionally dithered) quantization.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ToneMapping {
+    /// Exposure in stops, the radiance is scaled by `2^exposure`
+    pub exposure: f32,
+    pub operator: ToneMapOperator,
+    /// Radiance mapped to white by `ToneMapOperator::ExtendedReinhard`
+    pub white_point: f32,
+    pub transfer: TransferFunction,
+    /// Adds noise of about one quantization step before quantizing, hides banding in gradients
+    pub dither: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum ToneMapOperator {
+    /// Clips every channel to 0..=1
+    Clamp,
+    /// `L / (1 + L)` on the luminance, never reaches white
+    Reinhard,
+    /// Reinhard reaching white at `ToneMapping::white_point`
+    ExtendedReinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces,
+    /// Approximation of Blender's AgX, desaturates bright colors instead of skewing their hue
+    Agx,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub enum TransferFunction {
+    /// The piecewise sRGB curve (IEC 61966-2-1)
+    Srgb,
+    /// Plain power curve, the books use `Gamma(2.0)`
+    Gamma(f32),
+    /// No encoding, for linear displays or further processing
+    Linear,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            exposure: 0.0,
+            operator: ToneMapOperator::Clamp,
+            white_point: 4.0,
+            transfer: TransferFunction::Srgb,
+            dither: false,
+        }
+    }
+}
+
+impl ToneMapping {
+    /// Maps a linear color to display values within 0..=1
+    pub fn map(&self, color: [f32; 3]) -> [f32; 3] {
+        let scale = f32::exp2(self.exposure);
+        let color = color.map(|c| (c * scale).max(0.0));
+
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => color,
+            ToneMapOperator::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMapOperator::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                scale_luminance(color, |l| l * (1.0 + l / white_squared) / (1.0 + l))
+            }
+            ToneMapOperator::Aces => color.map(aces),
+            ToneMapOperator::Agx => agx(color),
+        };
+
+        mapped.map(|c| self.transfer.encode(c.clamp(0.0, 1.0)))
+    }
+
+    /// Maps the pixels returned by `Raytracer::raytrace` to 0..=255
+    pub fn quantize(&self, pixels: &[f32]) -> Vec<u8> {
+        self.quantize_with(pixels, u8::MAX as f32)
+            .map(|c| c as u8)
+            .collect()
+    }
+
+    /// Maps the pixels returned by `Raytracer::raytrace` to 0..=65535
+    pub fn quantize_u16(&self, pixels: &[f32]) -> Vec<u16> {
+        self.quantize_with(pixels, u16::MAX as f32)
+            .map(|c| c as u16)
+            .collect()
+    }
+
+    fn quantize_with<'a>(&'a self, pixels: &'a [f32], max: f32) -> impl Iterator<Item = f32> + 'a {
+        pixels
+            .chunks_exact(3)
+            .flat_map(move |pixel| self.map([pixel[0], pixel[1], pixel[2]]))
+            .enumerate()
+            .map(move |(i, c)| {
+                let noise = if self.dither { dither(i as u32) } else { 0.0 };
+                (c * max + noise).round().clamp(0.0, max)
+            })
+    }
+}
+
+impl TransferFunction {
+    /// Encodes a linear value within 0..=1
+    pub fn encode(&self, c: f32) -> f32 {
+        match *self {
+            TransferFunction::Srgb if c <= 0.0031308 => 12.92 * c,
+            TransferFunction::Srgb => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+            TransferFunction::Gamma(gamma) => c.powf(1.0 / gamma),
+            TransferFunction::Linear => c,
+        }
+    }
+}
+
+/// Scales `color` so its luminance becomes `curve(luminance)`
+fn scale_luminance(color: [f32; 3], curve: impl Fn(f32) -> f32) -> [f32; 3] {
+    let l = luminance(color);
+    if l <= 0.0 {
+        return color;
+    }
+
+    let scale = curve(l) / l;
+    color.map(|c| c * scale)
+}
+
+fn aces(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// AgX base look without the punchy/golden looks: the color is pushed towards the achromatic
+/// axis, log encoded and run through a sigmoid, then brought back to linear.
+///
+/// Matrices and curve from Benjamin Wrensch's "Minimal AgX implementation".
+fn agx(color: [f32; 3]) -> [f32; 3] {
+    const INSET: [[f32; 3]; 3] = [
+        [0.84247906, 0.0784336, 0.079223745],
+        [0.042328242, 0.87846864, 0.07916613],
+        [0.042375655, 0.0784336, 0.879143],
+    ];
+    const OUTSET: [[f32; 3]; 3] = [
+        [1.196879, -0.09802088, -0.09902974],
+        [-0.052896852, 1.1519031, -0.098961177],
+        [-0.052971636, -0.09804345, 1.1510737],
+    ];
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let contrast = |x: f32| {
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+
+    let encoded = multiply(&INSET, color).map(|c| {
+        let ev = f32::log2(c.max(1e-10)).clamp(MIN_EV, MAX_EV);
+        contrast((ev - MIN_EV) / (MAX_EV - MIN_EV))
+    });
+
+    // The curve outputs display values for a 2.2 power display
+    multiply(&OUTSET, encoded).map(|c| c.max(0.0).powf(2.2))
+}
+
+fn multiply(matrix: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+/// Triangular noise within -1..1 from a hash of the channel index, so the output stays the same
+/// from one run to the next
+fn dither(i: u32) -> f32 {
+    let uniform = |seed: u32| {
+        // Wang hash
+        let mut h = (seed ^ 61) ^ (seed >> 16);
+        h = h.wrapping_mul(9);
+        h ^= h >> 4;
+        h = h.wrapping_mul(0x27d4eb2d);
+        h ^= h >> 15;
+        h as f32 / u32::MAX as f32
+    };
+
+    uniform(2 * i) + uniform(2 * i + 1) - 1.0
+}
+
+impl ToneMapOperator {
+    pub const NAMES: [&'static str; 5] = ["clamp", "reinhard", "extended-reinhard", "aces", "agx"];
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" | "none" => Ok(ToneMapOperator::Clamp),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "extended-reinhard" => Ok(ToneMapOperator::ExtendedReinhard),
+            "aces" => Ok(ToneMapOperator::Aces),
+            "agx" => Ok(ToneMapOperator::Agx),
+            _ => Err(format!(
+                "unknown tone mapping operator '{}', expected one of: {}",
+                s,
+                ToneMapOperator::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl FromStr for TransferFunction {
+    type Err = String;
+
+    /// `srgb`, `linear` or a gamma such as `2.2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "srgb" => Ok(TransferFunction::Srgb),
+            "linear" => Ok(TransferFunction::Linear),
+            gamma => match gamma.parse::<f32>() {
+                Ok(gamma) => Ok(TransferFunction::Gamma(gamma)),
+                Err(_) => Err(format!(
+                    "unknown transfer function '{}', expected srgb, linear or a gamma",
+                    s
+                )),
+            },
+        }
+    }
+}
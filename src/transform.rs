@@ -0,0 +1,126 @@
+use crate::vec3::Vec3;
+use cgmath::{Deg, Matrix4, Point3, SquareMatrix, Transform as _, Vector3, Vector4};
+
+/// Affine transform, a column-major 4x4 matrix like glTF's
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Matrix4<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        matrix: Matrix4::from_cols(
+            Vector4::new(1.0, 0.0, 0.0, 0.0),
+            Vector4::new(0.0, 1.0, 0.0, 0.0),
+            Vector4::new(0.0, 0.0, 1.0, 0.0),
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+        ),
+    };
+
+    /// The bottom row is assumed to be `0, 0, 0, 1`
+    pub fn from_columns(columns: [[f32; 4]; 4]) -> Transform {
+        Transform {
+            matrix: columns.into(),
+        }
+    }
+
+    pub fn columns(&self) -> [[f32; 4]; 4] {
+        self.matrix.into()
+    }
+
+    pub fn scale(scale: Vec3) -> Transform {
+        Transform {
+            matrix: Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z),
+        }
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        Transform {
+            matrix: Matrix4::from_translation(vector(offset)),
+        }
+    }
+
+    /// Rotation of `degrees` around the x, then y, then z axis
+    pub fn rotate(degrees: Vec3) -> Transform {
+        let x = Matrix4::from_angle_x(Deg(degrees.x));
+        let y = Matrix4::from_angle_y(Deg(degrees.y));
+        let z = Matrix4::from_angle_z(Deg(degrees.z));
+        Transform { matrix: z * y * x }
+    }
+
+    /// Rotation of `degrees` around `axis`, counter-clockwise when the axis points towards the
+    /// viewer. `axis` doesn't have to be normalized but can't be zero.
+    pub fn rotate_axis(axis: Vec3, degrees: f32) -> Transform {
+        Transform {
+            matrix: Matrix4::from_axis_angle(vector(axis.unit()), Deg(degrees)),
+        }
+    }
+
+    /// Applies `self`, then `next`
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            matrix: next.matrix * self.matrix,
+        }
+    }
+
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        let p = self.matrix.transform_point(Point3::new(p.x, p.y, p.z));
+        Vec3::new(p.x, p.y, p.z)
+    }
+
+    /// Transforms a direction, ignoring the translation
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let v = self.matrix.transform_vector(vector(v));
+        Vec3::new(v.x, v.y, v.z)
+    }
+
+    /// Transforms a surface normal so it stays perpendicular to the transformed surface. The
+    /// result isn't normalized.
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        // The columns of the cofactor matrix, the inverse transpose scaled by the determinant
+        let [x, y, z] = self.axes();
+        let cofactors = [y.cross(&z), z.cross(&x), x.cross(&y)];
+        let sign = self.determinant().signum();
+
+        (cofactors[0] * n.x + cofactors[1] * n.y + cofactors[2] * n.z) * sign
+    }
+
+    /// Translation part of the transform
+    pub fn offset(&self) -> Vec3 {
+        let w = self.matrix.w;
+        Vec3::new(w.x, w.y, w.z)
+    }
+
+    /// Determinant of the linear part, negative when the transform mirrors
+    pub fn determinant(&self) -> f32 {
+        // The bottom row of affine transforms leaves only the linear part
+        self.matrix.determinant()
+    }
+
+    /// `None` when the transform collapses space onto a plane, line or point
+    pub fn inverse(&self) -> Option<Transform> {
+        if !self.determinant().is_finite() {
+            return None;
+        }
+        self.matrix.invert().map(|matrix| Transform { matrix })
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.matrix.is_finite()
+    }
+
+    /// The transformed x, y and z axes
+    fn axes(&self) -> [Vec3; 3] {
+        [self.matrix.x, self.matrix.y, self.matrix.z].map(|axis| Vec3::new(axis.x, axis.y, axis.z))
+    }
+}
+
+fn vector(v: Vec3) -> Vector3<f32> {
+    Vector3::new(v.x, v.y, v.z)
+}
@@ -1,10 +1,12 @@
 use bytemuck::{Pod, Zeroable};
 use display_json::DebugAsJsonPretty;
 use rand::distributions::{Distribution, Uniform};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::ops;
 
-#[derive(Copy, Clone, PartialEq, Zeroable, Pod, Default, Serialize, DebugAsJsonPretty)]
+#[derive(
+    Copy, Clone, PartialEq, Zeroable, Pod, Default, Serialize, Deserialize, DebugAsJsonPretty,
+)]
 #[repr(C)]
 pub struct Vec3 {
     pub x: f32,
@@ -62,7 +64,7 @@ impl Vec3 {
     }
 
     pub fn random_in_unit_sphere() -> Vec3 {
-        let uniform_sampler = Uniform::from(0.0..1.0);
+        let uniform_sampler = Uniform::from(-1.0..1.0);
         loop {
             let p = Vec3::random_in_bounds(&uniform_sampler);
 
@@ -109,6 +111,24 @@ impl Vec3 {
         f32::abs(self.x) < 1e-8 && f32::abs(self.y) < 1e-8 && f32::abs(self.z) < 1e-8
     }
 
+    /// Component-wise minimum
+    pub fn min(&self, rhs: &Vec3) -> Vec3 {
+        Vec3 {
+            x: f32::min(self.x, rhs.x),
+            y: f32::min(self.y, rhs.y),
+            z: f32::min(self.z, rhs.z),
+        }
+    }
+
+    /// Component-wise maximum
+    pub fn max(&self, rhs: &Vec3) -> Vec3 {
+        Vec3 {
+            x: f32::max(self.x, rhs.x),
+            y: f32::max(self.y, rhs.y),
+            z: f32::max(self.z, rhs.z),
+        }
+    }
+
     pub fn refract(&self, normal: &Vec3, etai_over_etat: f32) -> Vec3 {
         let cos_theta = f32::min((-(*self)).dot(normal), 1.0);
 
@@ -183,6 +203,25 @@ impl ops::Div<f32> for Vec3 {
     }
 }
 
+impl From<[f32; 3]> for Vec3 {
+    fn from([x, y, z]: [f32; 3]) -> Self {
+        Vec3 { x, y, z }
+    }
+}
+
+impl ops::Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 has no axis {}", axis),
+        }
+    }
+}
+
 impl ops::Neg for Vec3 {
     type Output = Vec3;
 
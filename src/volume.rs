@@ -0,0 +1,114 @@
+use crate::instance::ObjectId;
+use crate::material::MaterialId;
+use crate::vec3::Vec3;
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+use std::fmt;
+
+/// Homogeneous participating medium filling a closed object, the `ConstantMedium` of the second
+/// book. Rays travelling through it scatter after a random distance, the denser the medium the
+/// sooner, in a direction picked by its `Isotropic` material.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Volume {
+    object: ObjectId,
+    density: f32,
+    material: MaterialId,
+}
+
+/// Fog filling the whole scene: the light coming from a surface at distance `d` is blended into
+/// `color` by `1 - exp(-density * d)`. The background is infinitely far, rays escaping the scene
+/// see `color` instead.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fog {
+    pub color: Vec3,
+    pub density: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VolumeError {
+    /// The object isn't part of the scene
+    UnknownObject(ObjectId),
+    /// The object has no primitives, there would be no boundary
+    EmptyObject(ObjectId),
+    /// The density must be finite and greater than 0
+    InvalidDensity(f32),
+    /// The material isn't part of the scene
+    UnknownMaterial(MaterialId),
+    /// Volumes scatter light with an `Isotropic` material
+    NotIsotropic(MaterialId),
+}
+
+/// Volume as laid out in `compute.glsl` (std430)
+#[derive(Pod, Zeroable, Copy, Clone, Default)]
+#[repr(C)]
+pub(crate) struct GpuVolume {
+    /// Node of the BVH the hierarchy of the boundary starts at
+    root: u32,
+    density: f32,
+    /// Index into `Scene::materials`
+    material: u32,
+    _0: u32,
+}
+
+impl Volume {
+    /// `None` when the density isn't finite and greater than 0
+    pub fn new(object: ObjectId, density: f32, material: MaterialId) -> Option<Volume> {
+        if !(density.is_finite() && density > 0.0) {
+            return None;
+        }
+
+        Some(Volume {
+            object,
+            density,
+            material,
+        })
+    }
+
+    /// The object whose surface bounds the volume, in world space
+    pub fn object(&self) -> ObjectId {
+        self.object
+    }
+
+    pub fn density(&self) -> f32 {
+        self.density
+    }
+
+    pub fn material(&self) -> MaterialId {
+        self.material
+    }
+
+    /// `root` is the node of the BVH the hierarchy of the object starts at
+    pub(crate) fn lower(&self, root: u32) -> GpuVolume {
+        GpuVolume {
+            root,
+            density: self.density,
+            material: self.material.0,
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for VolumeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VolumeError::UnknownObject(id) => {
+                write!(f, "object {} isn't part of the scene", id.index())
+            }
+            VolumeError::EmptyObject(id) => {
+                write!(f, "object {} has no primitives", id.index())
+            }
+            VolumeError::InvalidDensity(density) => {
+                write!(f, "the density must be greater than 0, got {}", density)
+            }
+            VolumeError::UnknownMaterial(id) => {
+                write!(f, "the scene has no material {}", id.index())
+            }
+            VolumeError::NotIsotropic(id) => {
+                write!(f, "material {} isn't Isotropic", id.index())
+            }
+        }
+    }
+}
+
+impl std::error::Error for VolumeError {}
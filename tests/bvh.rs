@@ -0,0 +1,36 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use raytracer::{Aabb, Bvh, Ray, SceneDescription, Vec3};
+
+#[test]
+fn traversal_visits_every_primitive_hit() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let (_, scene) = SceneDescription::random(&mut rng).build().unwrap();
+
+    let bounds: Vec<Aabb> = scene.spheres().iter().map(|s| s.bounds()).collect();
+    let bvh = Bvh::build(&bounds);
+    let ordered = bvh.reorder(&bounds);
+
+    let stats = bvh.stats();
+    assert_eq!(stats.primitive_count, bounds.len());
+    assert_eq!(stats.node_count, 2 * stats.leaf_count - 1);
+
+    for _ in 0..1000 {
+        let mut random = || Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2.0 - Vec3::ONE;
+        let ray = Ray::new(random() * 12.0 + Vec3::new(0.0, 2.0, 0.0), random(), 0.0);
+        let inv_dir = Vec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+
+        // Never reporting a hit keeps t_max at infinity, so every overlapping leaf is visited
+        let mut visited = vec![false; ordered.len()];
+        bvh.traverse(&ray, 0.001, f32::INFINITY, |i, _| {
+            visited[i] = true;
+            None
+        });
+
+        for (i, bounds) in ordered.iter().enumerate() {
+            if bounds.hit(&ray, &inv_dir, 0.001, f32::INFINITY) {
+                assert!(visited[i], "primitive {} was skipped", i);
+            }
+        }
+    }
+}
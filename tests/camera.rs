@@ -0,0 +1,118 @@
+use raytracer::{Camera, CameraError, Vec3};
+
+fn assert_near(actual: Vec3, expected: Vec3) {
+    assert!(
+        (actual - expected).length() < 1e-5,
+        "{:?} isn't {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn look_at_rejects_degenerate_views() {
+    let from = Vec3::new(1.0, 2.0, 3.0);
+    let at = Vec3::new(1.0, 2.0, 0.0);
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let look_at = |from, at, up| Camera::look_at(from, at, up, 60.0, 1.5, 0.0, None).err();
+
+    assert_eq!(look_at(from, from, up), Some(CameraError::NoViewDirection));
+    assert_eq!(
+        look_at(from, at, Vec3::ZERO),
+        Some(CameraError::DegenerateUp)
+    );
+    // Up along the view direction, either way
+    assert_eq!(
+        look_at(from, at, Vec3::new(0.0, 0.0, -2.0)),
+        Some(CameraError::DegenerateUp)
+    );
+    assert_eq!(
+        look_at(from, at, Vec3::new(0.0, 0.0, 1.0)),
+        Some(CameraError::DegenerateUp)
+    );
+    assert_eq!(look_at(from, at, up), None);
+
+    let camera = |vfov, aspect_ratio, aperture, focus_dist| {
+        Camera::look_at(from, at, up, vfov, aspect_ratio, aperture, focus_dist).err()
+    };
+    assert_eq!(
+        camera(0.0, 1.5, 0.0, None),
+        Some(CameraError::InvalidFov(0.0))
+    );
+    assert_eq!(
+        camera(180.0, 1.5, 0.0, None),
+        Some(CameraError::InvalidFov(180.0))
+    );
+    assert_eq!(
+        camera(60.0, 0.0, 0.0, None),
+        Some(CameraError::InvalidAspectRatio(0.0))
+    );
+    assert_eq!(
+        camera(60.0, 1.5, -1.0, None),
+        Some(CameraError::NegativeAperture(-1.0))
+    );
+    assert_eq!(
+        camera(60.0, 1.5, 0.1, Some(0.0)),
+        Some(CameraError::InvalidFocusDistance(0.0))
+    );
+
+    let camera = Camera::look_at(from, at, up, 60.0, 1.5, 0.0, None).unwrap();
+    assert_eq!(
+        camera.shutter(0.6, 0.4).err(),
+        Some(CameraError::InvalidShutter(0.6, 0.4))
+    );
+    assert_eq!(
+        camera.shutter(0.0, 1.5).err(),
+        Some(CameraError::InvalidShutter(0.0, 1.5))
+    );
+}
+
+#[test]
+fn look_at_focuses_on_the_target_by_default() {
+    let from = Vec3::new(0.0, 0.0, 4.0);
+    let at = Vec3::new(0.0, 0.0, 1.0);
+    let up = Vec3::new(0.0, 1.0, 0.0);
+
+    // The viewport lies on the plane in focus, its center straight ahead
+    let center = |camera: Camera| camera.ray(0.5, 0.5).dir;
+    let auto = Camera::look_at(from, at, up, 40.0, 1.0, 0.5, None).unwrap();
+    assert_near(center(auto), Vec3::new(0.0, 0.0, -3.0));
+    assert_eq!(auto.lens_radius, 0.25);
+
+    let fixed = Camera::look_at(from, at, up, 40.0, 1.0, 0.5, Some(10.0)).unwrap();
+    assert_near(center(fixed), Vec3::new(0.0, 0.0, -10.0));
+
+    // Only the distance changes, not the field of view
+    let ratio = fixed.horizontal.length() / auto.horizontal.length();
+    assert!((ratio - 10.0 / 3.0).abs() < 1e-5, "{}", ratio);
+}
+
+#[test]
+fn ray_maps_the_viewport_corners() {
+    // 90 degrees up and down, twice as wide, focused at 1
+    let camera = Camera::look_at(
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        90.0,
+        2.0,
+        0.0,
+        None,
+    )
+    .unwrap()
+    .shutter(0.25, 0.75)
+    .unwrap();
+
+    let corners = [
+        (0.0, 0.0, Vec3::new(-2.0, -1.0, -1.0)),
+        (1.0, 0.0, Vec3::new(2.0, -1.0, -1.0)),
+        (0.0, 1.0, Vec3::new(-2.0, 1.0, -1.0)),
+        (1.0, 1.0, Vec3::new(2.0, 1.0, -1.0)),
+    ];
+    for (u, v, dir) in corners {
+        let ray = camera.ray(u, v);
+        assert_eq!(ray.origin, Vec3::new(1.0, 1.0, 1.0));
+        assert_near(ray.dir, dir);
+        assert_eq!(ray.time, 0.25);
+    }
+}
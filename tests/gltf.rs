@@ -0,0 +1,276 @@
+use raytracer::gltf;
+use raytracer::{ColorSpace, Material, SceneDescription, Texture, Vec3, WrapMode};
+use std::fs::{self, File};
+
+/// Two nodes, the child scaling a triangle that the parent moves up, and a camera 5 units in front
+/// of the origin. The buffer holds the positions then the indices.
+const DOCUMENT: &str = r#"{
+    "asset": { "version": "2.0" },
+    "extensionsUsed": ["KHR_materials_transmission", "KHR_lights_punctual"],
+    "scene": 0,
+    "scenes": [{ "nodes": [0, 2] }],
+    "nodes": [
+        { "translation": [0, 1, 0], "children": [1] },
+        { "scale": [2, 2, 2], "mesh": 0 },
+        { "translation": [0, 0, 5], "camera": 0 }
+    ],
+    "cameras": [{
+        "type": "perspective",
+        "perspective": { "yfov": 0.5, "aspectRatio": 2.0, "znear": 0.1 }
+    }],
+    "meshes": [{
+        "primitives": [
+            { "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 },
+            { "attributes": { "POSITION": 0 }, "indices": 1, "material": 1 },
+            { "attributes": { "POSITION": 0 }, "indices": 1, "material": 2 }
+        ]
+    }],
+    "materials": [
+        {
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [0.9, 0.8, 0.2, 1.0],
+                "metallicFactor": 1.0,
+                "roughnessFactor": 0.25
+            }
+        },
+        { "emissiveFactor": [1.0, 0.5, 0.0] },
+        {
+            "pbrMetallicRoughness": { "metallicFactor": 0.0 },
+            "extensions": { "KHR_materials_transmission": { "transmissionFactor": 1.0 } }
+        }
+    ],
+    "accessors": [
+        {
+            "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+            "min": [0, 0, 0], "max": [1, 1, 0]
+        },
+        { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+    ],
+    "bufferViews": [
+        { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+        { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+    ],
+    "buffers": [BUFFER]
+}"#;
+
+fn buffer() -> Vec<u8> {
+    let positions = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+    let indices = [0u16, 1, 2];
+
+    let mut buffer: Vec<u8> = positions.iter().flat_map(|p| p.to_le_bytes()).collect();
+    buffer.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+    buffer.resize(44, 0);
+    buffer
+}
+
+/// Binary glTF: a header, then the JSON and binary chunks padded to 4 bytes
+fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+    let mut json = json.as_bytes().to_vec();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+
+    let mut glb = Vec::new();
+    glb.extend(b"glTF");
+    glb.extend(2u32.to_le_bytes());
+    glb.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(json);
+    glb.extend((bin.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(bin);
+    glb
+}
+
+#[test]
+fn gltf_nodes_materials_and_cameras_are_imported() {
+    let directory = std::env::temp_dir().join("hikari_gltf_nodes_materials_and_cameras");
+    fs::create_dir_all(&directory).unwrap();
+
+    // The same document with an external buffer and with the buffer embedded in a GLB
+    fs::write(directory.join("triangle.bin"), buffer()).unwrap();
+    fs::write(
+        directory.join("external.gltf"),
+        DOCUMENT.replace("BUFFER", r#"{ "byteLength": 44, "uri": "triangle.bin" }"#),
+    )
+    .unwrap();
+    fs::write(
+        directory.join("embedded.glb"),
+        glb(
+            &DOCUMENT.replace("BUFFER", r#"{ "byteLength": 44 }"#),
+            &buffer(),
+        ),
+    )
+    .unwrap();
+
+    let external = gltf::load(&directory.join("external.gltf")).unwrap();
+    let embedded = gltf::load(&directory.join("embedded.glb")).unwrap();
+    let camera = gltf::load_camera(&directory.join("embedded.glb")).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    for model in [&external, &embedded] {
+        assert_eq!(model.meshes.len(), 3);
+
+        let mesh = &model.meshes[0].mesh;
+        assert_eq!(
+            mesh.positions,
+            [
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(2.0, 1.0, 0.0),
+                Vec3::new(0.0, 3.0, 0.0)
+            ]
+        );
+        assert_eq!(mesh.indices, [[0, 1, 2]]);
+
+        let materials: Vec<_> = model.meshes.iter().map(|m| m.material).collect();
+        assert_eq!(materials, [Some(0), Some(1), Some(2)]);
+        assert_eq!(
+            model.materials,
+            [
+                Material::Metal {
+                    albedo: Vec3::new(0.9, 0.8, 0.2),
+                    fuzz: 0.25,
+                    texture: None,
+                    fuzz_texture: None
+                },
+                Material::DiffuseLight {
+                    color: Vec3::new(1.0, 0.5, 0.0),
+                    intensity: 1.0,
+                    texture: None
+                },
+                Material::Dielectric { ior: 1.5 },
+            ]
+        );
+
+        let warnings: Vec<_> = model.warnings.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(warnings, ["extension 'KHR_lights_punctual' ignored"]);
+    }
+
+    assert_eq!(camera.look_from, Vec3::new(0.0, 0.0, 5.0));
+    assert_eq!(camera.look_at, Vec3::new(0.0, 0.0, 4.0));
+    assert_eq!(camera.vup, Vec3::new(0.0, 1.0, 0.0));
+    assert!((camera.vfov - 0.5f32.to_degrees()).abs() < 1e-4);
+    assert_eq!(camera.aspect_ratio, Some(2.0));
+}
+
+/// The triangle of `DOCUMENT` three times, with materials sampling a 2x1 image in different ways
+const TEXTURED: &str = r#"{
+    "asset": { "version": "2.0" },
+    "scenes": [{ "nodes": [0] }],
+    "nodes": [{ "mesh": 0 }],
+    "meshes": [{
+        "primitives": [
+            { "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 },
+            { "attributes": { "POSITION": 0 }, "indices": 1, "material": 1 },
+            { "attributes": { "POSITION": 0 }, "indices": 1, "material": 2 }
+        ]
+    }],
+    "materials": [
+        {
+            "pbrMetallicRoughness": {
+                "baseColorTexture": { "index": 0 },
+                "metallicFactor": 1.0,
+                "roughnessFactor": 0.5,
+                "metallicRoughnessTexture": { "index": 0 }
+            }
+        },
+        {
+            "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 }, "metallicFactor": 0.0 },
+            "occlusionTexture": { "index": 0 }
+        },
+        { "emissiveFactor": [1.0, 1.0, 1.0], "emissiveTexture": { "index": 0, "texCoord": 1 } }
+    ],
+    "textures": [{ "source": 0, "sampler": 0 }],
+    "samplers": [{ "wrapS": 33648, "wrapT": 33648 }],
+    "images": [{ "uri": "texture.png" }],
+    "accessors": [
+        {
+            "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+            "min": [0, 0, 0], "max": [1, 1, 0]
+        },
+        { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+    ],
+    "bufferViews": [
+        { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+        { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+    ],
+    "buffers": [{ "byteLength": 44, "uri": "triangle.bin" }]
+}"#;
+
+#[test]
+fn gltf_textures_are_imported() {
+    let directory = std::env::temp_dir().join("hikari_gltf_textures_are_imported");
+    fs::create_dir_all(&directory).unwrap();
+
+    let mut encoder = png::Encoder::new(File::create(directory.join("texture.png")).unwrap(), 2, 1);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&[255, 64, 0, 0, 128, 255]).unwrap();
+    writer.finish().unwrap();
+
+    fs::write(directory.join("triangle.bin"), buffer()).unwrap();
+    fs::write(directory.join("textured.gltf"), TEXTURED).unwrap();
+
+    let model = gltf::load(&directory.join("textured.gltf")).unwrap();
+    let built = SceneDescription::load(&directory.join("textured.gltf"))
+        .and_then(|description| description.build());
+    fs::remove_dir_all(&directory).unwrap();
+
+    // The base color and the roughness of the same texture are two textures
+    assert_eq!(
+        model.materials,
+        [
+            Material::Metal {
+                albedo: Vec3::ONE,
+                fuzz: 0.5,
+                texture: Some(0),
+                fuzz_texture: Some(1)
+            },
+            Material::Lambertian {
+                albedo: Vec3::ONE,
+                texture: Some(0)
+            },
+            Material::DiffuseLight {
+                color: Vec3::ONE,
+                intensity: 1.0,
+                texture: None
+            },
+        ]
+    );
+
+    match &model.textures[..] {
+        [Texture::Image {
+            image: color,
+            wrap: WrapMode::Mirror,
+            color_space: ColorSpace::Srgb,
+        }, Texture::Image {
+            image: roughness,
+            wrap: WrapMode::Mirror,
+            color_space: ColorSpace::Linear,
+        }] => {
+            assert_eq!(color.texel(0, 0), [255, 64, 0, 255]);
+            assert_eq!(color.texel(1, 0), [0, 128, 255, 255]);
+            assert_eq!(roughness.texel(0, 0), [64, 64, 64, 255]);
+            assert_eq!(roughness.texel(1, 0), [128, 128, 128, 255]);
+        }
+        textures => panic!("unexpected textures {:?}", textures),
+    }
+
+    let warnings: Vec<_> = model.warnings.iter().map(|w| w.message.as_str()).collect();
+    assert_eq!(
+        warnings,
+        [
+            "normal and occlusion textures ignored",
+            "textures using a second set of texture coordinates ignored"
+        ]
+    );
+
+    let (_, scene) = built.unwrap();
+    assert_eq!(scene.textures().len(), 2);
+    let textures: Vec<usize> = scene
+        .materials()
+        .iter()
+        .map(|m| m.textures().count())
+        .collect();
+    assert_eq!(textures, [2, 1, 0]);
+}
@@ -0,0 +1,236 @@
+use raytracer::{
+    GeometryError, Material, MaterialError, Mesh, MeshError, Plane, Rect, Scene, SceneDescription,
+    SceneError, Vec3,
+};
+use std::path::Path;
+
+#[test]
+fn validate_rejects_out_of_range_values() {
+    let gray = Vec3::new(0.5, 0.5, 0.5);
+    let valid: [Material; 5] = [
+        Material::Lambertian {
+            albedo: gray,
+            texture: None,
+        },
+        Material::Metal {
+            albedo: Vec3::ONE,
+            fuzz: 1.0,
+            texture: None,
+            fuzz_texture: None,
+        },
+        Material::Dielectric { ior: 1.5 },
+        Material::DiffuseLight {
+            color: Vec3::ONE,
+            intensity: 20.0,
+            texture: None,
+        },
+        Material::Isotropic {
+            albedo: Vec3::ZERO,
+            texture: None,
+        },
+    ];
+    for material in valid {
+        assert_eq!(material.validate(), Ok(()), "{:?}", material);
+    }
+
+    let too_bright = Vec3::new(0.5, 1.5, 0.5);
+    let invalid: [(Material, MaterialError); 9] = [
+        (
+            Material::Lambertian {
+                albedo: too_bright,
+                texture: None,
+            },
+            MaterialError::InvalidAlbedo(too_bright),
+        ),
+        (
+            Material::Isotropic {
+                albedo: -gray,
+                texture: None,
+            },
+            MaterialError::InvalidAlbedo(-gray),
+        ),
+        (
+            Material::Metal {
+                albedo: too_bright,
+                fuzz: 0.0,
+                texture: None,
+                fuzz_texture: None,
+            },
+            MaterialError::InvalidAlbedo(too_bright),
+        ),
+        (
+            Material::Metal {
+                albedo: gray,
+                fuzz: 1.5,
+                texture: None,
+                fuzz_texture: None,
+            },
+            MaterialError::InvalidFuzz(1.5),
+        ),
+        (
+            Material::Dielectric { ior: 0.0 },
+            MaterialError::InvalidIor(0.0),
+        ),
+        (
+            Material::Dielectric { ior: f32::INFINITY },
+            MaterialError::InvalidIor(f32::INFINITY),
+        ),
+        (
+            Material::DiffuseLight {
+                color: too_bright,
+                intensity: 1.0,
+                texture: None,
+            },
+            MaterialError::InvalidColor(too_bright),
+        ),
+        (
+            Material::DiffuseLight {
+                color: Vec3::ONE,
+                intensity: -1.0,
+                texture: None,
+            },
+            MaterialError::InvalidIntensity(-1.0),
+        ),
+        (
+            Material::DiffuseLight {
+                color: Vec3::ONE,
+                intensity: f32::INFINITY,
+                texture: None,
+            },
+            MaterialError::InvalidIntensity(f32::INFINITY),
+        ),
+    ];
+    for (material, error) in invalid {
+        assert_eq!(material.validate(), Err(error), "{:?}", material);
+    }
+
+    // Nothing compares equal to NaN, check the variant only
+    let nan: Material = Material::Metal {
+        albedo: gray,
+        fuzz: f32::NAN,
+        texture: None,
+        fuzz_texture: None,
+    };
+    assert!(matches!(nan.validate(), Err(MaterialError::InvalidFuzz(_))));
+}
+
+#[test]
+fn scene_rejects_materials_of_other_scenes() {
+    let lambertian = Material::Lambertian {
+        albedo: Vec3::ONE,
+        texture: None,
+    };
+    let mut other = Scene::new();
+    other.add_material(lambertian).unwrap();
+    let foreign = other.add_material(lambertian).unwrap();
+
+    let mut scene = Scene::new();
+    let material = scene.add_material(lambertian).unwrap();
+    let unknown = Err(GeometryError::UnknownMaterial(foreign));
+
+    assert_eq!(scene.add_sphere(Vec3::ZERO, 1.0, foreign), unknown);
+    assert_eq!(
+        scene.add_rect(Rect::new(Plane::Xy, [0.0, 0.0], [1.0, 1.0], 0.0, foreign)),
+        unknown
+    );
+    assert_eq!(scene.add_box(Vec3::ZERO, Vec3::ONE, foreign), unknown);
+    let mesh = Mesh {
+        positions: vec![
+            Vec3::ZERO,
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ],
+        indices: vec![[0, 1, 2]],
+        ..Default::default()
+    };
+    assert_eq!(
+        scene.add_mesh(&mesh, foreign),
+        Err(MeshError::UnknownMaterial(foreign))
+    );
+    assert!(scene.spheres().is_empty() && scene.rects().is_empty());
+    assert!(scene.triangles().is_empty());
+
+    for radius in [0.0, -0.5, f32::INFINITY] {
+        assert_eq!(
+            scene.add_sphere(Vec3::ZERO, radius, material),
+            Err(GeometryError::InvalidRadius(radius))
+        );
+    }
+    assert_eq!(
+        scene.add_rect(Rect::new(Plane::Xz, [0.0, 1.0], [1.0, 1.0], 0.0, material)),
+        Err(GeometryError::InvalidCorners)
+    );
+    assert_eq!(
+        scene.add_box(Vec3::ONE, Vec3::ZERO, material),
+        Err(GeometryError::InvalidCorners)
+    );
+
+    scene.add_sphere(Vec3::ZERO, 1.0, material).unwrap();
+    scene
+        .add_moving_sphere(Vec3::ZERO, Vec3::ONE, 0.5, material)
+        .unwrap();
+    scene
+        .add_rect(Rect::new(Plane::Xy, [0.0, 0.0], [1.0, 1.0], 0.0, material))
+        .unwrap();
+    scene.add_box(Vec3::ZERO, Vec3::ONE, material).unwrap();
+    scene.add_mesh(&mesh, material).unwrap();
+    assert_eq!(scene.spheres().len(), 2);
+    assert_eq!(scene.rects().len(), 7);
+    assert_eq!(scene.triangles().len(), 1);
+}
+
+#[test]
+fn scene_files_reject_single_pixel_images() {
+    let description =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+
+    // A single column or row would divide by `width - 1` or `height - 1` = 0
+    for (width, height) in [(0, 8), (1, 8), (8, 1), (1, 1)] {
+        let mut invalid = description.clone();
+        invalid.settings.width = width;
+        invalid.settings.height = height;
+        assert!(
+            matches!(invalid.validate(), Err(SceneError::Invalid { field, .. }) if field == "settings"),
+            "{}x{}",
+            width,
+            height
+        );
+    }
+
+    let mut smallest = description;
+    smallest.settings.width = 2;
+    smallest.settings.height = 2;
+    assert!(smallest.validate().is_ok());
+}
+
+#[test]
+fn scene_files_reject_degenerate_spheres() {
+    let description =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    let field = |description: &SceneDescription| match description.build() {
+        Err(SceneError::Invalid { field, .. }) => field,
+        Err(why) => panic!("unexpected error {}", why),
+        Ok(_) => panic!("the scene is valid"),
+    };
+
+    // Errors rather than panics when adding them to the Scene
+    for radius in [0.0, f32::NAN, f32::INFINITY] {
+        let mut invalid = description.clone();
+        invalid.spheres[0].radius = radius;
+        assert_eq!(field(&invalid), "spheres[0].radius", "{}", radius);
+    }
+
+    // Non-finite motion would give the BVH NaN bounds
+    let infinite = Vec3::new(0.0, f32::INFINITY, 0.0);
+    let mut invalid = description.clone();
+    invalid.spheres[0].center = infinite;
+    assert_eq!(field(&invalid), "spheres[0].center");
+    let mut invalid = description.clone();
+    invalid.spheres[0].center1 = Some(infinite);
+    assert_eq!(field(&invalid), "spheres[0].center1");
+    let mut invalid = description.clone();
+    invalid.spheres[0].velocity = Some(Vec3::new(f32::NAN, 0.0, 0.0));
+    assert_eq!(field(&invalid), "spheres[0].velocity");
+}
@@ -0,0 +1,133 @@
+use raytracer::obj;
+use raytracer::{Material, ModelWarning, SceneDescription, Vec3};
+use std::fs;
+
+#[test]
+fn obj_faces_and_materials_are_imported() {
+    let directory = std::env::temp_dir().join("hikari_obj_faces_and_materials_are_imported");
+    fs::create_dir_all(&directory).unwrap();
+
+    fs::write(
+        directory.join("scene.mtl"),
+        "newmtl matte\n\
+         Kd 0.5 0.25 0.125\n\
+         map_Kd matte.png\n\
+         newmtl chrome\n\
+         Kd 0.1 0.1 0.1\n\
+         Ks 0.9 0.9 0.9\n\
+         Ns 1000\n\
+         newmtl glass\n\
+         Ni 1.33\n\
+         d 0.1\n\
+         newmtl lamp\n\
+         Ke 4 2 0\n",
+    )
+    .unwrap();
+
+    fs::write(
+        directory.join("scene.obj"),
+        "mtllib scene.mtl\n\
+         v 0 0 0\n\
+         v 1 0 0\n\
+         v 1 1 0\n\
+         v 0 1 0\n\
+         vt 0 0\n\
+         vn 0 0 1\n\
+         usemtl matte\n\
+         # A quad with negative indices, split in two triangles\n\
+         f -4/1/1 -3/1/1 -2/1/1 -1/1/1\n\
+         usemtl chrome\n\
+         s 1\n\
+         f 1 2 3\n\
+         f 1 3 4\n\
+         usemtl missing\n\
+         l 1 2\n\
+         l 2 3\n\
+         f 1 2 4\n",
+    )
+    .unwrap();
+
+    let model = obj::load(&directory.join("scene.obj")).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(
+        model.materials["matte"],
+        Material::Lambertian {
+            albedo: Vec3::new(0.5, 0.25, 0.125),
+            texture: None
+        }
+    );
+    assert!(matches!(model.materials["chrome"], Material::Metal { fuzz, .. } if fuzz < 0.1));
+    assert_eq!(model.materials["glass"], Material::Dielectric { ior: 1.33 });
+    assert_eq!(
+        model.materials["lamp"],
+        Material::DiffuseLight {
+            color: Vec3::new(1.0, 0.5, 0.0),
+            intensity: 4.0,
+            texture: None
+        }
+    );
+
+    let materials: Vec<_> = model.meshes.iter().map(|m| m.material.as_deref()).collect();
+    assert_eq!(materials, [Some("matte"), Some("chrome"), None]);
+
+    // The quad shares its 4 vertices between the two triangles
+    let quad = &model.meshes[0].mesh;
+    assert_eq!(quad.indices, [[0, 1, 2], [0, 2, 3]]);
+    assert_eq!(quad.uvs.len(), 4);
+    assert_eq!(quad.normals, [Vec3::new(0.0, 0.0, 1.0); 4]);
+
+    // Smoothed from the faces, the shared edge averages both triangles
+    let smooth = &model.meshes[1].mesh;
+    assert_eq!(smooth.positions.len(), 4);
+    assert!(smooth
+        .normals
+        .iter()
+        .all(|n| n.x == 0.0 && n.y == 0.0 && n.z > 0.0));
+
+    let warnings: Vec<String> = model.warnings.iter().map(|w| w.message.clone()).collect();
+    assert!(warnings.iter().any(|w| w.contains("'map_Kd'")));
+    assert!(warnings.iter().any(|w| w.contains("'l' ignored (2 times)")));
+    assert!(warnings.iter().any(|w| w.contains("'missing'")));
+}
+
+#[test]
+fn model_warnings_are_returned_by_build() {
+    let directory = std::env::temp_dir().join("hikari_model_warnings_are_returned_by_build");
+    fs::create_dir_all(&directory).unwrap();
+
+    fs::write(
+        directory.join("triangle.obj"),
+        "v 0 0 -1\n\
+         v 1 0 -1\n\
+         v 0 1 -1\n\
+         l 1 2\n\
+         f 1 2 3\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("scene.ron"),
+        r#"(
+            camera: (
+                look_from: (x: 0.0, y: 0.0, z: 0.0),
+                look_at: (x: 0.0, y: 0.0, z: -1.0),
+                vfov: 40.0,
+            ),
+            materials: {},
+            spheres: [],
+            models: [(path: "triangle.obj")],
+        )"#,
+    )
+    .unwrap();
+
+    let built = SceneDescription::load(&directory.join("scene.ron"))
+        .and_then(|description| description.build_with_warnings());
+    fs::remove_dir_all(&directory).unwrap();
+
+    let (config, _, warnings) = built.unwrap();
+    assert_eq!(config.num_triangles, 1);
+    match &warnings[..] {
+        [ModelWarning::Obj(warning)] => assert!(warning.message.contains("'l' ignored")),
+        warnings => panic!("unexpected warnings {:?}", warnings),
+    }
+}
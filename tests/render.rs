@@ -0,0 +1,997 @@
+use raytracer::{
+    image, Background, BoxDescription, Config, CpuRaytracer, Fog, ImageFormat, InstanceDescription,
+    LightSampling, Material, MeshDescription, ObjectDescription, Plane, ProgressiveOptions,
+    Raytracer, RaytracerError, RectDescription, Sampler, Scene, SceneDescription,
+    SphereDescription, ToneMapping, Vec3, VolumeDescription,
+};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+fn small_scene(width: u32, height: u32, sample_count: u32) -> (Config, Scene) {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    scene.settings.width = width;
+    scene.settings.height = height;
+    scene.settings.sample_count = sample_count;
+    scene.camera.aperture = 0.0;
+
+    scene.build().unwrap()
+}
+
+/// Average of the quantized channels, in 0..=255
+fn mean(pixels: &[f32]) -> f32 {
+    let quantized = ToneMapping::default().quantize(pixels);
+    quantized.iter().map(|&c| c as f32).sum::<f32>() / quantized.len() as f32
+}
+
+#[test]
+fn cpu_renders_scene_file() {
+    let (config, scene) = small_scene(24, 16, 4);
+    let output = CpuRaytracer::new(config, scene).raytrace();
+
+    assert_eq!(output.len(), 24 * 16 * 3);
+    assert!(output.iter().all(|&c| c.is_finite() && c >= 0.0));
+    assert!(mean(&output) > 0.0, "the image is black");
+}
+
+#[test]
+fn cpu_renders_triangle_mesh() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    scene.settings.width = 16;
+    scene.settings.height = 8;
+    scene.settings.sample_count = 2;
+    scene.background = Background::Constant(Vec3::ZERO);
+    scene.spheres.clear();
+
+    // A light filling the view: a big quad on the plane x = 0, facing the camera
+    scene.materials.insert(
+        String::from("light"),
+        Material::DiffuseLight {
+            color: Vec3::ONE,
+            intensity: 1.0,
+            texture: None,
+        },
+    );
+    scene.meshes.push(MeshDescription {
+        positions: vec![
+            Vec3::new(0.0, -100.0, -100.0),
+            Vec3::new(0.0, 100.0, -100.0),
+            Vec3::new(0.0, 100.0, 100.0),
+            Vec3::new(0.0, -100.0, 100.0),
+        ],
+        normals: vec![Vec3::new(1.0, 0.0, 0.0); 4],
+        uvs: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+        indices: vec![[0, 1, 2], [0, 2, 3]],
+        material: String::from("light"),
+    });
+
+    let (config, scene) = scene.build().unwrap();
+    assert_eq!(config.num_triangles, 2);
+
+    let output = CpuRaytracer::new(config, scene).raytrace();
+    assert!(
+        output.iter().all(|&c| (c - 1.0).abs() < 1e-6),
+        "some rays missed the quad"
+    );
+}
+
+#[test]
+fn cpu_stops_after_max_bounces() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    scene.settings.width = 4;
+    scene.settings.height = 4;
+    scene.settings.sample_count = 1;
+    scene.background = Background::Constant(Vec3::ONE);
+    scene.spheres.clear();
+    let (config, scene) = scene.build().unwrap();
+
+    // A raw `Config` isn't validated, the last bounce only ends the path, even the camera ray's
+    for (max_bounces, expected) in [(0, 0.0), (1, 0.0), (2, 1.0)] {
+        let mut config = config;
+        config.max_bounces = max_bounces;
+        let output = CpuRaytracer::new(config, scene.clone()).raytrace();
+        assert!(
+            output.iter().all(|&c| c == expected),
+            "{} bounces",
+            max_bounces
+        );
+    }
+}
+
+#[test]
+fn cpu_renders_lights_and_background() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    scene.settings.width = 9;
+    scene.settings.height = 9;
+    scene.settings.sample_count = 4;
+    scene.camera.look_from = Vec3::ZERO;
+    scene.camera.look_at = Vec3::new(0.0, 0.0, -1.0);
+    scene.camera.aperture = 0.0;
+    scene.camera.focus_dist = None;
+
+    // A light in the middle of the view, the corners see the background
+    scene.materials.insert(
+        String::from("light"),
+        Material::DiffuseLight {
+            color: Vec3::new(1.0, 0.5, 0.25),
+            intensity: 4.0,
+            texture: None,
+        },
+    );
+    scene.spheres = vec![SphereDescription {
+        center: Vec3::new(0.0, 0.0, -10.0),
+        radius: 1.0,
+        material: String::from("light"),
+        center1: None,
+        velocity: None,
+    }];
+    let pixel = |output: &[f32], x: usize, y: usize| {
+        let i = (y * 9 + x) * 3;
+        Vec3::new(output[i], output[i + 1], output[i + 2])
+    };
+    let assert_near = |actual: Vec3, expected: Vec3| {
+        assert!(
+            (actual - expected).length() < 1e-5,
+            "{:?} isn't {:?}",
+            actual,
+            expected
+        );
+    };
+
+    scene.background = Background::Constant(Vec3::new(0.1, 0.2, 0.3));
+    let (config, built) = scene.build().unwrap();
+    let output = CpuRaytracer::new(config, built).raytrace();
+    assert_near(pixel(&output, 4, 4), Vec3::new(4.0, 2.0, 1.0));
+    for (x, y) in [(0, 0), (8, 0), (0, 8), (8, 8)] {
+        assert_near(pixel(&output, x, y), Vec3::new(0.1, 0.2, 0.3));
+    }
+
+    // Blue straight down, red straight up, the bottom row comes first
+    scene.background = Background::Gradient {
+        bottom: Vec3::new(0.0, 0.0, 1.0),
+        top: Vec3::new(1.0, 0.0, 0.0),
+    };
+    let (config, built) = scene.build().unwrap();
+    let output = CpuRaytracer::new(config, built).raytrace();
+    assert_near(pixel(&output, 4, 4), Vec3::new(4.0, 2.0, 1.0));
+    let (bottom, top) = (pixel(&output, 0, 0), pixel(&output, 0, 8));
+    assert!(
+        bottom.x < top.x && bottom.z > top.z,
+        "{:?} {:?}",
+        bottom,
+        top
+    );
+    for color in [bottom, top] {
+        assert!((color.x + color.z - 1.0).abs() < 1e-5 && color.y == 0.0);
+    }
+
+    // Fog fades the light about 9 units away and hides the infinitely far background
+    let gray = Vec3::new(0.5, 0.5, 0.5);
+    scene.fog = Some(Fog {
+        color: gray,
+        density: 0.1,
+    });
+    let (config, built) = scene.build().unwrap();
+    let output = CpuRaytracer::new(config, built).raytrace();
+    let transmittance = (-0.1f32 * 9.0).exp();
+    let light = transmittance * Vec3::new(4.0, 2.0, 1.0) + (1.0 - transmittance) * gray;
+    assert!((pixel(&output, 4, 4) - light).length() < 0.01);
+    for (x, y) in [(0, 0), (8, 0), (0, 8), (8, 8)] {
+        assert_near(pixel(&output, x, y), gray);
+    }
+}
+
+#[test]
+fn cpu_blurs_moving_spheres() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/night.ron"))
+            .unwrap();
+    scene.settings.width = 33;
+    scene.settings.height = 9;
+    scene.camera.look_from = Vec3::ZERO;
+    scene.camera.look_at = Vec3::new(0.0, 0.0, -1.0);
+    scene.camera.vfov = 45.0;
+    scene.camera.aperture = 0.0;
+    scene.camera.focus_dist = None;
+
+    // A light crossing the view from left to right, columns 8, 16 and 24 of the middle row
+    // look at where it is at times 0, 0.5 and 1
+    scene.spheres = vec![SphereDescription {
+        center: Vec3::new(-8.0, 0.0, -10.0),
+        radius: 1.0,
+        material: String::from("light"),
+        center1: Some(Vec3::new(8.0, 0.0, -10.0)),
+        velocity: None,
+    }];
+    let middle_row = |scene: &SceneDescription, time0, time1, sample_count| {
+        let mut scene = scene.clone();
+        scene.camera.time0 = time0;
+        scene.camera.time1 = time1;
+        scene.settings.sample_count = sample_count;
+        let (config, scene) = scene.build().unwrap();
+        let output = CpuRaytracer::new(config, scene).raytrace();
+        [8, 16, 24].map(|x| output[(4 * 33 + x) * 3] / 8.0)
+    };
+
+    // Closing the shutter right away freezes the light where it is at that time
+    assert_eq!(middle_row(&scene, 0.0, 0.0, 4), [1.0, 0.0, 0.0]);
+    assert_eq!(middle_row(&scene, 1.0, 1.0, 4), [0.0, 0.0, 1.0]);
+
+    // Open from 0 to 1, it covers the middle for an eighth of the time
+    let [start, middle, end] = middle_row(&scene, 0.0, 1.0, 256);
+    for value in [start, middle, end] {
+        assert!(0.02 < value && value < 0.3, "{:?}", [start, middle, end]);
+    }
+}
+
+#[test]
+fn cpu_renders_instances_like_transformed_meshes() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/night.ron"))
+            .unwrap();
+    scene.settings.width = 32;
+    scene.settings.height = 24;
+    scene.settings.sample_count = 1;
+    scene.camera.look_from = Vec3::new(0.0, 0.0, 8.0);
+    scene.camera.look_at = Vec3::ZERO;
+    scene.camera.aperture = 0.0;
+    scene.spheres.clear();
+
+    // A light quad in the xy plane, only seen through its instances
+    let quad = MeshDescription {
+        positions: vec![
+            Vec3::new(-0.5, -0.5, 0.0),
+            Vec3::new(0.5, -0.5, 0.0),
+            Vec3::new(0.5, 0.5, 0.0),
+            Vec3::new(-0.5, 0.5, 0.0),
+        ],
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        indices: vec![[0, 1, 2], [0, 2, 3]],
+        material: String::from("light"),
+    };
+    let instances = [
+        InstanceDescription {
+            object: String::from("quad"),
+            scale: Vec3::new(3.0, 1.0, 1.0),
+            rotate: Vec3::new(10.0, 20.0, 30.0),
+            axis: None,
+            angle: 0.0,
+            translate: Vec3::new(-1.5, 1.0, 0.0),
+        },
+        InstanceDescription {
+            object: String::from("quad"),
+            scale: Vec3::new(1.0, 2.0, 1.0),
+            rotate: Vec3::ZERO,
+            axis: Some(Vec3::new(1.0, 1.0, 0.0)),
+            angle: -40.0,
+            translate: Vec3::new(2.0, -1.0, 1.0),
+        },
+    ];
+
+    let mut instanced = scene.clone();
+    instanced.objects.insert(
+        String::from("quad"),
+        ObjectDescription {
+            meshes: vec![quad.clone()],
+            ..Default::default()
+        },
+    );
+    instanced.instances = instances.to_vec();
+
+    // The same quads moved into world space
+    let mut flattened = scene;
+    for instance in instances.iter() {
+        let mut mesh = quad.to_mesh();
+        mesh.transform(&instance.transform());
+        flattened.meshes.push(MeshDescription {
+            positions: mesh.positions,
+            indices: mesh.indices,
+            ..quad.clone()
+        });
+    }
+
+    let render = |scene: &SceneDescription| {
+        let (config, scene) = scene.build().unwrap();
+        CpuRaytracer::new(config, scene).raytrace()
+    };
+    let (instanced, flattened) = (render(&instanced), render(&flattened));
+
+    assert!(mean(&flattened) > 10.0, "the quads are out of view");
+    let different = instanced
+        .iter()
+        .zip(flattened.iter())
+        .filter(|(a, b)| (*a - *b).abs() > 1e-3)
+        .count();
+    assert!(different <= 6, "{} channels differ", different);
+}
+
+#[test]
+fn objects_without_instances_render_the_background() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    scene.settings.width = 8;
+    scene.settings.height = 8;
+    scene.settings.sample_count = 1;
+    scene.background = Background::Constant(Vec3::new(0.1, 0.2, 0.3));
+    scene.spheres.clear();
+
+    // All the geometry is in an object that nothing places, the BVH has no world tree
+    scene.objects.insert(
+        String::from("ball"),
+        ObjectDescription {
+            spheres: vec![SphereDescription {
+                center: Vec3::ZERO,
+                radius: 1000.0,
+                material: String::from("ground"),
+                center1: None,
+                velocity: None,
+            }],
+            ..Default::default()
+        },
+    );
+    let (config, scene) = scene.build().unwrap();
+    assert_eq!(config.num_spheres, 1);
+    assert_eq!(scene.build_bvh().stats().node_count, 0);
+
+    let is_background = |output: &[f32]| {
+        output.len() == 8 * 8 * 3
+            && output
+                .chunks_exact(3)
+                .all(|c| (Vec3::new(c[0], c[1], c[2]) - Vec3::new(0.1, 0.2, 0.3)).length() < 1e-5)
+    };
+    let cpu_output = CpuRaytracer::new(config, scene.clone()).raytrace();
+    assert!(is_background(&cpu_output), "{:?}", cpu_output);
+
+    let mut gpu = match Raytracer::new(config, scene) {
+        Ok(gpu) => gpu,
+        Err(why) if why.is_unavailable() => {
+            eprintln!("skipping: {}", why);
+            return;
+        }
+        Err(why) => panic!("{}", why),
+    };
+    let gpu_output = gpu.raytrace().unwrap();
+    assert!(is_background(&gpu_output), "{:?}", gpu_output);
+}
+
+#[test]
+fn cpu_renders_cornell_box() {
+    let mut scene = SceneDescription::cornell_box();
+    scene.settings.width = 24;
+    scene.settings.height = 24;
+    scene.settings.sample_count = 32;
+
+    let (config, built) = scene.build().unwrap();
+    assert_eq!(config.num_rects, 6 + 2 * 6);
+    assert_eq!(config.num_instances, 2);
+
+    let output = CpuRaytracer::new(config, built).raytrace();
+    assert!(output.iter().all(|&c| c.is_finite() && c >= 0.0));
+
+    // Seen from the front, the green wall is on the left and the red one on the right
+    let column = |x: usize| {
+        (4..20).fold(Vec3::ZERO, |sum, y| {
+            let i = (y * 24 + x) * 3;
+            sum + Vec3::new(output[i], output[i + 1], output[i + 2])
+        })
+    };
+    let (left, right) = (column(3), column(20));
+    assert!(left.y > left.x, "left wall {:?}", left);
+    assert!(right.x > right.y, "right wall {:?}", right);
+
+    scene.objects.get_mut("tall_box").unwrap().boxes[0].max.y = -1.0;
+    let error = scene.build().err().unwrap().to_string();
+    assert!(
+        error.contains("objects.tall_box.boxes[0].max: must be greater than min"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn cpu_renders_volumes_and_fog() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    scene.settings.width = 16;
+    scene.settings.height = 8;
+    scene.settings.sample_count = 2;
+    scene.background = Background::Constant(Vec3::ZERO);
+    scene.spheres.clear();
+
+    // The light quad of cpu_renders_triangle_mesh, 13 to 17 away from the camera at x = 13
+    scene.materials.insert(
+        String::from("light"),
+        Material::DiffuseLight {
+            color: Vec3::ONE,
+            intensity: 1.0,
+            texture: None,
+        },
+    );
+    scene.meshes.push(MeshDescription {
+        positions: vec![
+            Vec3::new(0.0, -100.0, -100.0),
+            Vec3::new(0.0, 100.0, -100.0),
+            Vec3::new(0.0, 100.0, 100.0),
+            Vec3::new(0.0, -100.0, 100.0),
+        ],
+        normals: vec![Vec3::new(1.0, 0.0, 0.0); 4],
+        uvs: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+        indices: vec![[0, 1, 2], [0, 2, 3]],
+        material: String::from("light"),
+    });
+
+    // Black smoke, a slab between the camera and the light and a block around the camera
+    scene.materials.insert(
+        String::from("smoke"),
+        Material::Isotropic {
+            albedo: Vec3::ZERO,
+            texture: None,
+        },
+    );
+    let block = |min, max| ObjectDescription {
+        boxes: vec![BoxDescription {
+            min,
+            max,
+            material: String::from("smoke"),
+        }],
+        ..Default::default()
+    };
+    scene.objects.insert(
+        String::from("slab"),
+        block(Vec3::new(5.0, -100.0, -100.0), Vec3::new(6.0, 100.0, 100.0)),
+    );
+    scene.objects.insert(
+        String::from("around"),
+        block(Vec3::new(10.0, -5.0, -5.0), Vec3::new(20.0, 5.0, 5.0)),
+    );
+
+    let render = |scene: &SceneDescription| {
+        let (config, scene) = scene.build().unwrap();
+        CpuRaytracer::new(config, scene).raytrace()
+    };
+    let volume = |object: &str, density| VolumeDescription {
+        object: object.to_string(),
+        density,
+        material: String::from("smoke"),
+    };
+
+    // Rays entering a dense medium or starting inside it are all absorbed
+    for object in ["slab", "around"] {
+        let mut dense = scene.clone();
+        dense.volumes = vec![volume(object, 100.0)];
+        assert!(render(&dense).iter().all(|&c| c == 0.0), "{}", object);
+    }
+
+    // The boundary itself isn't rendered, a thin medium lets the light through
+    let mut thin = scene.clone();
+    thin.volumes = vec![volume("slab", 1e-6)];
+    assert!(render(&thin).iter().all(|&c| (c - 1.0).abs() < 1e-6));
+
+    // Black fog dims the light by how far it is, the lens moves the rays up to half the aperture
+    // closer or further
+    let mut foggy = scene.clone();
+    foggy.fog = Some(Fog {
+        color: Vec3::ZERO,
+        density: 0.1,
+    });
+    let lens_radius = scene.camera.aperture / 2.0;
+    let (near, far) = (
+        (-0.1 * (13.0 - lens_radius)).exp(),
+        (-0.1 * (17.0 + lens_radius)).exp(),
+    );
+    let output = render(&foggy);
+    assert!(
+        output.iter().all(|&c| far <= c && c <= near + 1e-6),
+        "{:?}",
+        output
+    );
+
+    scene.volumes = vec![volume("slab", 1.0)];
+    scene.volumes[0].material = String::from("light");
+    let error = scene.build().err().unwrap().to_string();
+    assert!(
+        error.contains("volumes[0].material: volumes scatter light with an Isotropic material"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn cpu_light_sampling_converges_faster() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    scene.settings.width = 16;
+    scene.settings.height = 16;
+    scene.settings.sample_count = 64;
+    // Direct light only: camera, floor, light
+    scene.settings.max_bounces = 3;
+    scene.background = Background::Constant(Vec3::ZERO);
+    scene.camera.look_from = Vec3::new(0.0, 2.0, 3.0);
+    scene.camera.look_at = Vec3::ZERO;
+    scene.camera.vfov = 40.0;
+    scene.camera.aperture = 0.0;
+
+    // A small sphere light above a diffuse floor
+    let (center, radius, intensity, albedo) = (Vec3::new(0.0, 1.0, 0.0), 0.2, 4.0, 0.5);
+    scene.materials.insert(
+        String::from("floor"),
+        Material::Lambertian {
+            albedo: Vec3::ONE * albedo,
+            texture: None,
+        },
+    );
+    scene.materials.insert(
+        String::from("light"),
+        Material::DiffuseLight {
+            color: Vec3::ONE,
+            intensity,
+            texture: None,
+        },
+    );
+    scene.rects = vec![RectDescription {
+        plane: Plane::Xz,
+        min: [-50.0, -50.0],
+        max: [50.0, 50.0],
+        k: 0.0,
+        material: String::from("floor"),
+        flip: false,
+    }];
+    scene.spheres = vec![SphereDescription {
+        center,
+        radius,
+        material: String::from("light"),
+        center1: None,
+        velocity: None,
+    }];
+
+    // The floor reflects albedo / PI of the irradiance PI * L * (r / d)^2 * cos of the sphere,
+    // averaged over the area of the pixel
+    let (config, _) = scene.build().unwrap();
+    let radiance = |u: f32, v: f32| {
+        let ray = config.camera.ray(u, v);
+        let to_light = center - ray.origin;
+        let along = to_light.dot(&ray.dir) / ray.dir.length_squared();
+        if (to_light - along * ray.dir).length() < radius + 0.05 {
+            return None;
+        }
+
+        let point = ray.at(-ray.origin.y / ray.dir.y);
+        let to_center = center - point;
+        let cosine = to_center.y / to_center.length();
+        Some(albedo * intensity * radius * radius * cosine / to_center.length_squared())
+    };
+    let expected: Vec<Option<f32>> = (0..16 * 16)
+        .map(|i| {
+            let (x, y) = ((i % 16) as f32, (i / 16) as f32);
+            let mut sum = 0.0;
+            for j in 0..64 {
+                let (dx, dy) = ((j % 8) as f32 / 8.0 - 0.4375, (j / 8) as f32 / 8.0 - 0.4375);
+                sum += radiance((x + dx) / 15.0, (y + dy) / 15.0)?;
+            }
+            Some(sum / 64.0)
+        })
+        .collect();
+
+    let render = |scene: &SceneDescription, sampling| {
+        let mut scene = scene.clone();
+        scene.settings.light_sampling = sampling;
+        let (config, scene) = scene.build().unwrap();
+        CpuRaytracer::new(config, scene).raytrace()
+    };
+    let error = |output: &[f32]| {
+        let squared: Vec<f32> = expected
+            .iter()
+            .enumerate()
+            .filter_map(|(i, expected)| Some((output[i * 3] - (*expected)?).powi(2)))
+            .collect();
+        (squared.iter().sum::<f32>() / squared.len() as f32).sqrt()
+    };
+
+    let off = error(&render(&scene, LightSampling::Off));
+    for sampling in [LightSampling::Balance, LightSampling::Power] {
+        let sampled = error(&render(&scene, sampling));
+        assert!(
+            sampled < off / 4.0,
+            "{:?}: {} against {}",
+            sampling,
+            sampled,
+            off
+        );
+        assert!(sampled < 0.01, "{:?}: {}", sampling, sampled);
+    }
+
+    // A rectangle light averages to the same image either way
+    scene.spheres.clear();
+    scene.rects.push(RectDescription {
+        plane: Plane::Xz,
+        min: [-0.3, -0.3],
+        max: [0.3, 0.3],
+        k: 1.0,
+        material: String::from("light"),
+        flip: true,
+    });
+    let brightness = |output: &[f32]| output.iter().sum::<f32>() / output.len() as f32;
+    scene.settings.sample_count = 256;
+    let off = brightness(&render(&scene, LightSampling::Off));
+    scene.settings.sample_count = 16;
+    let power = brightness(&render(&scene, LightSampling::Power));
+    assert!((power - off).abs() < 0.1 * off, "{} against {}", power, off);
+}
+
+#[test]
+fn cpu_renders_are_reproducible_per_seed() {
+    let render = |seed: u32, sample_count: u32| {
+        let (mut config, scene) = small_scene(32, 32, sample_count);
+        config.seed = seed;
+        CpuRaytracer::new(config, scene).raytrace()
+    };
+
+    // Bit-identical, whatever the threads or passes the samples are spread over
+    let first = render(7, 4);
+    assert_eq!(first, render(7, 4));
+
+    let (mut config, scene) = small_scene(32, 32, 4);
+    config.seed = 7;
+    let options = ProgressiveOptions {
+        samples_per_pass: 1,
+        ..Default::default()
+    };
+    let progressive = CpuRaytracer::new(config, scene)
+        .raytrace_progressive(&options, |_| ControlFlow::Continue(()));
+    assert_eq!(first, progressive);
+
+    // The noise of other seeds is independent: uncorrelated around the converged image, pooled
+    // over a few pairs as a handful of bright pixels dominate a single one
+    assert_ne!(first, render(8, 4));
+    let reference = render(0, 256);
+    let noise = |seed: u32| -> Vec<f32> {
+        let output = render(seed, 4);
+        output.iter().zip(&reference).map(|(a, b)| a - b).collect()
+    };
+    let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+    for seed in 1..=8 {
+        let (a, b) = (noise(seed), noise(seed + 1000));
+        ab += dot(&a, &b);
+        aa += dot(&a, &a);
+        bb += dot(&b, &b);
+    }
+    let correlation = ab / (aa * bb).sqrt();
+    assert!(correlation.abs() < 0.1, "correlation {}", correlation);
+}
+
+#[test]
+fn cpu_samplers_antialias_edges() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    scene.settings.width = 15;
+    scene.settings.height = 3;
+    scene.settings.sample_count = 256;
+    scene.background = Background::Constant(Vec3::ZERO);
+    scene.spheres.clear();
+    scene.camera.look_from = Vec3::new(0.0, 0.0, 1.0);
+    scene.camera.look_at = Vec3::ZERO;
+    scene.camera.vfov = 90.0;
+    scene.camera.aperture = 0.0;
+
+    // A light filling the left half of the view, its edge runs through the middle column
+    scene.materials.insert(
+        String::from("light"),
+        Material::DiffuseLight {
+            color: Vec3::ONE,
+            intensity: 1.0,
+            texture: None,
+        },
+    );
+    scene.rects = vec![RectDescription {
+        plane: Plane::Xy,
+        min: [-10.0, -10.0],
+        max: [0.0, 10.0],
+        k: 0.0,
+        material: String::from("light"),
+        flip: false,
+    }];
+
+    for sampler in Sampler::NAMES {
+        scene.settings.sampler = sampler.parse().unwrap();
+        let (config, built) = scene.build().unwrap();
+        let output = CpuRaytracer::new(config, built).raytrace();
+
+        for (i, &c) in output.iter().enumerate() {
+            let x = i / 3 % 15;
+            match x {
+                0..=6 => assert_eq!(c, 1.0, "{}: pixel {}", sampler, i / 3),
+                7 => assert!((c - 0.5).abs() < 0.1, "{}: {}", sampler, c),
+                _ => assert_eq!(c, 0.0, "{}: pixel {}", sampler, i / 3),
+            }
+        }
+    }
+}
+
+#[test]
+fn gpu_matches_cpu_reference() {
+    let (config, scene) = small_scene(32, 32, 64);
+
+    let mut gpu = match Raytracer::new(config, scene.clone()) {
+        Ok(gpu) => gpu,
+        Err(why) if why.is_unavailable() => {
+            eprintln!("skipping: {}", why);
+            return;
+        }
+        Err(why) => panic!("{}", why),
+    };
+
+    let gpu_output = gpu.raytrace().unwrap();
+    let cpu_output = CpuRaytracer::new(config, scene).raytrace();
+
+    assert_eq!(gpu_output.len(), cpu_output.len());
+
+    // Both are noisy, compare the average brightness of each channel
+    for channel in 0..3 {
+        let gpu_channel: Vec<f32> = gpu_output
+            .iter()
+            .skip(channel)
+            .step_by(3)
+            .copied()
+            .collect();
+        let cpu_channel: Vec<f32> = cpu_output
+            .iter()
+            .skip(channel)
+            .step_by(3)
+            .copied()
+            .collect();
+
+        let difference = (mean(&gpu_channel) - mean(&cpu_channel)).abs();
+        assert!(
+            difference < 8.0,
+            "channel {} differs by {} on average",
+            channel,
+            difference
+        );
+    }
+}
+
+#[test]
+fn gpu_rejects_images_too_large_to_index() {
+    let (mut config, scene) = small_scene(4, 4, 1);
+    // 65536 * 65536 wraps to 0 in u32, three floats per pixel don't fit a uint index either way
+    config.width = 65536;
+    config.height = 65536;
+
+    match Raytracer::new(config, scene) {
+        Err(RaytracerError::ImageTooLarge { width, height }) => {
+            assert_eq!((width, height), (65536, 65536))
+        }
+        Err(why) => panic!("{}", why),
+        Ok(_) => panic!("a 65536x65536 image was accepted"),
+    }
+}
+
+#[test]
+fn gpu_matches_cpu_with_several_volumes() {
+    let mut scene =
+        SceneDescription::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/book1.ron"))
+            .unwrap();
+    scene.settings.width = 24;
+    scene.settings.height = 8;
+    scene.settings.sample_count = 16;
+    scene.camera.look_from = Vec3::ZERO;
+    scene.camera.look_at = Vec3::new(0.0, 0.0, -1.0);
+    scene.camera.vfov = 90.0;
+    scene.camera.aperture = 0.0;
+    scene.camera.focus_dist = None;
+    scene.background = Background::Constant(Vec3::ZERO);
+    scene.spheres.clear();
+
+    // A light behind two slabs of black smoke, one on each side, the middle third sees the light
+    scene.materials.insert(
+        String::from("light"),
+        Material::DiffuseLight {
+            color: Vec3::ONE,
+            intensity: 1.0,
+            texture: None,
+        },
+    );
+    scene.materials.insert(
+        String::from("smoke"),
+        Material::Isotropic {
+            albedo: Vec3::ZERO,
+            texture: None,
+        },
+    );
+    scene.rects.push(RectDescription {
+        plane: Plane::Xy,
+        min: [-100.0, -100.0],
+        max: [100.0, 100.0],
+        k: -10.0,
+        material: String::from("light"),
+        flip: false,
+    });
+    for (name, min_x, max_x) in [("left", -100.0, -5.0), ("right", 5.0, 100.0)] {
+        scene.objects.insert(
+            String::from(name),
+            ObjectDescription {
+                boxes: vec![BoxDescription {
+                    min: Vec3::new(min_x, -100.0, -6.0),
+                    max: Vec3::new(max_x, 100.0, -5.0),
+                    material: String::from("smoke"),
+                }],
+                ..Default::default()
+            },
+        );
+        scene.volumes.push(VolumeDescription {
+            object: String::from(name),
+            density: 100.0,
+            material: String::from("smoke"),
+        });
+    }
+    let (config, scene) = scene.build().unwrap();
+    assert_eq!(config.num_volumes, 2);
+
+    let cpu_output = CpuRaytracer::new(config, scene.clone()).raytrace();
+    let column =
+        |output: &[f32], x: usize| (0..8).map(|y| output[(y * 24 + x) * 3]).sum::<f32>() / 8.0;
+    assert_eq!(column(&cpu_output, 0), 0.0);
+    assert_eq!(column(&cpu_output, 12), 1.0);
+    assert_eq!(column(&cpu_output, 23), 0.0);
+
+    let mut gpu = match Raytracer::new(config, scene) {
+        Ok(gpu) => gpu,
+        Err(why) if why.is_unavailable() => {
+            eprintln!("skipping: {}", why);
+            return;
+        }
+        Err(why) => panic!("{}", why),
+    };
+    let gpu_output = gpu.raytrace().unwrap();
+    assert_eq!(gpu_output.len(), cpu_output.len());
+
+    // Only the jitter within the pixels on the edges of the slabs differs
+    for x in 0..24 {
+        let (gpu, cpu) = (column(&gpu_output, x), column(&cpu_output, x));
+        assert!(
+            (gpu - cpu).abs() < 0.1,
+            "column {} is {} on the GPU but {} on the CPU",
+            x,
+            gpu,
+            cpu
+        );
+    }
+}
+
+#[test]
+fn progressive_render_stops_at_target_or_when_asked() {
+    let (config, scene) = small_scene(16, 8, 10);
+    let mut cpu = CpuRaytracer::new(config, scene);
+
+    let options = ProgressiveOptions {
+        samples_per_pass: 4,
+        ..Default::default()
+    };
+
+    let mut passes = Vec::new();
+    let output = cpu.raytrace_progressive(&options, |pass| {
+        passes.push((pass.index, pass.samples, pass.noise.is_some()));
+        assert_eq!(pass.pixels.len(), 16 * 8 * 3);
+        ControlFlow::Continue(())
+    });
+
+    // The last pass only renders what's left to reach the target
+    assert_eq!(passes, [(1, 4, false), (2, 8, false), (3, 10, false)]);
+    assert!(mean(&output) > 0.0, "the image is black");
+
+    // The noise is only estimated when it can stop the render
+    let mut noisy = Vec::new();
+    let threshold = ProgressiveOptions {
+        noise_threshold: Some(0.0),
+        ..options.clone()
+    };
+    cpu.raytrace_progressive(&threshold, |pass| {
+        noisy.push(pass.noise.is_some());
+        ControlFlow::Continue(())
+    });
+    assert_eq!(noisy, [true, true, true]);
+
+    let mut passes = 0;
+    cpu.raytrace_progressive(&options, |_| {
+        passes += 1;
+        ControlFlow::Break(())
+    });
+    assert_eq!(passes, 1);
+}
+
+#[test]
+fn png_is_written_top_down() {
+    let (width, height) = (4, 2);
+
+    // Bottom row white, top row black
+    let mut pixels = vec![1.0; (width * height * 3) as usize];
+    pixels[(width * 3) as usize..].fill(0.0);
+
+    let path = std::env::temp_dir().join("hikari_png_is_written_top_down.png");
+    image::write(
+        &path,
+        ImageFormat::Png,
+        width,
+        height,
+        &pixels,
+        &ToneMapping::default(),
+    )
+    .unwrap();
+
+    let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!((info.width, info.height), (width, height));
+    assert_eq!(buffer[0], 0);
+    assert_eq!(buffer[info.line_size], 255);
+}
+
+#[test]
+fn hdr_formats_keep_linear_values() {
+    let (width, height) = (2, 2);
+
+    // Bottom row brighter than white, top row dim
+    let mut pixels = vec![4.0; (width * height * 3) as usize];
+    pixels[(width * 3) as usize..].fill(0.25);
+
+    let path = std::env::temp_dir().join("hikari_hdr_formats_keep_linear_values.exr");
+    image::write(
+        &path,
+        ImageFormat::Exr,
+        width,
+        height,
+        &pixels,
+        &ToneMapping::default(),
+    )
+    .unwrap();
+
+    let exr = exr::prelude::read_first_rgba_layer_from_file(
+        &path,
+        |resolution, _| vec![vec![0.0; resolution.width()]; resolution.height()],
+        |rows: &mut Vec<Vec<f32>>, position, (r, _, _, _): (f32, f32, f32, f32)| {
+            rows[position.y()][position.x()] = r
+        },
+    )
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let rows = exr.layer_data.channel_data.pixels;
+    assert_eq!(rows, [[0.25, 0.25], [4.0, 4.0]]);
+
+    let path = path.with_extension("hdr");
+    image::write(
+        &path,
+        ImageFormat::Hdr,
+        width,
+        height,
+        &pixels,
+        &ToneMapping::default(),
+    )
+    .unwrap();
+    let hdr = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 2\n";
+    assert!(hdr.starts_with(header));
+
+    // 0.25 = 128 / 256 * 2^-1 and 4.0 = 128 / 256 * 2^3
+    let data = &hdr[header.len()..];
+    assert_eq!(&data[..4], [128, 128, 128, 127]);
+    assert_eq!(&data[8..12], [128, 128, 128, 131]);
+}
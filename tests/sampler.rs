@@ -0,0 +1,133 @@
+use raytracer::sampler::{self, BLUE_NOISE_SIZE};
+use raytracer::{Config, PixelSample, Sampler};
+
+const SAMPLERS: [Sampler; 5] = [
+    Sampler::Random,
+    Sampler::Stratified,
+    Sampler::Halton,
+    Sampler::Sobol,
+    Sampler::BlueNoise,
+];
+
+fn config(sampler: Sampler, sample_count: u32) -> Config {
+    let mut config = Config::default();
+    config.width = 16;
+    config.height = 16;
+    config.sample_count = sample_count;
+    config.sampler_mode = sampler.lower();
+    config
+}
+
+type Inside = fn(f32, f32) -> bool;
+
+#[test]
+fn samplers_converge_faster_than_random() {
+    let blue_noise = sampler::blue_noise();
+
+    // The share of the pixel below a diagonal edge, 1/2, and of a quarter disk, PI/4
+    let integrands: [(Inside, f32); 2] = [
+        (|u, v| u + v < 1.0, 0.5),
+        (|u, v| u * u + v * v < 1.0, std::f32::consts::FRAC_PI_4),
+    ];
+
+    for (inside, expected) in integrands {
+        let error = |sampler: Sampler, dimension: u32| {
+            let config = config(sampler, 64);
+            let mut squared = 0.0;
+            for pixel in 0..16 * 16 {
+                let hits = (0..64)
+                    .filter(|&i| {
+                        let mut sample =
+                            PixelSample::new(&config, &blue_noise, pixel % 16, pixel / 16, i);
+                        let [u, v] = sample.get_2d(dimension);
+                        assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                        inside(u, v)
+                    })
+                    .count();
+                squared += (hits as f32 / 64.0 - expected).powi(2);
+            }
+            (squared / 256.0).sqrt()
+        };
+
+        // The pixel and lens well below the error of random, the directions of a later bounce
+        // still ahead of it
+        for (dimension, factor) in [(0, 0.6), (2, 0.6), (11, 0.9)] {
+            let random = error(Sampler::Random, dimension);
+            for sampler in &SAMPLERS[1..] {
+                let sampled = error(*sampler, dimension);
+                assert!(
+                    sampled < random * factor,
+                    "{:?} in dimension {}: {} against {}",
+                    sampler,
+                    dimension,
+                    sampled,
+                    random
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn stratified_samples_cover_every_stratum() {
+    let config = config(Sampler::Stratified, 36);
+    let (x, y) = (3, 5);
+
+    let mut samples: Vec<PixelSample> = (0..36)
+        .map(|i| PixelSample::new(&config, &[], x, y, i))
+        .collect();
+    let points: Vec<[f32; 2]> = samples.iter_mut().map(|sample| sample.get_2d(0)).collect();
+    let times: Vec<f32> = samples.iter_mut().map(|sample| sample.get_1d(4)).collect();
+
+    // One sample per row and column of the 36 x 36 grid and per cell of the 6 x 6 one
+    let mut rows = [false; 36];
+    let mut columns = [false; 36];
+    let mut cells = [false; 36];
+    for [u, v] in points {
+        columns[(u * 36.0) as usize] = true;
+        rows[(v * 36.0) as usize] = true;
+        cells[(v * 6.0) as usize * 6 + (u * 6.0) as usize] = true;
+    }
+    assert!(rows.iter().chain(&columns).chain(&cells).all(|&c| c));
+
+    let mut strata = [false; 36];
+    for time in times {
+        strata[(time * 36.0) as usize] = true;
+    }
+    assert!(strata.iter().all(|&s| s));
+
+    // Other pixels shuffle their strata differently
+    let other = PixelSample::new(&config, &[], x + 1, y, 0).get_2d(0);
+    assert_ne!(PixelSample::new(&config, &[], x, y, 0).get_2d(0), other);
+}
+
+#[test]
+fn blue_noise_mask_has_no_low_frequencies() {
+    let mask = sampler::blue_noise();
+    let count = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+    assert_eq!(mask, sampler::blue_noise());
+
+    // Every threshold once
+    let mut sorted = mask.clone();
+    sorted.sort_by(f32::total_cmp);
+    for (i, value) in sorted.iter().enumerate() {
+        assert_eq!(*value, (i as f32 + 0.5) / count as f32);
+    }
+
+    // Blocks of 8 x 8 average out much closer to 1/2 than white noise, off by 0.036 typically
+    let blocks = (BLUE_NOISE_SIZE / 8).pow(2);
+    let squared: f32 = (0..blocks)
+        .map(|block| {
+            let (bx, by) = (
+                block % (BLUE_NOISE_SIZE / 8) * 8,
+                block / (BLUE_NOISE_SIZE / 8) * 8,
+            );
+            let sum: f32 = (0..64)
+                .map(|i| mask[(by + i / 8) * BLUE_NOISE_SIZE + bx + i % 8])
+                .sum();
+            (sum / 64.0 - 0.5).powi(2)
+        })
+        .sum();
+    let deviation = (squared / blocks as f32).sqrt();
+    assert!(deviation < 0.018, "deviation of the blocks: {}", deviation);
+}
@@ -0,0 +1,378 @@
+use raytracer::{
+    ColorSpace, CpuRaytracer, Image, Material, Raytracer, Scene, SceneDescription, Texture,
+    TextureError, Vec3, WrapMode,
+};
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::Arc;
+
+/// 2x2 RGB image: black and white on the top row, red and sRGB mid gray on the bottom one
+fn write_png(path: &Path) {
+    let mut encoder = png::Encoder::new(File::create(path).unwrap(), 2, 2);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer
+        .write_image_data(&[0, 0, 0, 255, 255, 255, 255, 0, 0, 188, 188, 188])
+        .unwrap();
+}
+
+fn assert_near(actual: Vec3, expected: Vec3) {
+    assert!(
+        (actual - expected).length() < 1e-3,
+        "expected {:?}, got {:?}",
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn image_textures_are_sampled_and_bound_to_materials() {
+    let directory = std::env::temp_dir().join("hikari_image_textures");
+    fs::create_dir_all(&directory).unwrap();
+    write_png(&directory.join("image.png"));
+
+    let image = Arc::new(Image::load_png(&directory.join("image.png")).unwrap());
+    assert_eq!((image.width(), image.height()), (2, 2));
+    assert_eq!(image.texel(1, 1), [188, 188, 188, 255]);
+
+    let texture = |wrap, color_space| Texture::Image {
+        image: image.clone(),
+        wrap,
+        color_space,
+    };
+    let srgb = texture(WrapMode::Repeat, ColorSpace::Srgb);
+    let linear = texture(WrapMode::Repeat, ColorSpace::Linear);
+
+    // (0, 0) is the bottom left corner, texel centers sample a single texel
+    let red = Vec3::new(1.0, 0.0, 0.0);
+    assert_near(srgb.value([0.25, 0.25], Vec3::ZERO, &[]), red);
+    assert_near(srgb.value([0.75, 0.75], Vec3::ZERO, &[]), Vec3::ONE);
+    assert_near(
+        srgb.value([0.75, 0.25], Vec3::ZERO, &[]),
+        Vec3::ONE * 0.5029,
+    );
+    assert_near(
+        linear.value([0.75, 0.25], Vec3::ZERO, &[]),
+        Vec3::ONE * (188.0 / 255.0),
+    );
+
+    // Filtering happens after decoding, halfway between black and white is half the radiance
+    assert_near(srgb.value([0.5, 0.75], Vec3::ZERO, &[]), Vec3::ONE * 0.5);
+
+    // One texture width to the right: the same texel, the mirrored one, the border one
+    assert_near(srgb.value([1.25, 0.25], Vec3::ZERO, &[]), red);
+    let mirrored = texture(WrapMode::Mirror, ColorSpace::Srgb).value([1.25, 0.25], Vec3::ZERO, &[]);
+    assert_near(mirrored, Vec3::ONE * 0.5029);
+    let clamped = texture(WrapMode::Clamp, ColorSpace::Srgb).value([1.25, 0.25], Vec3::ZERO, &[]);
+    assert_near(clamped, Vec3::ONE * 0.5029);
+    let clamped = texture(WrapMode::Clamp, ColorSpace::Srgb).value([-1.0, 0.25], Vec3::ZERO, &[]);
+    assert_near(clamped, red);
+
+    // Scenes reference textures by name, with paths relative to the scene file
+    fs::write(
+        directory.join("scene.ron"),
+        r#"(
+            camera: (
+                look_from: (x: 0.0, y: 0.0, z: 0.0),
+                look_at: (x: 0.0, y: 0.0, z: -1.0),
+                vfov: 40.0,
+            ),
+            textures: { "image": Image(path: "image.png", wrap: Clamp, color_space: Linear) },
+            materials: { "textured": Lambertian(texture: "image") },
+            spheres: [(center: (x: 0.0, y: 0.0, z: -1.0), radius: 0.5, material: "textured")],
+        )"#,
+    )
+    .unwrap();
+    fs::write(
+        directory.join("missing.ron"),
+        r#"(
+            camera: (
+                look_from: (x: 0.0, y: 0.0, z: 0.0),
+                look_at: (x: 0.0, y: 0.0, z: -1.0),
+                vfov: 40.0,
+            ),
+            materials: { "textured": Lambertian(texture: "image") },
+            spheres: [(center: (x: 0.0, y: 0.0, z: -1.0), radius: 0.5, material: "textured")],
+        )"#,
+    )
+    .unwrap();
+    let scene = SceneDescription::load(&directory.join("scene.ron")).and_then(|d| d.build());
+    let missing = SceneDescription::load(&directory.join("missing.ron")).and_then(|d| d.build());
+    fs::remove_dir_all(&directory).unwrap();
+
+    let (_, scene) = scene.unwrap();
+    assert_eq!(
+        scene.textures(),
+        [texture(WrapMode::Clamp, ColorSpace::Linear)]
+    );
+    match scene.materials() {
+        [Material::Lambertian {
+            albedo,
+            texture: Some(texture),
+        }] => {
+            assert_eq!(*albedo, Vec3::ONE);
+            assert_eq!(texture.index(), 0);
+        }
+        materials => panic!("unexpected materials {:?}", materials),
+    }
+
+    let error = missing.err().unwrap().to_string();
+    assert!(
+        error.contains("there is no texture named 'image'"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn procedural_textures_follow_the_hit_point() {
+    let mut scene = Scene::new();
+    let red = Vec3::new(1.0, 0.0, 0.0);
+    let blue = Vec3::new(0.0, 0.0, 1.0);
+    let even = scene.add_texture(Texture::Solid { color: red }).unwrap();
+    let odd = scene.add_texture(Texture::Solid { color: blue }).unwrap();
+    let checker = Texture::Checker {
+        even,
+        odd,
+        scale: 0.5,
+    };
+    scene.add_texture(checker.clone()).unwrap();
+    assert!(scene.add_texture(Texture::Noise { scale: 0.0 }).is_err());
+
+    // Cells of 0.5 units, the origin starts an even one
+    let at =
+        |texture: &Texture, x, y, z| texture.value([0.0; 2], Vec3::new(x, y, z), scene.textures());
+    assert_eq!(at(&checker, 0.25, 0.25, 0.25), red);
+    assert_eq!(at(&checker, 0.75, 0.25, 0.25), blue);
+    assert_eq!(at(&checker, -0.25, 0.25, 0.25), blue);
+    assert_eq!(at(&checker, -0.25, -0.25, 0.25), red);
+
+    // Noise is zero on the lattice, hence mid gray, and stays within black and white elsewhere
+    let noise = Texture::Noise { scale: 1.0 };
+    assert_eq!(at(&noise, 3.0, -2.0, 7.0), Vec3::ONE * 0.5);
+    let textures = [
+        noise,
+        Texture::Turbulence {
+            scale: 4.0,
+            depth: 7,
+        },
+        Texture::Marble {
+            scale: 4.0,
+            depth: 7,
+        },
+    ];
+    for texture in textures.iter() {
+        let values: Vec<f32> = (0..1000)
+            .map(|i| {
+                let t = i as f32 * 0.0137;
+                at(texture, t, 2.0 * t + 0.3, -t).x
+            })
+            .collect();
+        assert!(
+            values.iter().all(|v| (0.0..=1.0).contains(v)),
+            "{:?}",
+            texture
+        );
+        let (min, max) = values
+            .iter()
+            .fold((1.0f32, 0.0f32), |(min, max), &v| (min.min(v), max.max(v)));
+        assert!(max - min > 0.2, "{:?} barely varies", texture);
+    }
+
+    // Scene files reference the textures of checkers and of any material slot by name
+    let directory = std::env::temp_dir().join("hikari_procedural_textures");
+    fs::create_dir_all(&directory).unwrap();
+    let scene_file = |textures: &str| {
+        format!(
+            r#"(
+                camera: (
+                    look_from: (x: 0.0, y: 0.0, z: 0.0),
+                    look_at: (x: 0.0, y: 0.0, z: -1.0),
+                    vfov: 40.0,
+                ),
+                textures: {{ {} }},
+                materials: {{
+                    "brushed": Metal(fuzz: 1.0, fuzz_texture: "marble"),
+                    "lamp": DiffuseLight(intensity: 4.0, texture: "checker"),
+                }},
+                spheres: [(center: (x: 0.0, y: 0.0, z: -1.0), radius: 0.5, material: "lamp")],
+            )"#,
+            textures
+        )
+    };
+    fs::write(
+        directory.join("valid.ron"),
+        scene_file(
+            r#""checker": Checker(even: "white", odd: "marble", scale: 2.0),
+            "white": Solid(color: (x: 1.0, y: 1.0, z: 1.0)),
+            "marble": Marble(scale: 4.0)"#,
+        ),
+    )
+    .unwrap();
+    fs::write(
+        directory.join("cycle.ron"),
+        scene_file(
+            r#""checker": Checker(even: "white", odd: "nested"),
+            "nested": Checker(even: "checker", odd: "white"),
+            "white": Solid(color: (x: 1.0, y: 1.0, z: 1.0)),
+            "marble": Marble()"#,
+        ),
+    )
+    .unwrap();
+    fs::write(
+        directory.join("deep.ron"),
+        scene_file(
+            r#""checker": Solid(color: (x: 1.0, y: 1.0, z: 1.0)),
+            "marble": Marble(depth: 1000)"#,
+        ),
+    )
+    .unwrap();
+    let valid = SceneDescription::load(&directory.join("valid.ron")).and_then(|d| d.build());
+    let cycle = SceneDescription::load(&directory.join("cycle.ron")).and_then(|d| d.build());
+    let deep = SceneDescription::load(&directory.join("deep.ron")).and_then(|d| d.build());
+    fs::remove_dir_all(&directory).unwrap();
+
+    // Checkers are added after the textures they alternate between
+    let (_, scene) = valid.unwrap();
+    match scene.textures() {
+        [Texture::Solid { .. }, Texture::Marble { depth: 7, .. }, checker] => {
+            assert!(matches!(checker, Texture::Checker { even, odd, .. }
+                if (even.index(), odd.index()) == (0, 1)));
+        }
+        textures => panic!("unexpected textures {:?}", textures),
+    }
+
+    let error = cycle.err().unwrap().to_string();
+    assert!(
+        error.contains("checkers can't contain themselves"),
+        "{}",
+        error
+    );
+
+    // Every octave is a loop iteration per pixel on the GPU
+    let error = deep.err().unwrap().to_string();
+    assert!(
+        error.contains("textures.marble.depth") && error.contains("greater than 16"),
+        "{}",
+        error
+    );
+    let deep = Texture::Turbulence {
+        scale: 1.0,
+        depth: 17,
+    };
+    assert!(matches!(
+        deep.validate(),
+        Err(TextureError::InvalidDepth(17))
+    ));
+    assert!(matches!(
+        Scene::new().add_texture(deep),
+        Err(TextureError::InvalidDepth(17))
+    ));
+}
+
+#[test]
+fn gpu_matches_cpu_with_image_textures() {
+    let directory = std::env::temp_dir().join("hikari_gpu_matches_cpu_with_image_textures");
+    fs::create_dir_all(&directory).unwrap();
+    write_png(&directory.join("image.png"));
+
+    // A second image of another size, so that misreading the textures past the first shows
+    let mut encoder = png::Encoder::new(File::create(directory.join("wide.png")).unwrap(), 3, 1);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer
+        .write_image_data(&[0, 0, 255, 0, 255, 0, 255, 255, 0])
+        .unwrap();
+    writer.finish().unwrap();
+
+    // Two textured lights filling the view side by side, the texture coordinates running past 1
+    fs::write(
+        directory.join("scene.ron"),
+        r#"(
+            camera: (
+                look_from: (x: 0.0, y: 0.0, z: 0.0),
+                look_at: (x: 0.0, y: 0.0, z: -1.0),
+                vfov: 90.0,
+            ),
+            settings: (width: 32, height: 32, sample_count: 16),
+            background: Constant((x: 0.0, y: 0.0, z: 0.0)),
+            textures: {
+                "image": Image(path: "image.png", wrap: Mirror),
+                "wide": Image(path: "wide.png", wrap: Clamp, color_space: Linear),
+            },
+            materials: {
+                "left": DiffuseLight(texture: "image"),
+                "right": DiffuseLight(texture: "wide"),
+            },
+            spheres: [],
+            meshes: [
+                (
+                    positions: [
+                        (x: -10.0, y: -10.0, z: -10.0),
+                        (x: 0.0, y: -10.0, z: -10.0),
+                        (x: 0.0, y: 10.0, z: -10.0),
+                        (x: -10.0, y: 10.0, z: -10.0),
+                    ],
+                    uvs: [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)],
+                    indices: [(0, 1, 2), (0, 2, 3)],
+                    material: "left",
+                ),
+                (
+                    positions: [
+                        (x: 0.0, y: -10.0, z: -10.0),
+                        (x: 10.0, y: -10.0, z: -10.0),
+                        (x: 10.0, y: 10.0, z: -10.0),
+                        (x: 0.0, y: 10.0, z: -10.0),
+                    ],
+                    uvs: [(-0.5, 0.0), (1.5, 0.0), (1.5, 1.0), (-0.5, 1.0)],
+                    indices: [(0, 1, 2), (0, 2, 3)],
+                    material: "right",
+                ),
+            ],
+        )"#,
+    )
+    .unwrap();
+    let scene = SceneDescription::load(&directory.join("scene.ron")).and_then(|d| d.build());
+    fs::remove_dir_all(&directory).unwrap();
+    let (config, scene) = scene.unwrap();
+
+    let mut gpu = match Raytracer::new(config, scene.clone()) {
+        Ok(gpu) => gpu,
+        Err(why) if why.is_unavailable() => {
+            eprintln!("skipping: {}", why);
+            return;
+        }
+        Err(why) => panic!("{}", why),
+    };
+    let gpu_output = gpu.raytrace().unwrap();
+    let cpu_output = CpuRaytracer::new(config, scene).raytrace();
+    assert_eq!(gpu_output.len(), cpu_output.len());
+
+    // Lights don't bounce anything, only the jitter within the pixels differs: compare the
+    // average of 4x4 pixel blocks
+    let block = |output: &[f32], bx: usize, by: usize| {
+        let mut sum = Vec3::ZERO;
+        for y in by * 4..by * 4 + 4 {
+            for x in bx * 4..bx * 4 + 4 {
+                let i = (y * 32 + x) * 3;
+                sum += Vec3::new(output[i], output[i + 1], output[i + 2]);
+            }
+        }
+        sum / 16.0
+    };
+    for by in 0..8 {
+        for bx in 0..8 {
+            let (gpu, cpu) = (block(&gpu_output, bx, by), block(&cpu_output, bx, by));
+            assert!(
+                (gpu - cpu).length() < 0.05,
+                "block ({}, {}) is {:?} on the GPU but {:?} on the CPU",
+                bx,
+                by,
+                gpu,
+                cpu
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use raytracer::{ToneMapOperator, ToneMapping, TransferFunction};
+
+#[test]
+fn operators_map_radiance_to_display_range() {
+    let operators = [
+        ToneMapOperator::Clamp,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::ExtendedReinhard,
+        ToneMapOperator::Aces,
+        ToneMapOperator::Agx,
+    ];
+
+    for operator in operators {
+        let tone_mapping = ToneMapping {
+            operator,
+            ..Default::default()
+        };
+
+        let mut previous = 0.0;
+        for radiance in [0.0, 0.01, 0.1, 0.5, 1.0, 4.0, 100.0] {
+            let mapped = tone_mapping.map([radiance; 3]);
+            assert!(
+                mapped.iter().all(|c| (0.0..=1.0).contains(c)),
+                "{:?} maps {} to {:?}",
+                operator,
+                radiance,
+                mapped
+            );
+            assert!(mapped[0] >= previous, "{:?} isn't monotonic", operator);
+            previous = mapped[0];
+        }
+    }
+
+    let extended = ToneMapping {
+        operator: ToneMapOperator::ExtendedReinhard,
+        white_point: 4.0,
+        ..Default::default()
+    };
+    assert!((extended.map([4.0; 3])[0] - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn quantization_follows_transfer_function_and_dither() {
+    let srgb = ToneMapping::default();
+    assert_eq!(srgb.quantize(&[0.0, 0.5, 1.0]), [0, 188, 255]);
+
+    let gamma = ToneMapping {
+        transfer: TransferFunction::Gamma(2.0),
+        ..Default::default()
+    };
+    assert_eq!(gamma.quantize(&[0.25, 0.25, 0.25]), [128, 128, 128]);
+
+    // Halving the exposure by one stop
+    let darker = ToneMapping {
+        exposure: -1.0,
+        transfer: TransferFunction::Linear,
+        ..Default::default()
+    };
+    assert_eq!(darker.quantize(&[1.0, 1.0, 1.0]), [128, 128, 128]);
+
+    // A flat area between two levels averages out to its value once dithered
+    let dithered = ToneMapping {
+        transfer: TransferFunction::Linear,
+        dither: true,
+        ..Default::default()
+    };
+    let level = 100.25 / 255.0;
+    let quantized = dithered.quantize(&vec![level; 3 * 4096]);
+    let mean = quantized.iter().map(|&c| c as f32).sum::<f32>() / quantized.len() as f32;
+    assert!((mean - 100.25).abs() < 0.05, "dithered mean is {}", mean);
+    assert!(quantized.iter().all(|&c| (99..=101).contains(&c)));
+}